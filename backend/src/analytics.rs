@@ -0,0 +1,243 @@
+// Visitor analytics for the admin dashboard, over both recent visits and the
+// rollups of visits past the retention period
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use chrono::NaiveDate;
+use diesel::{
+    prelude::*,
+    sql_types::{Bool, Date},
+};
+use model::{
+    analytics::{
+        CountryVisits, DeviceVisits, Interval, PeriodVisits, ReferrerVisits, StateVisits, Visits,
+        VisitsReport,
+    },
+    catalogue::Format,
+    user::{DailyUniqueVisitors, DailyVisits, Device},
+};
+use serde::Serialize;
+
+use crate::visits::UNIQUE_VISITORS;
+
+/// How many referrers reports list
+pub const TOP_REFERRERS: usize = 10;
+
+/// Visits between `from` and `to` inclusive, grouped by day like rollups are.
+/// Bots' visits are left out unless `bots` is set
+pub fn daily_visits(
+    conn: &mut SqliteConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+    bots: bool,
+) -> QueryResult<Vec<DailyVisits>> {
+    diesel::sql_query(
+        "select date(time) as day, country, state, device, \
+         coalesce(referrer, '') as referrer, count(*) as visits \
+         from users where date(time) between ? and ? and (? or not is_bot) \
+         group by date(time), country, state, device, coalesce(referrer, '') \
+         union all \
+         select day, country, state, device, referrer, visits \
+         from daily_visits where day between ? and ?",
+    )
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .bind::<Bool, _>(bots)
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load(conn)
+}
+
+/// Distinct visitors between `from` and `to` inclusive, per day overall and
+/// per breakdown. Bots are left out unless `bots` is set
+pub fn daily_unique_visitors(
+    conn: &mut SqliteConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+    bots: bool,
+) -> QueryResult<Vec<DailyUniqueVisitors>> {
+    diesel::sql_query(format!(
+        "with visits as (\
+         select date(time) as day, visitor, device, country, state, \
+         coalesce(referrer, '') as referrer \
+         from users where date(time) between ? and ? and (? or not is_bot)) \
+         {UNIQUE_VISITORS} \
+         union all \
+         select day, breakdown, country, name, unique_visitors \
+         from daily_unique_visitors where day between ? and ?"
+    ))
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .bind::<Bool, _>(bots)
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load(conn)
+}
+
+/// Most visits first, ties keep their keys' order
+fn ranked<K>(counts: BTreeMap<K, Visits>) -> Vec<(K, Visits)> {
+    let mut ranked = counts.into_iter().collect::<Vec<(K, Visits)>>();
+    ranked.sort_by_key(|(_, visits)| Reverse(visits.visits));
+    ranked
+}
+
+/// Visitors can't be told apart from one day to the next, so unique visitors
+/// over more than a day are the sum of each day's
+pub fn report(
+    groups: &[DailyVisits],
+    uniques: &[DailyUniqueVisitors],
+    from: NaiveDate,
+    to: NaiveDate,
+    interval: Interval,
+) -> VisitsReport {
+    let mut periods = interval
+        .periods(from, to)
+        .into_iter()
+        .map(|start| (start, Visits::default()))
+        .collect::<BTreeMap<NaiveDate, Visits>>();
+
+    let mut total = Visits::default();
+    let mut devices = BTreeMap::<Device, Visits>::new();
+    let mut countries = BTreeMap::<String, Visits>::new();
+    let mut states = BTreeMap::<(String, String), Visits>::new();
+    let mut referrers = BTreeMap::<String, Visits>::new();
+    for group in groups {
+        let visits = Visits {
+            visits: group.visits.max(0) as u32,
+            unique_visitors: 0,
+        };
+
+        total += visits;
+        *periods.entry(interval.period_start(group.day)).or_default() += visits;
+        *devices
+            .entry(group.device.parse().unwrap_or_default())
+            .or_default() += visits;
+        *countries.entry(group.country.clone()).or_default() += visits;
+        *states
+            .entry((group.country.clone(), group.state.clone()))
+            .or_default() += visits;
+        if !group.referrer.is_empty() {
+            *referrers.entry(group.referrer.clone()).or_default() += visits;
+        }
+    }
+    for unique in uniques {
+        let visits = Visits {
+            visits: 0,
+            unique_visitors: unique.unique_visitors.max(0) as u32,
+        };
+
+        match unique.breakdown.as_str() {
+            "total" => {
+                total += visits;
+                *periods
+                    .entry(interval.period_start(unique.day))
+                    .or_default() += visits;
+            }
+            "device" => {
+                *devices
+                    .entry(unique.name.parse().unwrap_or_default())
+                    .or_default() += visits
+            }
+            "country" => *countries.entry(unique.name.clone()).or_default() += visits,
+            "state" => {
+                *states
+                    .entry((unique.country.clone(), unique.name.clone()))
+                    .or_default() += visits
+            }
+            "referrer" => *referrers.entry(unique.name.clone()).or_default() += visits,
+            _ => (),
+        }
+    }
+
+    VisitsReport {
+        from,
+        to,
+        interval,
+        total,
+        periods: periods
+            .into_iter()
+            .map(|(start, visits)| PeriodVisits { start, visits })
+            .collect(),
+        devices: ranked(devices)
+            .into_iter()
+            .map(|(device, visits)| DeviceVisits { device, visits })
+            .collect(),
+        countries: ranked(countries)
+            .into_iter()
+            .map(|(country, visits)| CountryVisits { country, visits })
+            .collect(),
+        states: ranked(states)
+            .into_iter()
+            .map(|((country, state), visits)| StateVisits {
+                country,
+                state,
+                visits,
+            })
+            .collect(),
+        referrers: ranked(referrers)
+            .into_iter()
+            .take(TOP_REFERRERS)
+            .map(|(referrer, visits)| ReferrerVisits { referrer, visits })
+            .collect(),
+    }
+}
+
+/// One row per count, `breakdown` says what `name` is: "total", the interval
+/// for periods, "device", "country", "state" or "referrer"
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    breakdown: &'a str,
+    name: String,
+    /// Only filled in for states
+    country: Option<&'a str>,
+    visits: u32,
+    unique_visitors: u32,
+}
+
+impl<'a> CsvRow<'a> {
+    fn new(breakdown: &'a str, name: String, visits: Visits) -> Self {
+        Self {
+            breakdown,
+            name,
+            country: None,
+            visits: visits.visits,
+            unique_visitors: visits.unique_visitors,
+        }
+    }
+}
+
+pub fn serialize(report: &VisitsReport, format: Format) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Json => serde_json::to_vec_pretty(report).map_err(|e| format!("{e}")),
+        Format::Csv => {
+            let interval = report.interval.to_string();
+            let rows =
+                std::iter::once(CsvRow::new("total", String::new(), report.total))
+                    .chain(report.periods.iter().map(|period| {
+                        CsvRow::new(&interval, period.start.to_string(), period.visits)
+                    }))
+                    .chain(report.devices.iter().map(|device| {
+                        CsvRow::new("device", device.device.to_string(), device.visits)
+                    }))
+                    .chain(report.countries.iter().map(|country| {
+                        CsvRow::new("country", country.country.clone(), country.visits)
+                    }))
+                    .chain(report.states.iter().map(|state| CsvRow {
+                        country: Some(&state.country),
+                        ..CsvRow::new("state", state.state.clone(), state.visits)
+                    }))
+                    .chain(report.referrers.iter().map(|referrer| {
+                        CsvRow::new("referrer", referrer.referrer.clone(), referrer.visits)
+                    }));
+
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in rows {
+                writer
+                    .serialize(row)
+                    .map_err(|e| format!("Cannot write CSV: {e}"))?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| format!("Cannot write CSV: {e}"))
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use actix_web::{error, get, web, HttpResponse, Result};
+use chrono::{Days, NaiveDate, Utc};
+use model::{analytics::AnalyticsQuery, catalogue::Format, funnel::FunnelQuery, sales::SalesQuery};
+
+use crate::{request_id, DbPool};
+
+/// How many days reports cover when no range is given
+const DEFAULT_DAYS: u64 = 30;
+
+/// The inclusive range a report covers, ending today and lasting
+/// `DEFAULT_DAYS` unless given
+fn date_range(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<(NaiveDate, NaiveDate)> {
+    let to = to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = from.unwrap_or_else(|| to - Days::new(DEFAULT_DAYS - 1));
+    if from > to {
+        return Err(error::ErrorBadRequest(format!(
+            "Range starts ({from}) after it ends ({to})"
+        )));
+    }
+
+    Ok((from, to))
+}
+
+/// CSV reports are downloaded as `{name}-{from}-{to}.csv`
+fn report_response(
+    body: Vec<u8>,
+    format: Format,
+    name: &str,
+    (from, to): (NaiveDate, NaiveDate),
+) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    response.content_type(format.content_type());
+    if format == Format::Csv {
+        response.insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{name}-{from}-{to}.csv\""),
+        ));
+    }
+    response.body(body)
+}
+
+/// Visits per day, week or month, unique visitors and their devices, locations and
+/// referrers over a date range, for the admin dashboard or as a CSV export
+#[get("/analytics/visits")]
+pub async fn get_visits(
+    query: web::Query<AnalyticsQuery>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let AnalyticsQuery {
+        from,
+        to,
+        interval,
+        format,
+        bots,
+    } = query.into_inner();
+    let (from, to) = date_range(from, to)?;
+
+    let body = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        let groups = crate::analytics::daily_visits(&mut conn, from, to, bots)
+            .map_err(|e| format!("Cannot fetch visits: {e}"))?;
+        let uniques = crate::analytics::daily_unique_visitors(&mut conn, from, to, bots)
+            .map_err(|e| format!("Cannot fetch visitors: {e}"))?;
+        crate::analytics::serialize(
+            &crate::analytics::report(&groups, &uniques, from, to, interval),
+            format,
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(report_response(body, format, "visits", (from, to)))
+}
+
+/// Sessions that viewed the catalogue, viewed a product, started checking out
+/// and paid over a date range, overall and per product
+#[get("/analytics/funnel")]
+pub async fn get_funnel(
+    query: web::Query<FunnelQuery>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let FunnelQuery { from, to, format } = query.into_inner();
+    let (from, to) = date_range(from, to)?;
+
+    let body = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        let report = crate::funnel::report(&mut conn, from, to)
+            .map_err(|e| format!("Cannot fetch funnel: {e}"))?;
+        crate::funnel::serialize(&report, format)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(report_response(body, format, "funnel", (from, to)))
+}
+
+/// Revenue per day, week or month, units sold per item and kind, top customers,
+/// refunds and the shipping and tax collected for orders placed over a date range
+#[get("/analytics/sales")]
+pub async fn get_sales(
+    query: web::Query<SalesQuery>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let SalesQuery {
+        from,
+        to,
+        interval,
+        format,
+    } = query.into_inner();
+    let (from, to) = date_range(from, to)?;
+
+    let body = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        let report = crate::sales::report(&mut conn, from, to, interval)
+            .map_err(|e| format!("Cannot fetch sales: {e}"))?;
+        crate::sales::serialize(&report, format)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(report_response(body, format, "sales", (from, to)))
+}
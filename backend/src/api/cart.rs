@@ -0,0 +1,95 @@
+use actix_web::{post, web, HttpResponse, Result};
+use diesel::{define_sql_function, prelude::*, sql_types::Text};
+use std::{collections::HashMap, sync::Arc};
+
+use model::{
+    quote::{Quote, Warning},
+    schema::{carts, orders, stock},
+    CartMap, ItemId, Quantity,
+};
+
+use super::stock::get_matching_ids;
+use crate::{request_id, DbPool};
+
+define_sql_function!(fn lower(x: Text) -> Text);
+
+/// Prices a cart against current stock, this is what checkout charges
+pub async fn get_quote(cart: &CartMap, pool: Arc<DbPool>) -> Result<Quote> {
+    let ids = cart.keys().copied().collect::<Vec<ItemId>>();
+    let items = get_matching_ids(ids, pool).await?;
+
+    Ok(Quote::new(&items, cart, chrono::Utc::now().naive_utc()))
+}
+
+/// Checks the items in `cart` that are limited per customer against what was
+/// already bought with `email`, matched case insensitively
+pub async fn customer_limit_warnings(
+    cart: &CartMap,
+    email: Option<String>,
+    pool: Arc<DbPool>,
+) -> Result<Vec<Warning>> {
+    let ids = cart.keys().map(|id| *id as i32).collect::<Vec<i32>>();
+    let has_email = email.is_some();
+
+    let (limits, purchased) = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        let limits = stock::table
+            .filter(stock::id.eq_any(&ids))
+            .filter(stock::max_per_customer.is_not_null())
+            .order(stock::id)
+            .select((stock::id, stock::max_per_customer.assume_not_null()))
+            .get_results::<(i32, i32)>(&mut conn)
+            .map_err(|e| format!("Cannot fetch customer limits: {e}"))?;
+
+        let purchased = match email {
+            Some(email) if !limits.is_empty() => carts::table
+                .inner_join(orders::table)
+                .filter(lower(orders::email).eq(email.trim().to_lowercase()))
+                .filter(carts::item_id.eq_any(limits.iter().map(|(id, _)| *id)))
+                .group_by(carts::item_id)
+                .select((carts::item_id, diesel::dsl::sum(carts::quantity)))
+                .get_results::<(i32, Option<i64>)>(&mut conn)
+                .map_err(|e| format!("Cannot fetch earlier orders: {e}"))?,
+            _ => Vec::new(),
+        };
+
+        Ok::<_, String>((limits, purchased))
+    })
+    .await?
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let purchased = purchased
+        .into_iter()
+        .map(|(id, quantity)| (id as ItemId, quantity.unwrap_or_default() as Quantity))
+        .collect::<HashMap<ItemId, Quantity>>();
+
+    Ok(limits
+        .into_iter()
+        .filter_map(|(id, limit)| {
+            let item = id as ItemId;
+            if !has_email {
+                return Some(Warning::EmailRequired { item });
+            }
+
+            let requested = cart[&item];
+            let purchased = purchased.get(&item).copied().unwrap_or_default();
+            let limit = limit as Quantity;
+            (purchased.saturating_add(requested) > limit).then_some(Warning::CustomerLimit {
+                item,
+                requested,
+                purchased,
+                limit,
+            })
+        })
+        .collect())
+}
+
+#[post("/cart/quote")]
+pub async fn quote_cart(cart: web::Json<CartMap>, pool: web::Data<DbPool>) -> Result<HttpResponse> {
+    let quote = get_quote(&cart, pool.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(quote))
+}
@@ -0,0 +1,84 @@
+use actix_web::{error, get, put, web, HttpResponse, Result};
+use model::catalogue;
+
+use super::{inventory::alert_low_stock, notification::notify_back_in_stock};
+use crate::{request_id, DbPool, Mailer};
+
+/// Exports every product and variant, JSON exports can be imported as-is
+#[get("/catalogue")]
+pub async fn export_catalogue(
+    query: web::Query<catalogue::ExportQuery>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let format = query.into_inner().format;
+
+    let body = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        crate::catalogue::serialize(&crate::catalogue::export(&mut conn)?, format)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"catalogue.{format}\""),
+        ))
+        .body(body))
+}
+
+/// Upserts the catalogue from a CSV or JSON body, responding with what changed.
+/// Nothing is written if `dry_run` is set or any row is invalid
+#[put("/catalogue")]
+pub async fn import_catalogue(
+    query: web::Query<catalogue::ImportQuery>,
+    body: web::Bytes,
+    pool: web::Data<DbPool>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    let catalogue::ImportQuery { format, dry_run } = query.into_inner();
+
+    let db_pool = pool.clone();
+    let report = request_id::block(move || {
+        let rows = match crate::catalogue::parse(&body, format) {
+            Ok(rows) => rows,
+            Err(errors) => {
+                return Ok(catalogue::ImportReport {
+                    errors,
+                    ..Default::default()
+                })
+            }
+        };
+
+        let mut conn = db_pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        crate::catalogue::import(&mut conn, &rows, dry_run, None)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    if !report.low_stock.is_empty() {
+        request_id::spawn(alert_low_stock(
+            "A catalogue import".to_string(),
+            report.low_stock.clone(),
+            mailer.clone().into_inner(),
+        ));
+    }
+    if !report.restocked.is_empty() {
+        request_id::spawn(notify_back_in_stock(
+            report.restocked.iter().map(|id| *id as i32).collect(),
+            pool.into_inner(),
+            mailer.into_inner(),
+        ));
+    }
+
+    if report.errors.is_empty() {
+        Ok(HttpResponse::Ok().json(report))
+    } else {
+        Ok(HttpResponse::BadRequest().json(report))
+    }
+}
@@ -0,0 +1,126 @@
+use actix_web::{delete, error, get, put, web, HttpResponse, Result};
+use diesel::prelude::*;
+use model::{
+    category,
+    schema::{categories, products},
+};
+
+use crate::{request_id, DbPool};
+
+/// Looks up the ID of the category named `name`, unknown categories are a bad request
+pub async fn category_id(name: Option<String>, pool: &web::Data<DbPool>) -> Result<Option<i32>> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+
+    let pool = pool.clone();
+    request_id::block(move || -> std::result::Result<Option<i32>, String> {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        categories::table
+            .select(categories::id)
+            .filter(categories::name.eq(&name))
+            .first::<i32>(&mut conn)
+            .optional()
+            .map_err(|e| format!("Cannot fetch category {name}: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .map(Some)
+    .ok_or_else(|| error::ErrorBadRequest("Unknown category"))
+}
+
+#[get("/categories")]
+pub async fn get_categories(pool: web::Data<DbPool>) -> Result<web::Json<Vec<category::Category>>> {
+    let categories = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        categories::table
+            .select(category::TableCategory::as_select())
+            .order(categories::name)
+            .get_results::<category::TableCategory>(&mut conn)
+            .map_err(|e| format!("Cannot fetch categories: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .into_iter()
+    .map(category::Category::from)
+    .collect();
+
+    Ok(web::Json(categories))
+}
+
+#[put("/categories")]
+pub async fn put_category(
+    pool: web::Data<DbPool>,
+    fields: web::Json<category::NewCategory>,
+) -> Result<HttpResponse> {
+    let fields = fields.into_inner();
+
+    let id = request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        diesel::insert_into(categories::table)
+            .values(&fields)
+            .returning(categories::id)
+            .get_result::<i32>(&mut conn)
+            .map_err(|_| "Cannot insert category, is the name already taken?")
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(id))
+}
+
+#[put("/categories/{category_id}")]
+pub async fn update_category(
+    category_id: web::Path<i32>,
+    fields: web::Json<category::NewCategory>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let category_id = category_id.into_inner();
+    let fields = fields.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        match diesel::update(categories::table.filter(categories::id.eq(category_id)))
+            .set(&fields)
+            .execute(&mut conn)
+        {
+            Ok(0) => Err(format!("Category {category_id} does not exist")),
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("Cannot update category {category_id}")),
+        }
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Deletes a category, its products are left uncategorized
+#[delete("/categories/{category_id}")]
+pub async fn delete_category(
+    category_id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let category_id = category_id.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        conn.transaction(|conn| {
+            diesel::update(products::table.filter(products::category_id.eq(category_id)))
+                .set(products::category_id.eq(None::<i32>))
+                .execute(conn)?;
+            diesel::delete(categories::table.filter(categories::id.eq(category_id))).execute(conn)
+        })
+        .map_err(|_| "Cannot delete category")
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use actix_web::{delete, error, get, put, web, HttpResponse, Result};
+use diesel::prelude::*;
+use model::{
+    collection,
+    product::ProductId,
+    schema::{collection_products, collections},
+};
+
+use crate::{request_id, DbPool};
+
+/// Replaces the products of a collection, keeping the order they're given in
+fn set_products(
+    conn: &mut SqliteConnection,
+    collection_id: i32,
+    product_ids: &[ProductId],
+) -> QueryResult<()> {
+    diesel::delete(
+        collection_products::table.filter(collection_products::collection_id.eq(collection_id)),
+    )
+    .execute(conn)?;
+
+    let rows = product_ids
+        .iter()
+        .enumerate()
+        .map(|(position, product_id)| collection::NewCollectionProduct {
+            collection_id,
+            product_id: *product_id as i32,
+            position: position as i32,
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(collection_products::table)
+        .values(rows)
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Loads collections along with their products, all collections if `id` is `None`
+fn load_collections(
+    conn: &mut SqliteConnection,
+    id: Option<i32>,
+) -> QueryResult<Vec<collection::Collection>> {
+    let mut query = collections::table
+        .select(collection::TableCollection::as_select())
+        .order(collections::name)
+        .into_boxed();
+    if let Some(id) = id {
+        query = query.filter(collections::id.eq(id));
+    }
+    let table_collections = query.get_results::<collection::TableCollection>(conn)?;
+
+    let ids = table_collections.iter().map(|c| c.id).collect::<Vec<i32>>();
+    let mut members = HashMap::<i32, Vec<ProductId>>::new();
+    for (collection_id, product_id) in collection_products::table
+        .select((
+            collection_products::collection_id,
+            collection_products::product_id,
+        ))
+        .filter(collection_products::collection_id.eq_any(&ids))
+        .order(collection_products::position)
+        .get_results::<(i32, i32)>(conn)?
+    {
+        members
+            .entry(collection_id)
+            .or_default()
+            .push(product_id as ProductId);
+    }
+
+    Ok(table_collections
+        .into_iter()
+        .map(|table_collection| {
+            let products = members.remove(&table_collection.id).unwrap_or_default();
+            collection::Collection {
+                products,
+                ..collection::Collection::from(table_collection)
+            }
+        })
+        .collect())
+}
+
+#[get("/collections")]
+pub async fn get_collections(
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<Vec<collection::Collection>>> {
+    let collections = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        load_collections(&mut conn, None).map_err(|e| format!("Cannot fetch collections: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(web::Json(collections))
+}
+
+#[get("/collections/{collection_id}")]
+pub async fn get_collection(
+    collection_id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<collection::Collection>> {
+    let collection_id = collection_id.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        load_collections(&mut conn, Some(collection_id))
+            .map_err(|e| format!("Cannot fetch collection {collection_id}: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .pop()
+    .map(web::Json)
+    .ok_or_else(|| error::ErrorNotFound(format!("No collection with ID {collection_id}")))
+}
+
+#[put("/collections")]
+pub async fn put_collection(
+    pool: web::Data<DbPool>,
+    fields: web::Json<collection::CollectionFields>,
+) -> Result<HttpResponse> {
+    let fields = fields.into_inner();
+
+    let id = request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        conn.transaction(|conn| {
+            let id = diesel::insert_into(collections::table)
+                .values(collection::NewCollection::from(&fields))
+                .returning(collections::id)
+                .get_result::<i32>(conn)?;
+            set_products(conn, id, &fields.products)?;
+            Ok::<_, diesel::result::Error>(id)
+        })
+        .map_err(|_| "Cannot insert collection, is the name taken and do its products exist?")
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(id))
+}
+
+/// Replaces a collection's name, description and products
+#[put("/collections/{collection_id}")]
+pub async fn update_collection(
+    collection_id: web::Path<i32>,
+    fields: web::Json<collection::CollectionFields>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let collection_id = collection_id.into_inner();
+    let fields = fields.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        conn.transaction(|conn| {
+            let updated =
+                diesel::update(collections::table.filter(collections::id.eq(collection_id)))
+                    .set(collection::NewCollection::from(&fields))
+                    .execute(conn)?;
+            if updated == 0 {
+                return Err(diesel::result::Error::NotFound);
+            }
+            set_products(conn, collection_id, &fields.products)
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                format!("Collection {collection_id} does not exist")
+            }
+            _ => format!("Cannot update collection {collection_id}"),
+        })
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/collections/{collection_id}")]
+pub async fn delete_collection(
+    collection_id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let collection_id = collection_id.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        conn.transaction(|conn| {
+            set_products(conn, collection_id, &[])?;
+            diesel::delete(collections::table.filter(collections::id.eq(collection_id)))
+                .execute(conn)
+        })
+        .map_err(|_| "Cannot delete collection")
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,272 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use actix_multipart::Multipart;
+use actix_web::{delete, error, post, put, web, HttpResponse, Result};
+use diesel::prelude::*;
+use futures_util::TryStreamExt;
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    DynamicImage,
+};
+use model::{
+    image::{self as product_image, Format, Size},
+    schema::{images, products},
+};
+
+use crate::{env::Env, request_id, DbPool};
+
+/// Largest upload accepted per image
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+
+/// Decodes an upload, then writes it and every `Size`/`Format` combination to a
+/// new directory under `dir`. Returns the directory name and the original's file name
+pub fn save_image(bytes: &[u8], dir: &Path) -> Result<(String, String), String> {
+    let format = image::guess_format(bytes).map_err(|e| format!("Not an image: {e}"))?;
+    let decoded = image::load_from_memory_with_format(bytes, format).map_err(|e| format!("{e}"))?;
+
+    let key = uuid::Uuid::new_v4().to_string();
+    let image_dir = dir.join(&key);
+    std::fs::create_dir_all(&image_dir)
+        .map_err(|e| format!("Cannot create image directory: {e}"))?;
+
+    let extension = format.extensions_str().first().copied().unwrap_or("bin");
+    let original = format!("original.{extension}");
+
+    let written = std::fs::write(image_dir.join(&original), bytes)
+        .map_err(|e| format!("Cannot write image: {e}"))
+        .and_then(|_| {
+            Size::ALL
+                .into_iter()
+                .try_for_each(|size| write_resized(&decoded, size, &image_dir))
+        });
+
+    match written {
+        Ok(_) => Ok((key, original)),
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&image_dir);
+            Err(e)
+        }
+    }
+}
+
+fn write_resized(image: &DynamicImage, size: Size, dir: &Path) -> Result<(), String> {
+    let max = size.max_dimension();
+    let resized = if image.width() > max || image.height() > max {
+        image.resize(max, max, image::imageops::FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+
+    for format in Format::ALL {
+        let mut buf = Cursor::new(Vec::new());
+        match format {
+            // JPEG has no alpha channel, transparency is dropped there
+            Format::WebP => resized
+                .to_rgba8()
+                .write_with_encoder(WebPEncoder::new_lossless(&mut buf)),
+            Format::Jpeg => resized
+                .to_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut buf, JPEG_QUALITY)),
+        }
+        .map_err(|e| format!("Cannot encode {}: {e}", format.extension()))?;
+
+        std::fs::write(
+            dir.join(product_image::file_name(size, format)),
+            buf.into_inner(),
+        )
+        .map_err(|e| format!("Cannot write image: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Removes the files belonging to images with the given keys, missing files are ignored
+pub fn remove_image_files(keys: &[String], dir: &Path) {
+    for key in keys {
+        let _ = std::fs::remove_dir_all(dir.join(key));
+    }
+}
+
+/// Accepts one or more images as multipart fields and appends them to the
+/// product's images
+#[post("/stock/{product_id}/images")]
+pub async fn upload_images(
+    product_id: web::Path<i32>,
+    mut payload: Multipart,
+    pool: web::Data<DbPool>,
+    env: web::Data<Env>,
+) -> Result<HttpResponse> {
+    let product_id = product_id.into_inner();
+    let dir = PathBuf::from(env.image_dir);
+
+    let exists = {
+        let pool = pool.clone();
+        request_id::block(move || {
+            let mut conn = pool
+                .get()
+                .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+            diesel::select(diesel::dsl::exists(
+                products::table.filter(products::id.eq(product_id)),
+            ))
+            .get_result::<bool>(&mut conn)
+            .map_err(|e| format!("Cannot fetch product {product_id}: {e}"))
+        })
+        .await?
+        .map_err(error::ErrorInternalServerError)?
+    };
+    if !exists {
+        return Err(error::ErrorNotFound(format!(
+            "No product with ID {product_id}"
+        )));
+    }
+
+    let mut saved = Vec::<(String, String)>::new();
+    while let Some(mut field) = payload.try_next().await? {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.try_next().await? {
+            if bytes.len() + chunk.len() > MAX_IMAGE_BYTES {
+                remove_image_files(
+                    &saved.into_iter().map(|(key, _)| key).collect::<Vec<_>>(),
+                    &dir,
+                );
+                return Err(error::ErrorPayloadTooLarge("Image is too large"));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let image_dir = dir.clone();
+        match request_id::block(move || save_image(&bytes, &image_dir)).await? {
+            Ok(image) => saved.push(image),
+            Err(e) => {
+                remove_image_files(
+                    &saved.into_iter().map(|(key, _)| key).collect::<Vec<_>>(),
+                    &dir,
+                );
+                return Err(error::ErrorBadRequest(e));
+            }
+        }
+    }
+
+    let keys = saved.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+    let inserted = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        conn.transaction(|conn| {
+            let last = images::table
+                .select(diesel::dsl::max(images::position))
+                .filter(images::product_id.eq(product_id))
+                .get_result::<Option<i32>>(conn)?
+                .unwrap_or(-1);
+
+            saved
+                .iter()
+                .enumerate()
+                .map(|(i, (key, original))| {
+                    diesel::insert_into(images::table)
+                        .values(product_image::NewImage {
+                            product_id,
+                            position: last + 1 + i as i32,
+                            key,
+                            original,
+                        })
+                        .returning(product_image::TableImage::as_returning())
+                        .get_result::<product_image::TableImage>(conn)
+                })
+                .collect::<QueryResult<Vec<_>>>()
+        })
+        .map_err(|e| format!("Cannot insert images into DB: {e}"))
+    })
+    .await?;
+
+    match inserted {
+        Ok(inserted) => Ok(HttpResponse::Ok().json(
+            inserted
+                .into_iter()
+                .map(product_image::Image::from)
+                .collect::<Vec<_>>(),
+        )),
+        Err(e) => {
+            remove_image_files(&keys, &dir);
+            Err(error::ErrorInternalServerError(e))
+        }
+    }
+}
+
+/// Sets the order of a product's images, the body lists every image ID in its new order
+#[put("/stock/{product_id}/images")]
+pub async fn order_images(
+    product_id: web::Path<i32>,
+    order: web::Json<Vec<i32>>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let product_id = product_id.into_inner();
+    let order = order.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+
+        let mut current = images::table
+            .select(images::id)
+            .filter(images::product_id.eq(product_id))
+            .get_results::<i32>(&mut conn)
+            .map_err(|e| format!("Cannot fetch images: {e}"))?;
+        let mut requested = order.clone();
+        current.sort_unstable();
+        requested.sort_unstable();
+        if current != requested {
+            return Err(format!(
+                "Order must list every image of product {product_id} exactly once"
+            ));
+        }
+
+        conn.transaction(|conn| {
+            for (position, id) in order.iter().enumerate() {
+                diesel::update(images::table.filter(images::id.eq(id)))
+                    .set(images::position.eq(position as i32))
+                    .execute(conn)?;
+            }
+
+            Ok::<_, diesel::result::Error>(())
+        })
+        .map_err(|e| format!("Cannot reorder images: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete("/stock/{product_id}/images/{image_id}")]
+pub async fn delete_image(
+    path: web::Path<(i32, i32)>,
+    pool: web::Data<DbPool>,
+    env: web::Data<Env>,
+) -> Result<HttpResponse> {
+    let (product_id, image_id) = path.into_inner();
+
+    let key = request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        diesel::delete(
+            images::table
+                .filter(images::id.eq(image_id))
+                .filter(images::product_id.eq(product_id)),
+        )
+        .returning(images::key)
+        .get_result::<String>(&mut conn)
+        .optional()
+        .map_err(|e| format!("Cannot delete image {image_id}: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorNotFound(format!("No image with ID {image_id}")))?;
+
+    remove_image_files(&[key], Path::new(env.image_dir));
+
+    Ok(HttpResponse::Ok().finish())
+}
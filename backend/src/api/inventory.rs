@@ -0,0 +1,295 @@
+use actix_web::{error, get, put, web, HttpResponse, Result};
+use diesel::prelude::*;
+use model::{
+    inventory, item, price,
+    schema::{inventory_movements, kinds, price_history, products, stock},
+};
+use std::sync::Arc;
+
+use super::notification::notify_back_in_stock;
+use crate::{mail, request_id, DbPool, Mailer, ENV};
+
+/// Applies a movement to its item's quantity and appends it to the ledger,
+/// returning the new quantity. Every change to a stock quantity goes through here
+pub fn record_movement(
+    conn: &mut SqliteConnection,
+    movement: &inventory::NewMovement,
+) -> QueryResult<i32> {
+    conn.transaction(|conn| {
+        let quantity = diesel::update(stock::table.filter(stock::id.eq(movement.item_id)))
+            .set(stock::quantity.eq(stock::quantity + movement.delta))
+            .returning(stock::quantity)
+            .get_result::<i32>(conn)?;
+
+        diesel::insert_into(inventory_movements::table)
+            .values(movement)
+            .execute(conn)?;
+
+        Ok(quantity)
+    })
+}
+
+/// The alert for `item_id` if going from `before` to `after` units is what takes
+/// it down to its low stock threshold
+pub fn low_stock(
+    conn: &mut SqliteConnection,
+    item_id: i32,
+    before: i32,
+    after: i32,
+) -> QueryResult<Option<inventory::LowStock>> {
+    let (sku, options, item_threshold, kind_threshold, title) = stock::table
+        .inner_join(products::table)
+        .inner_join(kinds::table)
+        .filter(stock::id.eq(item_id))
+        .select((
+            stock::sku,
+            stock::options,
+            stock::low_stock_threshold,
+            kinds::low_stock_threshold,
+            products::title,
+        ))
+        .first::<(String, String, Option<i32>, i32, String)>(conn)?;
+
+    let threshold = item_threshold.unwrap_or(kind_threshold);
+    Ok(
+        item::reaches_threshold(before, after, threshold).then(|| inventory::LowStock {
+            item: item_id as model::ItemId,
+            title: item::display_title(&title, &item::parse_options(&options).unwrap_or_default()),
+            sku,
+            quantity: after,
+            threshold: threshold as u32,
+        }),
+    )
+}
+
+/// Emails the admins about `items`, `cause` says what took them down. Run in
+/// the background, failures are only logged
+pub async fn alert_low_stock(cause: String, items: Vec<inventory::LowStock>, mailer: Arc<Mailer>) {
+    let alert = mail::low_stock::LowStock::new(cause, items);
+    if let Err(e) = mail::send::send_low_stock(alert, ENV.admin_email, mailer).await {
+        log::error!("Cannot send low stock alert: {e}");
+    }
+}
+
+/// Appends an item's current price to its price history, unless it's the same
+/// as the last one recorded. Run after anything that might change a price
+pub fn record_price(
+    conn: &mut SqliteConnection,
+    item_id: i32,
+    actor: Option<&str>,
+) -> QueryResult<()> {
+    let (own_price, kind_price) = stock::table
+        .inner_join(kinds::table)
+        .filter(stock::id.eq(item_id))
+        .select((stock::price, kinds::price))
+        .first::<(Option<i32>, i32)>(conn)?;
+    let price = own_price.unwrap_or(kind_price);
+    let last = price_history::table
+        .filter(price_history::item_id.eq(item_id))
+        .order(price_history::id.desc())
+        .select(price_history::price)
+        .first::<i32>(conn)
+        .optional()?;
+
+    if last != Some(price) {
+        diesel::insert_into(price_history::table)
+            .values(price::NewPriceChange {
+                item_id,
+                price,
+                actor,
+            })
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Inserts an item without any units, then records its quantity as a restock
+pub fn insert_item(
+    conn: &mut SqliteConnection,
+    item: item::NewItem,
+    actor: Option<&str>,
+) -> QueryResult<i32> {
+    conn.transaction(|conn| {
+        let quantity = item.quantity;
+        let item_id = diesel::insert_into(stock::table)
+            .values(item::NewItem {
+                quantity: 0,
+                ..item
+            })
+            .returning(stock::id)
+            .get_result::<i32>(conn)?;
+        record_price(conn, item_id, actor)?;
+
+        if quantity != 0 {
+            record_movement(
+                conn,
+                &inventory::NewMovement::new(item_id, quantity, inventory::Reason::Restock),
+            )?;
+        }
+
+        Ok(item_id)
+    })
+}
+
+/// Shows an item's movements and rebuilds its quantity from them for auditing
+#[get("/variants/{item_id}/movements")]
+pub async fn get_movements(
+    item_id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<inventory::ItemHistory>> {
+    let item_id = item_id.into_inner();
+
+    let history = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        let quantity = stock::table
+            .select(stock::quantity)
+            .filter(stock::id.eq(item_id))
+            .first::<i32>(&mut conn)
+            .optional()
+            .map_err(|e| format!("Cannot fetch item {item_id}: {e}"))?;
+        let Some(quantity) = quantity else {
+            return Ok(None);
+        };
+
+        let movements = inventory_movements::table
+            .select(inventory::TableMovement::as_select())
+            .filter(inventory_movements::item_id.eq(item_id))
+            .order(inventory_movements::id)
+            .get_results::<inventory::TableMovement>(&mut conn)
+            .map_err(|e| format!("Cannot fetch movements of item {item_id}: {e}"))?
+            .into_iter()
+            .map(inventory::Movement::try_from)
+            .collect::<Result<Vec<inventory::Movement>, String>>()?;
+
+        Ok::<_, String>(Some(inventory::ItemHistory {
+            item: item_id as model::ItemId,
+            quantity: quantity as i64,
+            ledger_quantity: movements.iter().map(|movement| movement.delta as i64).sum(),
+            movements,
+        }))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorNotFound(format!("No item with ID {item_id}")))?;
+
+    Ok(web::Json(history))
+}
+
+/// Shows every price an item has sold for
+#[get("/variants/{item_id}/prices")]
+pub async fn get_prices(
+    item_id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<price::PriceHistory>> {
+    let item_id = item_id.into_inner();
+
+    let changes = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        price_history::table
+            .select(price::TablePriceChange::as_select())
+            .filter(price_history::item_id.eq(item_id))
+            .order(price_history::id)
+            .get_results::<price::TablePriceChange>(&mut conn)
+            .map_err(|e| format!("Cannot fetch prices of item {item_id}: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    if changes.is_empty() {
+        return Err(error::ErrorNotFound(format!("No item with ID {item_id}")));
+    }
+
+    Ok(web::Json(price::PriceHistory {
+        item: item_id as model::ItemId,
+        changes: changes.into_iter().map(price::PriceChange::from).collect(),
+    }))
+}
+
+/// Records a manual adjustment, quantities cannot be taken below zero this way
+/// but pre-ordered and backordered items can be restocked while below zero.
+/// Restocking a sold out item emails everyone waiting on it, taking an item
+/// down to its low stock threshold alerts the admins
+#[put("/variants/{item_id}/movements")]
+pub async fn adjust_item(
+    item_id: web::Path<i32>,
+    adjustment: web::Json<inventory::AdjustmentFields>,
+    pool: web::Data<DbPool>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    let item_id = item_id.into_inner();
+    let inventory::AdjustmentFields {
+        delta,
+        reason,
+        note,
+    } = adjustment.into_inner();
+
+    if reason == inventory::Reason::Sale {
+        return Err(error::ErrorBadRequest(
+            "Sales are only recorded by checkout",
+        ));
+    }
+    if !reason.allows(delta) {
+        return Err(error::ErrorBadRequest(format!(
+            "{reason} cannot change quantity by {delta}"
+        )));
+    }
+
+    let db_pool = pool.clone();
+    let (before, quantity, low_stock) = request_id::block(move || {
+        let mut conn = db_pool
+            .get()
+            .map_err(|_| "Cannot connect to DB".to_string())?;
+        conn.transaction(|conn| {
+            let quantity = stock::table
+                .select(stock::quantity)
+                .filter(stock::id.eq(item_id))
+                .first::<i32>(conn)?;
+            if delta < 0 && quantity + delta < 0 {
+                return Ok(Err(format!(
+                    "Item {item_id} only has {quantity} units, cannot remove {}",
+                    -delta
+                )));
+            }
+
+            let after = record_movement(
+                conn,
+                &inventory::NewMovement {
+                    note: note.as_deref(),
+                    ..inventory::NewMovement::new(item_id, delta, reason)
+                },
+            )?;
+            let low_stock = low_stock(conn, item_id, quantity, after)?;
+
+            Ok::<_, diesel::result::Error>(Ok((quantity, after, low_stock)))
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => format!("Item {item_id} does not exist"),
+            e => format!("Cannot adjust item {item_id}: {e}"),
+        })?
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    if let Some(low_stock) = low_stock {
+        request_id::spawn(alert_low_stock(
+            format!("A {reason} adjustment"),
+            vec![low_stock],
+            mailer.clone().into_inner(),
+        ));
+    }
+    if item::is_back_in_stock(before, quantity) {
+        request_id::spawn(notify_back_in_stock(
+            vec![item_id],
+            pool.into_inner(),
+            mailer.into_inner(),
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(quantity))
+}
@@ -0,0 +1,115 @@
+use actix_web::{error, get, put, web, HttpResponse, Result};
+use diesel::prelude::*;
+use model::{
+    kind,
+    schema::{kinds, stock},
+};
+
+use super::inventory::record_price;
+use crate::{request_id, DbPool};
+
+/// Looks up the ID of the kind named `name`, unknown kinds are a bad request
+pub async fn kind_id(name: String, pool: &web::Data<DbPool>) -> Result<i32> {
+    let pool = pool.clone();
+    request_id::block(move || -> std::result::Result<Option<i32>, String> {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        kinds::table
+            .select(kinds::id)
+            .filter(kinds::name.eq(&name))
+            .first::<i32>(&mut conn)
+            .optional()
+            .map_err(|e| format!("Cannot fetch kind {name}: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorBadRequest("Unknown kind"))
+}
+
+#[get("/kinds")]
+pub async fn get_kinds(pool: web::Data<DbPool>) -> Result<web::Json<Vec<kind::Kind>>> {
+    let kinds = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        kinds::table
+            .select(kind::TableKind::as_select())
+            .order(kinds::id)
+            .get_results::<kind::TableKind>(&mut conn)
+            .map_err(|e| format!("Cannot fetch kinds: {e}"))?
+            .into_iter()
+            .map(kind::Kind::try_from)
+            .collect::<Result<Vec<kind::Kind>, String>>()
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(web::Json(kinds))
+}
+
+#[put("/kinds")]
+pub async fn put_kind(
+    pool: web::Data<DbPool>,
+    fields: web::Json<kind::KindFields>,
+) -> Result<HttpResponse> {
+    let fields = fields.into_inner();
+
+    let id = request_id::block(move || {
+        let new_kind = kind::NewKind::try_from(&fields)?;
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        diesel::insert_into(kinds::table)
+            .values(new_kind)
+            .returning(kinds::id)
+            .get_result::<i32>(&mut conn)
+            .map_err(|_| "Cannot insert kind, is the name already taken?".to_string())
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(id))
+}
+
+/// Updates a kind, a new price is recorded against every item using it
+#[put("/kinds/{kind_id}")]
+pub async fn update_kind(
+    kind_id: web::Path<i32>,
+    fields: web::Json<kind::KindFields>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let kind_id = kind_id.into_inner();
+    let fields = fields.into_inner();
+
+    request_id::block(move || {
+        let new_kind = kind::NewKind::try_from(&fields)?;
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        conn.transaction(|conn| {
+            let updated = diesel::update(kinds::table.filter(kinds::id.eq(kind_id)))
+                .set(new_kind)
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(diesel::result::Error::NotFound);
+            }
+
+            let item_ids = stock::table
+                .filter(stock::kind.eq(kind_id))
+                .select(stock::id)
+                .get_results::<i32>(conn)?;
+            for item_id in item_ids {
+                record_price(conn, item_id, None)?;
+            }
+
+            Ok(())
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => format!("Kind {kind_id} does not exist"),
+            _ => format!("Cannot update kind {kind_id}"),
+        })
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -1,47 +1,60 @@
 // NOTE: The functions in this module ignore `Err` values because failing to log a visit is ok
-use actix_web::{web, HttpRequest, Result};
-use awc::Client;
+use actix_web::{http::header, web, HttpRequest, Result};
 use chrono::prelude::*;
 use model::{schema::users, user};
 
-use crate::DbConn;
+use crate::{bots, client_ip::client_ip, geoip::GeoIp, request_id, visits, DbConn};
 
-async fn get_location(ip: &str) -> Result<user::Location, ()> {
-    actix_web::rt::System::new().block_on(async {
-        let client = Client::default();
-        client
-            .get(format!("http://ip-api.com/json/{ip}"))
-            .send()
-            .await
-            .map_err(|_| ())?
-            .json::<user::Location>()
-            .await
-            .map_err(|_| ())
-    })
+/// Sent by the frontend with `document.referrer`, as its requests' own `Referer`
+/// is the shop's page
+pub const REFERRER_HEADER: &str = "X-Page-Referrer";
+
+/// Host of the site that linked to the shop, links within the shop aren't
+/// referrals. Full URLs aren't kept, they can identify the visitor
+fn referrer(req: &HttpRequest) -> Option<String> {
+    let referrer = [REFERRER_HEADER, header::REFERER.as_str()]
+        .iter()
+        .filter_map(|name| req.headers().get(*name))
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| !value.trim().is_empty())?;
+    let host = url::Url::parse(referrer.trim())
+        .ok()?
+        .host_str()?
+        .to_owned();
+
+    let own_host = url::Url::parse(&format!("http://{}", req.connection_info().host()))
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned));
+    (own_host.as_ref() != Some(&host)).then_some(host)
 }
 
-async fn get_user(req: HttpRequest) -> Result<user::User, String> {
+/// `visitor` is the hash of the visitor's IP, which is only used for its location
+fn get_user(req: &HttpRequest, visitor: String) -> Result<user::User, String> {
     let time = Utc::now().naive_utc();
-    let ip = req.peer_addr().map(|addr| addr.ip().to_string());
+    let ip = client_ip(req);
 
-    let location = if let Some(ref ip) = ip {
-        get_location(ip).await.unwrap_or_default()
-    } else {
-        user::Location::default()
+    // Apps without a GeoIP database, like most tests, log visits without a location
+    let location = match (ip, req.app_data::<web::Data<GeoIp>>()) {
+        (Some(ip), Some(geoip)) => geoip.locate(ip),
+        _ => user::Location::default(),
     };
 
-    let ip = ip.unwrap_or_default();
-
     let user_agent = req
         .headers()
         .get("User-Agent")
         .and_then(|field| field.to_str().ok())
         .map(|s| s.to_owned());
 
-    let device = user_agent
-        .as_ref()
-        .map(user::Device::from)
+    let is_bot = bots::is_bot(req);
+    let mut agent = user_agent
+        .as_deref()
+        .map(crate::user_agent::parse)
         .unwrap_or_default();
+    // Bots posing as browsers are told apart by more than their user agent
+    if is_bot {
+        agent.device_class = user::DeviceClass::Bot;
+    }
+    let device = user::Device::from(&agent);
 
     let user::Location {
         country,
@@ -51,19 +64,22 @@ async fn get_user(req: HttpRequest) -> Result<user::User, String> {
 
     Ok(user::User {
         device,
-        ip,
+        visitor,
         user_agent,
+        agent,
         time,
         country,
         state,
         city,
+        referrer: referrer(req),
+        is_bot,
     })
 }
 
 async fn insert_user(user: user::User, mut conn: DbConn) {
     use diesel::RunQueryDsl;
 
-    let _ = web::block(move || {
+    let _ = request_id::block(move || {
         diesel::insert_into(users::table)
             .values(user::NewUser::from(&user))
             .execute(&mut conn)
@@ -71,8 +87,24 @@ async fn insert_user(user: user::User, mut conn: DbConn) {
     .await;
 }
 
-pub async fn log_user(req: HttpRequest, conn: DbConn) {
-    if let Ok(user) = get_user(req).await {
+/// Logs a visit unless the visitor opted out of tracking
+pub async fn log_user(req: HttpRequest, mut conn: DbConn) {
+    if visits::opted_out(&req) {
+        return;
+    }
+
+    let today = Utc::now().date_naive();
+    let Ok(Ok((salt, conn))) =
+        request_id::block(move || visits::daily_salt(&mut conn, today).map(|salt| (salt, conn)))
+            .await
+    else {
+        return;
+    };
+    let visitor = client_ip(&req)
+        .map(|ip| visits::hash_visitor(&salt, ip))
+        .unwrap_or_default();
+
+    if let Ok(user) = get_user(&req, visitor) {
         insert_user(user, conn).await
     } // Otherwise do nothing! missing a visit is fine :)
 }
@@ -1,4 +1,15 @@
+pub mod analytics;
+pub mod cart;
+pub mod catalogue;
+pub mod category;
+pub mod collection;
+pub mod image;
+pub mod inventory;
+pub mod kind;
 mod metrics;
+pub mod monitoring;
+pub mod notification;
 pub mod order;
 pub mod stock;
 pub mod stripe;
+pub mod tag;
@@ -0,0 +1,28 @@
+use actix_web::{error, get, web, HttpRequest, HttpResponse, Result};
+
+use crate::{
+    monitoring::{MetricsAccess, PoolUsage, METRICS},
+    DbPool,
+};
+
+/// Request counts and latencies, DB pool usage, webhook events, emails and
+/// checkout failures for Prometheus. Only clients in `METRICS_ALLOWED` may
+/// scrape them, it's as if there were no such route when that's empty. nginx
+/// doesn't proxy `/metrics`, so the connection's peer is checked rather than
+/// forwarding headers any container on the network could send
+#[get("/metrics")]
+pub async fn get_metrics(req: HttpRequest, pool: web::Data<DbPool>) -> Result<HttpResponse> {
+    let Some(access) = req
+        .app_data::<web::Data<MetricsAccess>>()
+        .filter(|access| access.is_enabled())
+    else {
+        return Err(error::ErrorNotFound("Not found"));
+    };
+    if !req.peer_addr().is_some_and(|peer| access.allows(peer.ip())) {
+        return Err(error::ErrorForbidden("Not allowed to scrape metrics"));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(METRICS.render(PoolUsage::from(pool.get_ref()))))
+}
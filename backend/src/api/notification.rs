@@ -0,0 +1,311 @@
+use actix_web::{error, get, post, web, HttpResponse, Result};
+use askama::Template;
+use diesel::prelude::*;
+use model::{
+    item, notification,
+    schema::{products, stock, stock_notifications},
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    mail::{self, notification::BackInStock, notification::ConfirmNotification},
+    request_id, DbPool, Mailer, ENV,
+};
+
+/// How long an unconfirmed signup waits before signing up again resends the
+/// confirmation email
+const CONFIRMATION_RESEND_INTERVAL: chrono::Duration = chrono::Duration::minutes(15);
+
+/// Signs an email up to hear when a product, or one of its variants, is back in
+/// stock. The signup only counts once the emailed confirmation link is followed,
+/// and the response is the same whether or not the email was already signed up
+#[post("/stock/{product_id}/notify")]
+pub async fn notify_signup(
+    product_id: web::Path<i32>,
+    fields: web::Json<notification::NotificationFields>,
+    pool: web::Data<DbPool>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    let product_id = product_id.into_inner();
+    let notification::NotificationFields { email, item } = fields.into_inner();
+
+    let email = email.trim().to_string();
+    email
+        .parse::<lettre::Address>()
+        .map_err(|_| error::ErrorBadRequest(format!("Invalid email {email}")))?;
+
+    let db_pool = pool.clone();
+    let title = request_id::block(move || {
+        let mut conn = db_pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        match item {
+            Some(item_id) => stock::table
+                .inner_join(products::table)
+                .filter(stock::id.eq(item_id as i32))
+                .filter(stock::product_id.eq(product_id))
+                .select((products::title, stock::options))
+                .first::<(String, String)>(&mut conn)
+                .optional()
+                .map_err(|e| format!("Cannot fetch item {item_id}: {e}"))?
+                .map(|(title, options)| {
+                    item::parse_options(&options)
+                        .map(|options| item::display_title(&title, &options))
+                })
+                .transpose(),
+            None => products::table
+                .filter(products::id.eq(product_id))
+                .select(products::title)
+                .first::<String>(&mut conn)
+                .optional()
+                .map_err(|e| format!("Cannot fetch product {product_id}: {e}")),
+        }
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorNotFound("No such product"))?;
+
+    let signup_email = email.clone();
+    let token = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        let mut existing = stock_notifications::table
+            .filter(stock_notifications::product_id.eq(product_id))
+            .filter(stock_notifications::email.eq(&signup_email))
+            .select((
+                stock_notifications::id,
+                stock_notifications::token,
+                stock_notifications::confirmed,
+            ))
+            .into_boxed();
+        existing = match item {
+            Some(item_id) => existing.filter(stock_notifications::item_id.eq(item_id as i32)),
+            None => existing.filter(stock_notifications::item_id.is_null()),
+        };
+        let existing = existing
+            .first::<(i32, String, bool)>(&mut conn)
+            .optional()
+            .map_err(|e| format!("Cannot fetch signup: {e}"))?;
+
+        let now = chrono::Utc::now().naive_utc();
+        match existing {
+            Some((_, _, true)) => Ok(None),
+            // Signing up again resends the confirmation, unless it was only just
+            // sent so repeat submits can't be used to flood someone's inbox
+            Some((id, token, false)) => {
+                let resent = diesel::update(stock_notifications::table)
+                    .filter(stock_notifications::id.eq(id))
+                    .filter(
+                        stock_notifications::confirmation_sent
+                            .is_null()
+                            .or(stock_notifications::confirmation_sent
+                                .lt(now - CONFIRMATION_RESEND_INTERVAL)),
+                    )
+                    .set(stock_notifications::confirmation_sent.eq(now))
+                    .execute(&mut conn)
+                    .map_err(|e| format!("Cannot update signup: {e}"))?;
+                Ok((resent > 0).then_some(token))
+            }
+            None => {
+                let token = uuid::Uuid::new_v4().to_string();
+                diesel::insert_into(stock_notifications::table)
+                    .values(notification::NewNotification {
+                        product_id,
+                        item_id: item.map(|item_id| item_id as i32),
+                        email: &signup_email,
+                        token: &token,
+                        confirmation_sent: now,
+                    })
+                    .execute(&mut conn)
+                    .map_err(|e| format!("Cannot save signup: {e}"))?;
+                Ok::<_, String>(Some(token))
+            }
+        }
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    let confirmation =
+        token.map(|token| ConfirmNotification::new(ENV.public_url, email, title, &token));
+    if let Some(confirmation) = confirmation {
+        request_id::spawn(async move {
+            if let Err(e) =
+                mail::send::send_notification_confirmation(confirmation, mailer.into_inner()).await
+            {
+                log::error!("Cannot send back in stock confirmation: {e}");
+            }
+        });
+    }
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Page the links in signup emails open. Following a link only shows the page,
+/// the signup changes once its form is posted back to the same link, so link
+/// scanners and prefetching can't confirm or unsubscribe anyone
+#[derive(askama::Template)]
+#[template(path = "notification_action.html")]
+struct NotificationAction {
+    heading: &'static str,
+    message: &'static str,
+    button: &'static str,
+}
+
+impl NotificationAction {
+    fn respond(&self) -> Result<HttpResponse> {
+        let page = self.render().map_err(error::ErrorInternalServerError)?;
+        Ok(HttpResponse::Ok().content_type("text/html").body(page))
+    }
+}
+
+#[get("/notify/{token}/confirm")]
+pub async fn confirm_notification_page() -> Result<HttpResponse> {
+    NotificationAction {
+        heading: "Confirm your email",
+        message: "Please confirm you'd like to be emailed when it's back in stock.",
+        button: "Confirm",
+    }
+    .respond()
+}
+
+#[post("/notify/{token}/confirm")]
+pub async fn confirm_notification(
+    token: web::Path<String>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let token = token.into_inner();
+
+    let updated = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        diesel::update(stock_notifications::table)
+            .filter(stock_notifications::token.eq(&token))
+            .set(stock_notifications::confirmed.eq(true))
+            .execute(&mut conn)
+            .map_err(|e| format!("Cannot confirm signup: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    if updated == 0 {
+        return Err(error::ErrorNotFound(
+            "This link has expired, please sign up again",
+        ));
+    }
+
+    Ok(HttpResponse::Ok().body("Thanks! We'll email you when it's back in stock"))
+}
+
+#[get("/notify/{token}/unsubscribe")]
+pub async fn unsubscribe_notification_page() -> Result<HttpResponse> {
+    NotificationAction {
+        heading: "Unsubscribe",
+        message: "Stop hearing about this product being back in stock?",
+        button: "Unsubscribe",
+    }
+    .respond()
+}
+
+/// Removes a signup, unsubscribing again is harmless
+#[post("/notify/{token}/unsubscribe")]
+pub async fn unsubscribe_notification(
+    token: web::Path<String>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let token = token.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        diesel::delete(stock_notifications::table)
+            .filter(stock_notifications::token.eq(&token))
+            .execute(&mut conn)
+            .map_err(|e| format!("Cannot remove signup: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().body("You've been unsubscribed"))
+}
+
+/// Loads the confirmed signups waiting on any of `item_ids`, either on the item
+/// itself or on its whole product, and marks them as notified
+pub fn due_notifications(
+    conn: &mut SqliteConnection,
+    item_ids: &[i32],
+) -> Result<Vec<BackInStock>, String> {
+    let items = stock::table
+        .inner_join(products::table)
+        .filter(stock::id.eq_any(item_ids))
+        .select((
+            stock::id,
+            stock::product_id,
+            products::title,
+            stock::options,
+        ))
+        .get_results::<(i32, i32, String, String)>(conn)
+        .map_err(|e| format!("Cannot fetch items: {e}"))?;
+
+    // Keyed by signup so product wide signups are only emailed once
+    let mut due = BTreeMap::<i32, BackInStock>::new();
+    for (item_id, product_id, title, options) in items {
+        let signups = stock_notifications::table
+            .filter(stock_notifications::confirmed.eq(true))
+            .filter(stock_notifications::product_id.eq(product_id))
+            .filter(
+                stock_notifications::item_id
+                    .eq(item_id)
+                    .or(stock_notifications::item_id.is_null()),
+            )
+            .select(notification::TableNotification::as_select())
+            .get_results::<notification::TableNotification>(conn)
+            .map_err(|e| format!("Cannot fetch signups: {e}"))?;
+
+        for signup in signups {
+            let title = match signup.item_id {
+                Some(_) => item::display_title(&title, &item::parse_options(&options)?),
+                None => title.clone(),
+            };
+            due.entry(signup.id).or_insert_with(|| {
+                BackInStock::new(ENV.public_url, signup.email, title, item_id, &signup.token)
+            });
+        }
+    }
+
+    diesel::update(stock_notifications::table)
+        .filter(stock_notifications::id.eq_any(due.keys()))
+        .set(stock_notifications::notified.eq(diesel::dsl::now))
+        .execute(conn)
+        .map_err(|e| format!("Cannot mark signups as notified: {e}"))?;
+
+    Ok(due.into_values().collect())
+}
+
+/// Emails everyone waiting on the restocked `item_ids`. Run in the background,
+/// failures are only logged
+pub async fn notify_back_in_stock(item_ids: Vec<i32>, pool: Arc<DbPool>, mailer: Arc<Mailer>) {
+    let due = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        due_notifications(&mut conn, &item_ids)
+    })
+    .await;
+
+    let due = match due {
+        Ok(Ok(due)) => due,
+        Ok(Err(e)) => return log::error!("Cannot load back in stock signups: {e}"),
+        Err(e) => return log::error!("Cannot load back in stock signups: {e}"),
+    };
+
+    for restock in due {
+        if let Err(e) = mail::send::send_back_in_stock(restock, mailer.clone()).await {
+            log::error!("Cannot send back in stock email: {e}");
+        }
+    }
+}
@@ -1,18 +1,31 @@
 use actix_web::{delete, error, get, put, web, HttpResponse, Result};
 use diesel::prelude::*;
 use model::{
-    address, cart, order,
-    schema::{addresses, carts, orders},
+    address, cart, inventory, item, order,
+    quote::Warning,
+    schema::{addresses, carts, kinds, orders, stock},
 };
 
 use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     mail::{self, shipped},
-    DbConn, DbPool, Mailer,
+    request_id, DbConn, DbPool, Mailer,
 };
 
-use super::stripe;
+use super::{inventory::record_movement, notification::notify_back_in_stock, stripe};
+
+/// IDs of orders with lines that were sold beyond the units on hand, and whose
+/// items haven't been restocked since
+fn orders_awaiting_stock(conn: &mut SqliteConnection) -> QueryResult<Vec<i32>> {
+    carts::table
+        .inner_join(stock::table)
+        .filter(carts::awaiting_stock.gt(0))
+        .filter(stock::quantity.lt(0))
+        .select(carts::order_id)
+        .distinct()
+        .get_results(conn)
+}
 
 #[get("/orders/{filter}")]
 pub async fn get_orders(
@@ -21,7 +34,7 @@ pub async fn get_orders(
 ) -> Result<HttpResponse> {
     let filter = filter.into_inner();
 
-    let orders = web::block(move || {
+    let orders = request_id::block(move || {
         let mut conn = pool
             .get()
             .map_err(|e| format!("Cannot connect to database: {e}"))?;
@@ -37,6 +50,18 @@ pub async fn get_orders(
             Unshipped => select
                 .filter(orders::shipped.eq(false))
                 .get_results(&mut conn),
+            ReadyToShip => orders_awaiting_stock(&mut conn).and_then(|awaiting| {
+                select
+                    .filter(orders::shipped.eq(false))
+                    .filter(orders::id.ne_all(awaiting))
+                    .get_results(&mut conn)
+            }),
+            AwaitingStock => orders_awaiting_stock(&mut conn).and_then(|awaiting| {
+                select
+                    .filter(orders::shipped.eq(false))
+                    .filter(orders::id.eq_any(awaiting))
+                    .get_results(&mut conn)
+            }),
         }
         .map_err(|e| format!("Cannot fetch orders from DB: {e}"))
     })
@@ -47,6 +72,47 @@ pub async fn get_orders(
     Ok(HttpResponse::Ok().content_type("text/json").body(json))
 }
 
+/// An order with its lines as they were sold
+#[get("/orders/{id}/detail")]
+pub async fn get_order(
+    pool: web::Data<DbPool>,
+    id: web::Path<i32>,
+) -> Result<web::Json<order::OrderDetail>> {
+    let id = id.into_inner();
+
+    let detail = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to database: {e}"))?;
+
+        let Some(order) = orders::table
+            .select(order::TableOrder::as_select())
+            .filter(orders::id.eq(id))
+            .first::<order::TableOrder>(&mut conn)
+            .optional()
+            .map_err(|e| format!("Cannot fetch order {id}: {e}"))?
+        else {
+            return Ok(None);
+        };
+        let lines = carts::table
+            .select(cart::TableCart::as_select())
+            .filter(carts::order_id.eq(id))
+            .order(carts::id)
+            .get_results::<cart::TableCart>(&mut conn)
+            .map_err(|e| format!("Cannot fetch lines of order {id}: {e}"))?
+            .into_iter()
+            .map(order::OrderLine::from)
+            .collect();
+
+        Ok::<_, String>(Some(order::OrderDetail { order, lines }))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorNotFound(format!("No order with ID {id}")))?;
+
+    Ok(web::Json(detail))
+}
+
 #[put("/orders/shipped")]
 pub async fn order_shipped(
     pool: web::Data<DbPool>,
@@ -60,7 +126,7 @@ pub async fn order_shipped(
         .map_err(|e| error::ErrorInternalServerError(format!("Cannot connect to DB: {e}")))?;
 
     let id = order_id.into_inner() as i32;
-    let order = web::block(move || {
+    let order = request_id::block(move || {
         let order: order::TableOrder = orders::table
             .select(order::TableOrder::as_select())
             .filter(orders::id.eq(id))
@@ -97,20 +163,76 @@ pub async fn order_shipped(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Cancels an order, the items of an unshipped order are put back in stock. The
+/// order is deleted along with its lines and address
 #[delete("/orders/{id}")]
-pub async fn delete_order(pool: web::Data<DbPool>, id: web::Path<i32>) -> Result<HttpResponse> {
+pub async fn delete_order(
+    pool: web::Data<DbPool>,
+    mailer: web::Data<Mailer>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse> {
     let id = id.into_inner();
 
-    web::block(move || {
-        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+    let db_pool = pool.clone();
+    let restocked = request_id::block(move || {
+        let mut conn = db_pool.get().map_err(|_| "Cannot connect to DB")?;
 
-        diesel::delete(orders::table.filter(orders::id.eq(id)))
-            .execute(&mut conn)
-            .map_err(|_| "Cannot delete order")
+        conn.transaction(|conn| {
+            let mut restocked = Vec::new();
+            let shipped = orders::table
+                .select(orders::shipped)
+                .filter(orders::id.eq(id))
+                .first::<bool>(conn)
+                .optional()?;
+
+            if shipped == Some(false) {
+                let lines = carts::table
+                    .select((carts::item_id, carts::quantity))
+                    .filter(carts::order_id.eq(id))
+                    .get_results::<(i32, i32)>(conn)?;
+                for (item_id, quantity) in lines {
+                    match record_movement(
+                        conn,
+                        &inventory::NewMovement {
+                            order_id: Some(id),
+                            ..inventory::NewMovement::new(
+                                item_id,
+                                quantity,
+                                inventory::Reason::CancellationReturn,
+                            )
+                        },
+                    ) {
+                        Ok(after) => {
+                            if item::is_back_in_stock(after - quantity, after) {
+                                restocked.push(item_id);
+                            }
+                        }
+                        // The item was deleted since, there's nothing to return it to
+                        Err(diesel::result::Error::NotFound) => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+
+            // Left behind, the lines would keep their items from being deleted
+            diesel::delete(carts::table.filter(carts::order_id.eq(id))).execute(conn)?;
+            diesel::delete(addresses::table.filter(addresses::order_id.eq(id))).execute(conn)?;
+            diesel::delete(orders::table.filter(orders::id.eq(id))).execute(conn)?;
+            Ok(restocked)
+        })
+        .map_err(|_| "Cannot delete order")
     })
     .await?
     .map_err(error::ErrorInternalServerError)?;
 
+    if !restocked.is_empty() {
+        request_id::spawn(notify_back_in_stock(
+            restocked,
+            pool.into_inner(),
+            mailer.into_inner(),
+        ));
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -118,11 +240,12 @@ pub async fn insert_order(
     mut conn: DbConn,
     cart: Arc<HashMap<model::ItemId, stripe::Item>>,
     total: u32,
+    payment: order::OrderPayment,
     name: Arc<str>,
     email: Arc<str>,
     address: Arc<address::Address>,
-) -> Result<()> {
-    web::block(move || -> std::result::Result<(), String> {
+) -> Result<i32> {
+    request_id::block(move || -> std::result::Result<i32, String> {
         let order = order::NewOrder {
             name: &name,
             total: total as i32,
@@ -131,19 +254,35 @@ pub async fn insert_order(
         };
 
         let order_id = diesel::insert_into(orders::table)
-            .values(&order)
+            .values((&order, &payment))
             .returning(orders::id)
             .get_result::<i32>(&mut conn)
             .map_err(|_| "Cannot insert order into DB")?;
 
+        let item_kinds = stock::table
+            .inner_join(kinds::table)
+            .filter(stock::id.eq_any(cart.keys().map(|id| *id as i32)))
+            .select((stock::id, kinds::name))
+            .get_results::<(i32, String)>(&mut conn)
+            .map_err(|e| format!("Cannot fetch item kinds: {e}"))?
+            .into_iter()
+            .collect::<HashMap<i32, String>>();
+
         let new_carts = cart
             .iter()
-            .map(|(item_id, item)| cart::NewCart {
-                order_id,
-                item_id: *item_id as i32,
-                quantity: item.quantity as i32,
+            .map(|(item_id, item)| {
+                cart::NewCart::new(
+                    order_id,
+                    *item_id as i32,
+                    item.quantity as i32,
+                    item.price as i32,
+                    &item.title,
+                    item_kinds
+                        .get(&(*item_id as i32))
+                        .map_or("", String::as_str),
+                )
             })
-            .collect::<Vec<cart::NewCart>>();
+            .collect::<std::result::Result<Vec<cart::NewCart>, String>>()?;
 
         diesel::insert_into(carts::table)
             .values(&new_carts)
@@ -157,8 +296,53 @@ pub async fn insert_order(
             .execute(&mut conn)
             .map_err(|e| format!("Cannot insert address into db: {e}"))?;
 
-        Ok(())
+        Ok(order_id)
     })
     .await?
     .map_err(error::ErrorInternalServerError)
 }
+
+/// Records what has been refunded of the order paid with `payment_intent`,
+/// Stripe reports the total refunded so far
+pub async fn record_refund(pool: Arc<DbPool>, payment_intent: String, refunded: u32) -> Result<()> {
+    let intent = payment_intent.clone();
+    let updated = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to database: {e}"))?;
+        diesel::update(orders::table.filter(orders::payment_intent.eq(intent)))
+            .set(orders::refunded.eq(refunded as i32))
+            .execute(&mut conn)
+            .map_err(|e| format!("Cannot record refund: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    // Orders placed before payment intents were kept can't be matched
+    if updated == 0 {
+        log::warn!(payment_intent = payment_intent.as_str(); "Refund of unknown payment");
+    }
+
+    Ok(())
+}
+
+/// Flags a paid order that broke the limits it was checked out under, with why,
+/// so it can be refunded
+pub async fn flag_order(pool: Arc<DbPool>, order_id: i32, warnings: Vec<Warning>) -> Result<()> {
+    let flagged = serde_json::to_string(&warnings)?;
+    request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to database: {e}"))?;
+        diesel::update(orders::table.filter(orders::id.eq(order_id)))
+            .set(orders::warnings.eq(flagged))
+            .execute(&mut conn)
+            .map_err(|e| format!("Cannot flag order {order_id}: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    log::warn!(order_id, warnings = warnings.len(); "Order paid despite breaking its limits");
+
+    Ok(())
+}
@@ -1,128 +1,543 @@
-use model::{item, schema::stock};
-
-use actix_web::{delete, error, get, put, web, HttpResponse, Result};
+use model::{
+    funnel::Stage,
+    image, inventory, item, kind, product,
+    schema::{
+        carts, catalogue_version, categories, collection_products, images, kinds, orders,
+        product_tags, products, stock, tags,
+    },
+    search,
+};
+
+use actix_web::{
+    delete, error, get,
+    http::header::{self, EntityTag, HttpDate},
+    post, put, web, HttpMessage, HttpResponse, Result,
+};
 use std::{collections::HashMap, sync::Arc};
 
-use diesel::{prelude::*, r2d2::ConnectionManager};
-
-use super::metrics::log_user;
-use crate::DbPool;
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    r2d2::ConnectionManager,
+    sql_types::{Bool, Integer, Nullable},
+    sqlite::Sqlite,
+};
+
+use super::{
+    category::category_id,
+    image::remove_image_files,
+    inventory::{alert_low_stock, insert_item, low_stock, record_movement, record_price},
+    kind::kind_id,
+    metrics::log_user,
+    notification::notify_back_in_stock,
+    tag::set_tags,
+};
+use crate::{env::Env, funnel, request_id, DbPool, Mailer};
 
 use super::stripe;
 
-pub async fn item_from_db(item_id: model::ItemId, pool: &web::Data<DbPool>) -> Result<item::Item> {
-    let mut conn = pool.get().unwrap();
-    web::block(move || -> std::result::Result<item::Item, String> {
-        match stock::table
-            .filter(stock::id.eq(item_id as i32))
-            .select(item::TableItem::as_select())
-            .get_result(&mut conn)
-        {
-            Ok(item) => Ok(item::Item::from(item)),
-            Err(e) => Err(format!("Cannot fetch item: {e}")),
+/// IDs of the products matching every filter
+fn matching_product_ids(
+    filter: &product::StockFilter,
+) -> products::BoxedQuery<'static, Sqlite, Integer> {
+    let archived = filter.archived;
+    let mut query = products::table.select(products::id).into_boxed();
+    if !archived {
+        // Products without any variants yet are still listed
+        query = query.filter(
+            products::id
+                .eq_any(
+                    stock::table
+                        .filter(stock::archived.is_null())
+                        .select(stock::product_id),
+                )
+                .or(products::id.ne_all(stock::table.select(stock::product_id))),
+        );
+    }
+    if let Some(category) = filter.category.clone() {
+        query = query.filter(
+            products::category_id.eq_any(
+                categories::table
+                    .filter(categories::name.eq(category))
+                    .select(categories::id.nullable()),
+            ),
+        );
+    }
+    if let Some(tag) = filter.tag.clone() {
+        query = query.filter(
+            products::id.eq_any(
+                product_tags::table
+                    .inner_join(tags::table)
+                    .filter(tags::name.eq(tag))
+                    .select(product_tags::product_id),
+            ),
+        );
+    }
+    if let Some(kind) = filter.kind.clone() {
+        query = query.filter(
+            products::id.eq_any(
+                stock::table
+                    .inner_join(kinds::table)
+                    .filter(kinds::name.eq(kind))
+                    .filter(stock::archived.is_null().or(archived.into_sql::<Bool>()))
+                    .select(stock::product_id),
+            ),
+        );
+    }
+    if filter.in_stock {
+        query = query.filter(
+            products::id.eq_any(
+                stock::table
+                    .filter(stock::quantity.gt(0))
+                    .filter(stock::archived.is_null().or(archived.into_sql::<Bool>()))
+                    .select(stock::product_id),
+            ),
+        );
+    }
+
+    query
+}
+
+/// Loads products along with their variants, all products if `ids` is `None`.
+/// Only products matching every filter are returned
+pub fn load_products(
+    conn: &mut SqliteConnection,
+    ids: Option<Vec<i32>>,
+    filter: &product::StockFilter,
+) -> Result<HashMap<product::ProductId, product::Product>, String> {
+    let mut query = products::table
+        .left_join(categories::table)
+        .filter(products::id.eq_any(matching_product_ids(filter)))
+        .select((
+            product::TableProduct::as_select(),
+            categories::name.nullable(),
+        ))
+        .into_boxed();
+    if let Some(ids) = ids {
+        query = query.filter(products::id.eq_any(ids));
+    }
+    let table_products = query
+        .get_results::<(product::TableProduct, Option<String>)>(conn)
+        .map_err(|e| format!("Cannot fetch products: {e}"))?;
+
+    let product_ids = table_products
+        .iter()
+        .map(|(p, _)| p.id)
+        .collect::<Vec<i32>>();
+    let mut variant_query = stock::table
+        .inner_join(kinds::table)
+        .filter(stock::product_id.eq_any(&product_ids))
+        .select((item::TableItem::as_select(), kind::TableKind::as_select()))
+        .into_boxed();
+    if let Some(ref kind) = filter.kind {
+        variant_query = variant_query.filter(kinds::name.eq(kind.clone()));
+    }
+    if !filter.archived {
+        variant_query = variant_query.filter(stock::archived.is_null());
+    }
+    let variants = variant_query
+        .get_results::<(item::TableItem, kind::TableKind)>(conn)
+        .map_err(|e| format!("Cannot fetch stock: {e}"))?;
+    let table_images = images::table
+        .filter(images::product_id.eq_any(&product_ids))
+        .order(images::position)
+        .select(image::TableImage::as_select())
+        .get_results::<image::TableImage>(conn)
+        .map_err(|e| format!("Cannot fetch images: {e}"))?;
+    let product_tag_names = product_tags::table
+        .inner_join(tags::table)
+        .filter(product_tags::product_id.eq_any(&product_ids))
+        .order(tags::name)
+        .select((product_tags::product_id, tags::name))
+        .get_results::<(i32, String)>(conn)
+        .map_err(|e| format!("Cannot fetch tags: {e}"))?;
+
+    let mut products = table_products
+        .into_iter()
+        .map(|(p, category)| {
+            (
+                p.id as product::ProductId,
+                product::Product {
+                    category,
+                    ..product::Product::from(p)
+                },
+            )
+        })
+        .collect::<HashMap<product::ProductId, product::Product>>();
+
+    for pair @ (item::TableItem { id, product_id, .. }, _) in variants {
+        if let Some(product) = products.get_mut(&(product_id as product::ProductId)) {
+            product
+                .variants
+                .insert(id as model::ItemId, item::Item::try_from(pair)?);
+        }
+    }
+
+    for table_image in table_images {
+        if let Some(product) = products.get_mut(&(table_image.product_id as product::ProductId)) {
+            product.images.push(image::Image::from(table_image));
+        }
+    }
+
+    for (product_id, name) in product_tag_names {
+        if let Some(product) = products.get_mut(&(product_id as product::ProductId)) {
+            product.tags.push(name);
         }
+    }
+
+    Ok(products)
+}
+
+/// Inserts a product along with its variants and tags, `kinds` holds the kind
+/// ID of each variant. Initial quantities are recorded as restocks, and prices
+/// as set by `actor`
+pub fn insert_product(
+    conn: &mut SqliteConnection,
+    fields: &product::ProductFields,
+    kinds: &[i32],
+    category_id: Option<i32>,
+    actor: Option<&str>,
+) -> QueryResult<i32> {
+    conn.transaction(|conn| {
+        let product_id = diesel::insert_into(products::table)
+            .values(product::NewProduct::new(fields, category_id))
+            .returning(products::id)
+            .get_result::<i32>(conn)?;
+
+        for (variant, kind) in fields.variants.iter().zip(kinds) {
+            insert_item(conn, item::NewItem::new(variant, product_id, *kind), actor)?;
+        }
+
+        set_tags(conn, product_id, &fields.tags)?;
+
+        Ok(product_id)
     })
-    .await?
-    .map_err(error::ErrorInternalServerError)
 }
 
-pub async fn get_total(cart: Arc<model::CartMap>, pool: Arc<DbPool>) -> Result<u32> {
-    let id_kind_pairs = {
-        let cart = cart.clone();
-        web::block(move || -> std::result::Result<Vec<(i32, i32)>, String> {
+#[get("/stock/{product_id}")]
+pub async fn get_product(
+    req: actix_web::HttpRequest,
+    product_id: web::Path<u32>,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<product::Product>> {
+    let product_id = product_id.into_inner();
+
+    let mut products = {
+        let pool = pool.clone();
+        request_id::block(move || {
             let mut conn = pool
                 .get()
-                .map_err(|e| format!("Cannot get DB connection from pool: {e}"))?;
-
-            let ids = cart.keys().copied().map(|n| n as i32).collect::<Vec<i32>>();
-            stock::table
-                .select((stock::id, stock::kind))
-                .filter(stock::id.eq_any(ids))
-                .load::<(i32, i32)>(&mut conn)
-                .map_err(|e| format!("Cannot get id-kind pairs from DB: {e}"))
+                .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+            load_products(
+                &mut conn,
+                Some(vec![product_id as i32]),
+                &product::StockFilter::default(),
+            )
         })
+        .await?
+        .map_err(error::ErrorInternalServerError)?
+    };
+
+    let product = products
+        .remove(&product_id)
+        .ok_or_else(|| error::ErrorNotFound(format!("No product with ID {product_id}")))?;
+    if let Some(session) = funnel::session(&req) {
+        funnel::record_view(&pool, &session, Stage::ItemView, Some(product_id)).await;
     }
-    .await?
-    .map_err(|e| error::ErrorInternalServerError(format!("<fn GET_TOTAL>\n{e}")))?;
-
-    let id_kind_map = HashMap::<i32, i32>::from_iter(id_kind_pairs.into_iter());
-    cart.iter().try_fold(0, |total, (id, qty)| {
-        if let Some(kind) = id_kind_map.get(&(*id as i32)) {
-            let price = match item::Kind::from(*kind) {
-                item::Kind::BigPrint => 20_00,
-                item::Kind::SmallPrint => 7_00,
-                item::Kind::Button => 3_00,
-            };
 
-            Ok(total + price * qty)
-        } else {
-            Err(error::ErrorInternalServerError(
-                "<api::stock::get_total>\nError fetching total - Item in cart not present in kind map",
-            ))
-        }
-    })
+    Ok(web::Json(product))
 }
 
-#[get("/stock/{item_id}")]
-pub async fn get_item(
-    item_id: web::Path<u32>,
-    pool: web::Data<DbPool>,
-) -> Result<web::Json<item::Item>> {
-    let item_id = item_id.into_inner();
-    let item = item_from_db(item_id, &pool).await?;
+/// Version of the catalogue and when it last changed, bumped by triggers on
+/// every table the stock listing reads from. Drop windows opening or closing
+/// by `now` count as changes too, as they change what's on sale
+fn catalogue_version(
+    conn: &mut SqliteConnection,
+    now: chrono::NaiveDateTime,
+) -> QueryResult<(i32, chrono::NaiveDateTime)> {
+    let (version, modified) = catalogue_version::table
+        .select((catalogue_version::version, catalogue_version::modified))
+        .first::<(i32, chrono::NaiveDateTime)>(conn)?;
+
+    let opened = stock::table
+        .select(diesel::dsl::max(stock::available_from))
+        .filter(stock::available_from.le(now))
+        .first::<Option<chrono::NaiveDateTime>>(conn)?;
+    let closed = stock::table
+        .select(diesel::dsl::max(stock::available_until))
+        .filter(stock::available_until.le(now))
+        .first::<Option<chrono::NaiveDateTime>>(conn)?;
+
+    Ok((
+        version,
+        modified.max(opened.max(closed).unwrap_or(modified)),
+    ))
+}
 
-    Ok(web::Json(item))
+/// Loads one page of the products matching `filter`, in the order given by `listing`
+pub fn load_stock_page(
+    conn: &mut SqliteConnection,
+    filter: &product::StockFilter,
+    listing: &product::StockListing,
+) -> Result<product::StockPage, String> {
+    // Variants without their own price use their kind's, archived variants
+    // are left out even when listed
+    const CHEAPEST_VARIANT: &str = "(select min(coalesce(stock.price, kinds.price)) \
+        from stock join kinds on kinds.id = stock.kind \
+        where stock.product_id = products.id and stock.archived is null)";
+    const TOTAL_QUANTITY: &str = "(select sum(stock.quantity) from stock \
+        where stock.product_id = products.id and stock.archived is null)";
+
+    let total = products::table
+        .filter(products::id.eq_any(matching_product_ids(filter)))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(|e| format!("Cannot count products: {e}"))?;
+
+    let query = matching_product_ids(filter);
+    // Products are never renumbered, so higher IDs are newer. Every order ends
+    // with the ID to keep pages stable
+    let query = match listing.sort {
+        product::StockSort::Title => query.order((products::title.asc(), products::id.asc())),
+        product::StockSort::Price => query.order((
+            sql::<Nullable<Integer>>(CHEAPEST_VARIANT).asc(),
+            products::id.asc(),
+        )),
+        product::StockSort::PriceDesc => query.order((
+            sql::<Nullable<Integer>>(CHEAPEST_VARIANT).desc(),
+            products::id.asc(),
+        )),
+        product::StockSort::Newest => query.order(products::id.desc()),
+        product::StockSort::Quantity => query.order((
+            sql::<Nullable<Integer>>(TOTAL_QUANTITY).desc(),
+            products::id.asc(),
+        )),
+    };
+    let ids = query
+        .limit(listing.limit() as i64)
+        .offset(listing.offset() as i64)
+        .get_results::<i32>(conn)
+        .map_err(|e| format!("Cannot fetch products: {e}"))?;
+
+    let mut products = load_products(conn, Some(ids.clone()), filter)?;
+
+    Ok(product::StockPage {
+        products: ids
+            .into_iter()
+            .filter_map(|id| {
+                let id = id as product::ProductId;
+                products
+                    .remove(&id)
+                    .map(|product| product::ListedProduct { id, product })
+            })
+            .collect(),
+        total: total as u32,
+        page: listing.page(),
+        limit: listing.limit(),
+    })
 }
 
+/// Lists a page of products, optionally filtered by category, tag, variant kind
+/// and availability. Responses carry an ETag derived from the catalogue's
+/// version, so unchanged stock can be revalidated without being reloaded
 #[get("/stock")]
 pub async fn get_stock(
     req: actix_web::HttpRequest,
+    filter: web::Query<product::StockFilter>,
+    listing: web::Query<product::StockListing>,
     pool: web::Data<DbPool>,
 ) -> Result<HttpResponse> {
-    if let Ok(metrics_conn) = pool.get() {
-        actix_web::rt::spawn(log_user(req, metrics_conn));
-    }
+    let filter = filter.into_inner();
+    let listing = listing.into_inner();
 
-    let stock = {
-        let mut stock_conn = pool
-            .get()
-            .map_err(|e| error::ErrorInternalServerError(format!("Cannot connect to DB: {e}")))?;
-
-        web::block(move || {
-            stock::table
-                .select(item::TableItem::as_select())
-                .get_results::<item::TableItem>(&mut stock_conn)
-                .map_err(|e| format!("Cannot fetch stock: {e}"))
+    let (version, modified) = {
+        let pool = pool.clone();
+        request_id::block(move || {
+            let mut conn = pool
+                .get()
+                .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+            catalogue_version(&mut conn, chrono::Utc::now().naive_utc())
+                .map_err(|e| format!("Cannot fetch stock version: {e}"))
         })
         .await?
         .map_err(error::ErrorInternalServerError)?
+    };
+    let etag = EntityTag::new_strong(format!("{version}-{}", modified.and_utc().timestamp()));
+    let last_modified = HttpDate::from(std::time::SystemTime::from(modified.and_utc()));
+
+    // Later pages aren't new visits. Revalidations are, the listing is always
+    // revalidated so returning visitors are never sent it in full
+    if listing.page() == 1 {
+        if let Some(session) = funnel::session(&req) {
+            funnel::record_view(&pool, &session, Stage::CatalogueView, None).await;
+        }
+        if let Ok(metrics_conn) = pool.get() {
+            request_id::spawn(log_user(req.clone(), metrics_conn));
+        }
     }
-    .into_iter()
-    .map(|item @ item::TableItem { id, .. }| (id as u32, item::Item::from(item)))
-    .collect::<HashMap<u32, item::Item>>();
+
+    let unchanged = match req.get_header::<header::IfNoneMatch>() {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if unchanged {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .insert_header(header::LastModified(last_modified))
+            .finish());
+    }
+
+    let page = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        load_stock_page(&mut conn, &filter, &listing)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
 
     Ok(HttpResponse::Ok()
-        .content_type("application/json")
-        .json(stock))
+        .insert_header(header::ETag(etag))
+        .insert_header(header::LastModified(last_modified))
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .json(page))
+}
+
+/// Ranked full-text search over product titles, descriptions and tags. Must be
+/// registered before `get_product`, which would otherwise match "search" as an ID
+#[get("/stock/search")]
+pub async fn search_stock(
+    query: web::Query<search::SearchQuery>,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<search::SearchResults>> {
+    use diesel::sql_types::{Integer, Text};
+
+    let query = query.into_inner();
+    let expression = query
+        .match_expression()
+        .ok_or_else(|| error::ErrorBadRequest("Search query is empty"))?;
+    let (page, per_page, offset) = (query.page(), query.limit(), query.offset());
+
+    // Like the stock listing, products whose variants are all archived are
+    // left out, products without any variants yet are kept
+    const LISTED: &str = "(exists (select 1 from stock \
+        where stock.product_id = product_search.rowid and stock.archived is null) \
+        or not exists (select 1 from stock where stock.product_id = product_search.rowid))";
+
+    let (hits, total) = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        // Title matches weigh the most, then tags, then the description
+        let table_hits = diesel::sql_query(format!(
+            "select rowid as id, \
+             highlight(product_search, 0, '{open}', '{close}') as title, \
+             snippet(product_search, 1, '{open}', '{close}', '…', 16) as snippet, \
+             bm25(product_search, 10.0, 1.0, 5.0) as rank \
+             from product_search where product_search match ? and {LISTED} \
+             order by rank limit ? offset ?",
+            open = search::HIGHLIGHT_OPEN,
+            close = search::HIGHLIGHT_CLOSE,
+        ))
+        .bind::<Text, _>(&expression)
+        .bind::<Integer, _>(per_page as i32)
+        .bind::<Integer, _>(offset as i32)
+        .load::<search::TableSearchHit>(&mut conn)
+        .map_err(|e| format!("Cannot search stock: {e}"))?;
+
+        let total = diesel::sql_query(format!(
+            "select count(*) as total from product_search \
+             where product_search match ? and {LISTED}"
+        ))
+        .bind::<Text, _>(&expression)
+        .get_result::<search::SearchCount>(&mut conn)
+        .map_err(|e| format!("Cannot count search results: {e}"))?
+        .total;
+
+        let mut products = load_products(
+            &mut conn,
+            Some(table_hits.iter().map(|hit| hit.id).collect()),
+            &product::StockFilter::default(),
+        )?;
+
+        let hits = table_hits
+            .into_iter()
+            .filter_map(
+                |search::TableSearchHit {
+                     id, title, snippet, ..
+                 }| {
+                    let id = id as product::ProductId;
+                    products.remove(&id).map(|product| search::SearchHit {
+                        id,
+                        title,
+                        snippet,
+                        product,
+                    })
+                },
+            )
+            .collect::<Vec<search::SearchHit>>();
+
+        Ok::<_, String>((hits, total as u32))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(web::Json(search::SearchResults {
+        hits,
+        total,
+        page,
+        per_page,
+    }))
 }
 
 #[put("/stock")]
-pub async fn put_item(
+pub async fn put_product(
     pool: web::Data<DbPool>,
-    item: web::Json<item::Item>,
+    product: web::Json<product::ProductFields>,
 ) -> Result<HttpResponse> {
-    let item = item.into_inner();
+    let product = product.into_inner();
+
+    let mut kinds = Vec::with_capacity(product.variants.len());
+    for variant in &product.variants {
+        variant.validate().map_err(error::ErrorBadRequest)?;
+        kinds.push(kind_id(variant.kind.clone(), &pool).await?);
+    }
+    let category = category_id(product.category.clone(), &pool).await?;
 
-    web::block(move || {
-        let item = item::NewItem::from(&item);
+    let product_id = request_id::block(move || {
         let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
-        match diesel::insert_into(stock::table)
-            .values(item)
-            .execute(&mut conn)
-        {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Cannot insert item into stock table"),
-        }
+        insert_product(&mut conn, &product, &kinds, category, None)
+            .map_err(|_| "Cannot insert product into DB, are its title and SKUs unique?")
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(product_id))
+}
+
+/// Updates a product's title, description, category and tags, variants are
+/// edited through their own endpoints and are ignored here
+#[put("/stock/{product_id}")]
+pub async fn update_product(
+    product_id: web::Path<i32>,
+    new_fields: web::Json<product::ProductFields>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let product_id = product_id.into_inner();
+    let new_fields = new_fields.into_inner();
+    let category = category_id(new_fields.category.clone(), &pool).await?;
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        conn.transaction(|conn| {
+            diesel::update(products::table.filter(products::id.eq(product_id)))
+                .set(product::NewProduct::new(&new_fields, category))
+                .execute(conn)?;
+            set_tags(conn, product_id, &new_fields.tags)
+        })
+        .map_err(|_| format!("Cannot update product {product_id}"))
     })
     .await?
     .map_err(error::ErrorInternalServerError)?;
@@ -130,38 +545,179 @@ pub async fn put_item(
     Ok(HttpResponse::Ok().finish())
 }
 
-#[put("/stock/{item_id}")]
+#[delete("/stock")]
+pub async fn delete_products(
+    pool: web::Data<DbPool>,
+    env: web::Data<Env>,
+    product_ids: web::Json<Vec<i32>>,
+) -> Result<HttpResponse> {
+    let product_ids = product_ids.into_inner();
+
+    let image_keys = request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        conn.transaction(|conn| {
+            let item_ids = stock::table
+                .filter(stock::product_id.eq_any(&product_ids))
+                .select(stock::id)
+                .get_results::<i32>(conn)?;
+            let ordered = ordered_items(conn, &item_ids)?;
+            if !ordered.is_empty() {
+                return Ok(Err(ordered));
+            }
+
+            let image_keys =
+                diesel::delete(images::table.filter(images::product_id.eq_any(&product_ids)))
+                    .returning(images::key)
+                    .get_results::<String>(conn)?;
+            diesel::delete(stock::table.filter(stock::product_id.eq_any(&product_ids)))
+                .execute(conn)?;
+            diesel::delete(
+                product_tags::table.filter(product_tags::product_id.eq_any(&product_ids)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                collection_products::table
+                    .filter(collection_products::product_id.eq_any(&product_ids)),
+            )
+            .execute(conn)?;
+            diesel::delete(products::table.filter(products::id.eq_any(&product_ids)))
+                .execute(conn)?;
+            Ok::<_, diesel::result::Error>(Ok(image_keys))
+        })
+        .map_err(|_| "Cannot delete products from DB")
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .map_err(|ordered| {
+        error::ErrorConflict(format!(
+            "Variants {ordered:?} have been ordered, archive them instead"
+        ))
+    })?;
+
+    remove_image_files(&image_keys, std::path::Path::new(env.image_dir));
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[put("/stock/{product_id}/variants")]
+pub async fn put_item(
+    product_id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+    item: web::Json<item::ItemFields>,
+) -> Result<HttpResponse> {
+    let product_id = product_id.into_inner();
+    let item = item.into_inner();
+    item.validate().map_err(error::ErrorBadRequest)?;
+    let kind = kind_id(item.kind.clone(), &pool).await?;
+
+    let item_id = request_id::block(move || {
+        let item = item::NewItem::new(&item, product_id, kind);
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        insert_item(&mut conn, item, None)
+            .map_err(|_| "Cannot insert item into stock table, does the product exist?")
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(item_id))
+}
+
+/// Updates a variant, a changed quantity is recorded as a correction. Restocking
+/// a sold out variant emails everyone waiting on it, and taking it down to its
+/// low stock threshold alerts the admins
+#[put("/variants/{item_id}")]
 pub async fn update_item(
     item_id: web::Path<i32>,
-    new_fields: web::Json<item::Item>,
+    new_fields: web::Json<item::ItemFields>,
     pool: web::Data<DbPool>,
+    mailer: web::Data<Mailer>,
 ) -> Result<HttpResponse> {
     use model::schema::stock::id;
 
     let item_id = item_id.into_inner();
 
     let new_fields = new_fields.into_inner();
-    web::block(move || {
-        let new_item = item::NewItem::from(&new_fields);
+    new_fields.validate().map_err(error::ErrorBadRequest)?;
+    let kind = kind_id(new_fields.kind.clone(), &pool).await?;
 
-        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
-        match diesel::update(stock::dsl::stock)
-            .filter(id.eq(item_id))
-            .set(new_item)
-            .execute(&mut conn)
-            .map(|_| ())
-        {
-            Ok(_) => Ok(()),
-            Err(_) => Err(format!("Cannot update item {item_id}")),
-        }
+    let db_pool = pool.clone();
+    let (restocked, low_stock) = request_id::block(move || {
+        let mut conn = db_pool
+            .get()
+            .map_err(|_| "Cannot connect to DB".to_string())?;
+
+        // Read within the transaction, so a sale can't land between reading
+        // the quantity and correcting it
+        conn.transaction(|conn| {
+            let (product_id, quantity) = stock::table
+                .select((stock::product_id, stock::quantity))
+                .filter(id.eq(item_id))
+                .first::<(i32, i32)>(conn)?;
+            let new_item = item::NewItem::new(&new_fields, product_id, kind);
+            let delta = new_item.quantity - quantity;
+
+            diesel::update(stock::dsl::stock)
+                .filter(id.eq(item_id))
+                .set(new_item.changes)
+                .execute(conn)?;
+            record_price(conn, item_id, None)?;
+
+            let after = if delta != 0 {
+                record_movement(
+                    conn,
+                    &inventory::NewMovement::new(item_id, delta, inventory::Reason::Correction),
+                )?
+            } else {
+                quantity
+            };
+
+            Ok::<_, diesel::result::Error>((
+                item::is_back_in_stock(quantity, after),
+                low_stock(conn, item_id, quantity, after)?,
+            ))
+        })
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => format!("Item {item_id} does not exist"),
+            _ => format!("Cannot update item {item_id}"),
+        })
     })
     .await?
     .map_err(error::ErrorInternalServerError)?;
 
+    if let Some(low_stock) = low_stock {
+        request_id::spawn(alert_low_stock(
+            format!("An edit to item {item_id}"),
+            vec![low_stock],
+            mailer.clone().into_inner(),
+        ));
+    }
+    if restocked {
+        request_id::spawn(notify_back_in_stock(
+            vec![item_id],
+            pool.into_inner(),
+            mailer.into_inner(),
+        ));
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
 
-#[delete("/stock")]
+/// Which of `item_ids` are referenced by any order. Check within the same
+/// transaction as the delete, so an order can't slip in between. Lines left
+/// behind by orders cancelled before lines were deleted with them don't count
+fn ordered_items(conn: &mut SqliteConnection, item_ids: &[i32]) -> QueryResult<Vec<i32>> {
+    carts::table
+        .inner_join(orders::table)
+        .filter(carts::item_id.eq_any(item_ids))
+        .select(carts::item_id)
+        .distinct()
+        .order(carts::item_id)
+        .get_results::<i32>(conn)
+}
+
+/// Permanently removes items, refused for any item that has been ordered since
+/// order history needs it. Those should be archived instead
+#[delete("/variants")]
 pub async fn delete_items(
     pool: web::Data<DbPool>,
     item_ids: web::Json<Vec<i32>>,
@@ -169,33 +725,133 @@ pub async fn delete_items(
     use model::schema::stock::id;
     let item_ids = item_ids.into_inner();
 
-    web::block(move || {
+    request_id::block(move || {
         let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
-        match diesel::delete(stock::table.filter(id.eq_any(item_ids))).execute(&mut conn) {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Cannot delete items from DB"),
-        }
+        conn.transaction(|conn| {
+            let ordered = ordered_items(conn, &item_ids)?;
+            if !ordered.is_empty() {
+                return Ok(Err(ordered));
+            }
+
+            diesel::delete(stock::table.filter(id.eq_any(&item_ids))).execute(conn)?;
+            Ok::<_, diesel::result::Error>(Ok(()))
+        })
+        .map_err(|_| "Cannot delete items from DB")
     })
     .await?
-    .map_err(error::ErrorInternalServerError)?;
+    .map_err(error::ErrorInternalServerError)?
+    .map_err(|ordered| {
+        error::ErrorConflict(format!(
+            "Variants {ordered:?} have been ordered, archive them instead"
+        ))
+    })?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-// TODO: refactor for more effiicient, single-query batch updating
+/// Sets or clears the archived time of `item_ids`, archiving an item that is
+/// already archived keeps its original time
+fn set_archived(pool: &DbPool, item_ids: Vec<i32>, archive: bool) -> Result<usize, String> {
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+    let items = stock::table.filter(stock::id.eq_any(item_ids));
+
+    if archive {
+        diesel::update(items.filter(stock::archived.is_null()))
+            .set(stock::archived.eq(diesel::dsl::now))
+            .execute(&mut conn)
+    } else {
+        diesel::update(items)
+            .set(stock::archived.eq(None::<chrono::NaiveDateTime>))
+            .execute(&mut conn)
+    }
+    .map_err(|e| format!("Cannot update items: {e}"))
+}
+
+/// Hides items from the storefront and checkout, they stay in the stock table
+/// for order history
+#[post("/variants/archive")]
+pub async fn archive_items(
+    pool: web::Data<DbPool>,
+    item_ids: web::Json<Vec<i32>>,
+) -> Result<HttpResponse> {
+    let item_ids = item_ids.into_inner();
+
+    request_id::block(move || set_archived(&pool, item_ids, true))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[post("/variants/unarchive")]
+pub async fn unarchive_items(
+    pool: web::Data<DbPool>,
+    item_ids: web::Json<Vec<i32>>,
+) -> Result<HttpResponse> {
+    let item_ids = item_ids.into_inner();
+
+    request_id::block(move || set_archived(&pool, item_ids, false))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Records the sale of every item in `order_id`'s cart, and which of its lines
+/// were sold beyond the units on hand. Returns the items the sale took down to
+/// their low stock threshold
 pub async fn dec_items(
     cart: Arc<HashMap<model::ItemId, stripe::Item>>,
+    order_id: i32,
     mut conn: r2d2::PooledConnection<ConnectionManager<SqliteConnection>>,
-) -> Result<()> {
-    web::block(move || {
-        conn.transaction(|conn| -> diesel::QueryResult<()> {
+) -> Result<Vec<inventory::LowStock>> {
+    request_id::block(move || {
+        conn.transaction(|conn| -> diesel::QueryResult<Vec<inventory::LowStock>> {
+            let mut low_stock = Vec::new();
+
             for (item_id, stripe::Item { quantity, .. }) in cart.iter() {
-                diesel::update(stock::table.filter(stock::id.eq(*item_id as i32)))
-                    .set(stock::quantity.eq(stock::quantity - *quantity as i32))
+                let (before, ships_on) = stock::table
+                    .filter(stock::id.eq(*item_id as i32))
+                    .select((stock::quantity, stock::ships_on))
+                    .first::<(i32, Option<chrono::NaiveDate>)>(conn)?;
+
+                let after = record_movement(
+                    conn,
+                    &inventory::NewMovement {
+                        order_id: Some(order_id),
+                        ..inventory::NewMovement::new(
+                            *item_id as i32,
+                            -(*quantity as i32),
+                            inventory::Reason::Sale,
+                        )
+                    },
+                )?;
+
+                let awaiting_stock = (*quantity as i32 - before.max(0)).max(0);
+                if awaiting_stock > 0 {
+                    diesel::update(
+                        carts::table
+                            .filter(carts::order_id.eq(order_id))
+                            .filter(carts::item_id.eq(*item_id as i32)),
+                    )
+                    .set((
+                        carts::awaiting_stock.eq(awaiting_stock),
+                        carts::ships_on.eq(ships_on),
+                    ))
                     .execute(conn)?;
+                }
+
+                low_stock.extend(super::inventory::low_stock(
+                    conn,
+                    *item_id as i32,
+                    before,
+                    after,
+                )?);
             }
 
-            Ok(())
+            Ok(low_stock)
         })
         .map_err(|e| format!("Error updating stock quantities: {e}"))
     })
@@ -207,40 +863,53 @@ pub async fn get_title_map(
     cart: Arc<model::CartMap>,
     pool: DbPool,
 ) -> Result<HashMap<model::ItemId, String>> {
-    web::block(move || -> Result<HashMap<model::ItemId, String>, String> {
+    request_id::block(move || -> Result<HashMap<model::ItemId, String>, String> {
         let ids = cart.keys().map(|n| *n as i32).collect::<Vec<i32>>();
 
         let mut conn = pool
             .get()
             .map_err(|e| format!("Cannot get DB connection: {e}"))?;
 
-        let id_title_pairs: Vec<(i32, String)> = stock::table
-            .select((stock::id, stock::title))
+        let id_title_pairs: Vec<(i32, String, String)> = stock::table
+            .inner_join(products::table)
+            .select((stock::id, products::title, stock::options))
             .filter(stock::id.eq_any(ids))
-            .load::<(i32, String)>(&mut conn)
+            .load::<(i32, String, String)>(&mut conn)
             .map_err(|e| format!("Cannot fetch titles from DB: {e}"))?;
 
-        let id_title_map = id_title_pairs
+        id_title_pairs
             .into_iter()
-            .map(|(id, title)| (id as u32, title))
-            .collect::<HashMap<model::ItemId, String>>();
-
-        Ok(id_title_map)
+            .map(|(id, title, options)| {
+                let options = item::parse_options(&options)?;
+                Ok((id as u32, item::display_title(&title, &options)))
+            })
+            .collect::<Result<HashMap<model::ItemId, String>, String>>()
     })
     .await?
     .map_err(error::ErrorInternalServerError)
 }
 
-pub async fn get_matching_ids(ids: Vec<u32>, pool: Arc<DbPool>) -> Result<Vec<item::TableItem>> {
-    web::block(move || {
+pub async fn get_matching_ids(ids: Vec<u32>, pool: Arc<DbPool>) -> Result<Vec<item::PricedItem>> {
+    request_id::block(move || {
         let mut conn = pool
             .get()
             .map_err(|e| format!("Cannot connect to DB: {e}"))?;
         stock::table
-            .select(item::TableItem::as_select())
+            .inner_join(products::table)
+            .inner_join(kinds::table)
+            .select((
+                item::TableItem::as_select(),
+                product::TableProduct::as_select(),
+                kind::TableKind::as_select(),
+            ))
             .filter(stock::id.eq_any(ids.iter().map(|n| *n as i32)))
-            .get_results(&mut conn)
-            .map_err(|e| format!("Cannot fetch items from DB: {e}"))
+            // Archived items can't be bought, so they're quoted as not found
+            .filter(stock::archived.is_null())
+            .get_results::<(item::TableItem, product::TableProduct, kind::TableKind)>(&mut conn)
+            .map_err(|e| format!("Cannot fetch items from DB: {e}"))?
+            .into_iter()
+            .map(item::PricedItem::try_from)
+            .collect::<Result<Vec<item::PricedItem>, String>>()
     })
     .await?
     .map_err(error::ErrorInternalServerError)
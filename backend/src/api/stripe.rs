@@ -2,39 +2,53 @@ use serde::{Deserialize, Serialize};
 use std::{borrow::Borrow, collections::HashMap, sync::Arc};
 
 use actix_web::{
-    error, post, rt,
+    error, post,
     web::{self, Json},
     HttpRequest, HttpResponse, Result,
 };
 
 use stripe::{
-    Client, CreatePaymentLink, CreatePaymentLinkAfterCompletion,
-    CreatePaymentLinkAfterCompletionRedirect, CreatePaymentLinkLineItems,
-    CreatePaymentLinkShippingAddressCollection,
-    CreatePaymentLinkShippingAddressCollectionAllowedCountries, CreatePaymentLinkShippingOptions,
-    CreatePrice, CreateProduct, CreateShippingRate, CreateShippingRateDeliveryEstimate,
-    CreateShippingRateDeliveryEstimateMaximum, CreateShippingRateDeliveryEstimateMaximumUnit,
-    CreateShippingRateDeliveryEstimateMinimum, CreateShippingRateFixedAmount, Currency,
-    EventObject, EventType, PaymentLink, Price, Product, Shipping, ShippingRate,
-    ShippingRateTaxBehavior, ShippingRateType, Webhook,
+    CheckoutSession, CheckoutSessionCustomerCreation, CheckoutSessionMode, Client,
+    CreateCheckoutSession, CreateCheckoutSessionLineItems,
+    CreateCheckoutSessionShippingAddressCollection,
+    CreateCheckoutSessionShippingAddressCollectionAllowedCountries,
+    CreateCheckoutSessionShippingOptions, CreatePrice, CreateProduct, CreateShippingRate,
+    CreateShippingRateDeliveryEstimate, CreateShippingRateDeliveryEstimateMaximum,
+    CreateShippingRateDeliveryEstimateMaximumUnit, CreateShippingRateDeliveryEstimateMinimum,
+    CreateShippingRateFixedAmount, Currency, EventObject, EventType, Price, Product, Shipping,
+    ShippingRate, ShippingRateTaxBehavior, ShippingRateType, Webhook,
 };
 
 use crate::{
-    api::{order::insert_order, stock::dec_items},
+    api::{
+        order::{flag_order, insert_order, record_refund},
+        stock::dec_items,
+    },
+    funnel,
+    logging::Redacted,
     mail,
-    utils::print_red,
-    DbPool, Mailer, ENV,
+    monitoring::{WebhookOutcome, METRICS},
+    request_id, DbPool, Mailer, ENV,
 };
 
-use model::{address::Address, ItemId, Quantity};
+use model::{
+    address::Address,
+    funnel::Stage,
+    order::OrderPayment,
+    quote::{CheckoutFields, CheckoutRequest, Warning},
+    CartMap, ItemId, Quantity,
+};
 
-use super::stock::get_matching_ids;
+use super::cart::{customer_limit_warnings, get_quote};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Item {
     pub title: String,
     pub price: u32,
     pub quantity: Quantity,
+    /// Set when some of the line is pre-ordered or backordered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ships_on: Option<chrono::NaiveDate>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -47,22 +61,54 @@ pub struct User {
     pub cart: HashMap<ItemId, Item>,
 }
 
+/// How long customers have to pay once they've started checking out, Stripe
+/// allows between 30 minutes and a day. Drop windows and per customer limits are
+/// checked when checkout starts, so this bounds how stale those checks get
+const CHECKOUT_EXPIRY: chrono::Duration = chrono::Duration::hours(1);
+
+/// Counts a Stripe call made while creating a checkout session failing, for
+/// `/metrics`
+fn link_failed(e: stripe::StripeError) -> actix_web::Error {
+    METRICS.checkout_link_failed();
+    error::ErrorInternalServerError(e.to_string())
+}
+
 #[post("/checkout")]
 pub async fn checkout(
-    cart: Json<HashMap<ItemId, Quantity>>,
+    req: HttpRequest,
+    request: Json<CheckoutRequest>,
     pool: web::Data<DbPool>,
 ) -> Result<HttpResponse> {
-    let ids = cart.keys().copied().collect::<Vec<ItemId>>();
-    let items = get_matching_ids(ids, pool.into_inner()).await?;
-    let item_map = items
-        .into_iter()
-        .map(|item| {
+    let CheckoutFields { cart, email } = request.into_inner().into();
+    let pool = pool.into_inner();
+
+    let mut quote = get_quote(&cart, pool.clone()).await?;
+    quote
+        .warnings
+        .extend(customer_limit_warnings(&cart, email.clone(), pool.clone()).await?);
+    if !quote.is_orderable() {
+        return Err(error::ErrorBadRequest(serde_json::to_string(
+            &quote.warnings,
+        )?));
+    }
+
+    let funnel_session = funnel::session(&req);
+    if let Some(session) = &funnel_session {
+        let items = cart.keys().copied().collect();
+        funnel::record_items(&pool, session, Stage::CheckoutStarted, items, None).await;
+    }
+
+    let item_map = quote
+        .lines
+        .iter()
+        .map(|line| {
             (
-                item.id as u32,
+                line.item,
                 Item {
-                    title: item.title.clone(),
-                    price: item.price(),
-                    quantity: *cart.get(&(item.id as u32)).unwrap(),
+                    title: line.title.clone(),
+                    price: line.unit_price,
+                    quantity: line.quantity,
+                    ships_on: line.ships_on,
                 },
             )
         })
@@ -76,12 +122,13 @@ pub async fn checkout(
         title,
         price,
         quantity,
+        ..
     } in item_map.values()
     {
         let create_product = CreateProduct::new(title);
         let product = Product::create(&client, create_product)
             .await
-            .map_err(|e| error::ErrorInternalServerError(e.to_string()))?;
+            .map_err(link_failed)?;
 
         let mut create_price = CreatePrice::new(Currency::USD);
         create_price.product = Some(stripe::IdOrCreate::Id(&product.id));
@@ -90,27 +137,28 @@ pub async fn checkout(
 
         let price = Price::create(&client, create_price)
             .await
-            .map_err(|e| error::ErrorInternalServerError(e.to_string()))?;
+            .map_err(link_failed)?;
 
         product_price_pairs.push((price, u64::from(*quantity)));
     }
 
     let shipping = {
+        let option = quote.shipping();
         let rate = CreateShippingRate {
             metadata: None,
             delivery_estimate: Some(CreateShippingRateDeliveryEstimate {
                 maximum: Some(CreateShippingRateDeliveryEstimateMaximum {
                     unit: CreateShippingRateDeliveryEstimateMaximumUnit::Week,
-                    value: 2,
+                    value: option.max_weeks as i64,
                 }),
                 minimum: Some(CreateShippingRateDeliveryEstimateMinimum {
                     unit: stripe::CreateShippingRateDeliveryEstimateMinimumUnit::BusinessDay,
-                    value: 5,
+                    value: option.min_business_days as i64,
                 }),
             }),
-            display_name: "priority",
+            display_name: &option.name,
             fixed_amount: Some(CreateShippingRateFixedAmount {
-                amount: 1000,
+                amount: option.amount as i64,
                 currency: Currency::USD,
                 ..Default::default()
             }),
@@ -122,53 +170,63 @@ pub async fn checkout(
         ShippingRate::create(&client, rate)
     }
     .await
-    .map_err(|e| error::ErrorInternalServerError(e.to_string()))?;
-
-    let payment_link = {
-        let mut create_payment_link = CreatePaymentLink::new(
+    .map_err(link_failed)?;
+
+    // A session rather than a payment link, as those can be paid for over and
+    // over with any email
+    let session = {
+        let mut create_session = CreateCheckoutSession::new();
+        create_session.mode = Some(CheckoutSessionMode::Payment);
+        create_session.line_items = Some(
             product_price_pairs
                 .iter()
-                .map(|(price, qty)| CreatePaymentLinkLineItems {
-                    quantity: *qty,
-                    price: price.id.to_string(),
-                    adjustable_quantity: None,
+                .map(|(price, qty)| CreateCheckoutSessionLineItems {
+                    quantity: Some(*qty),
+                    price: Some(price.id.to_string()),
+                    ..Default::default()
                 })
                 .collect::<Vec<_>>(),
         );
-        create_payment_link.metadata = Some(
-            item_map
-                .iter()
-                .map(|(id, item)| Ok((id.to_string(), serde_json::to_string(item)?)))
-                .collect::<Result<HashMap<String, String>, serde_json::error::Error>>()?,
-        );
+        let mut metadata = item_map
+            .iter()
+            .map(|(id, item)| Ok((id.to_string(), serde_json::to_string(item)?)))
+            .collect::<Result<HashMap<String, String>, serde_json::error::Error>>()?;
+        // Links the purchase back to the session that started checking out
+        if let Some(session) = funnel_session {
+            metadata.insert(funnel::METADATA_KEY.to_string(), session);
+        }
+        create_session.metadata = Some(metadata);
 
-        create_payment_link.shipping_options = Some(vec![CreatePaymentLinkShippingOptions {
+        create_session.shipping_options = Some(vec![CreateCheckoutSessionShippingOptions {
             shipping_rate: Some(shipping.id.to_string()),
+            ..Default::default()
         }]);
 
-        create_payment_link.after_completion = Some(CreatePaymentLinkAfterCompletion {
-            type_: stripe::CreatePaymentLinkAfterCompletionType::Redirect,
-            redirect: Some(CreatePaymentLinkAfterCompletionRedirect {
-                url: ENV.completion_redirect.to_string(),
-            }),
-            hosted_confirmation: None,
-        });
+        create_session.success_url = Some(ENV.completion_redirect);
+        create_session.expires_at = Some((chrono::Utc::now() + CHECKOUT_EXPIRY).timestamp());
 
-        create_payment_link.shipping_address_collection =
-            Some(CreatePaymentLinkShippingAddressCollection {
+        create_session.shipping_address_collection =
+            Some(CreateCheckoutSessionShippingAddressCollection {
                 allowed_countries: vec![
-                    CreatePaymentLinkShippingAddressCollectionAllowedCountries::Us,
+                    CreateCheckoutSessionShippingAddressCollectionAllowedCountries::Us,
                 ],
             });
 
-        create_payment_link.customer_creation = Some(stripe::PaymentLinkCustomerCreation::Always);
+        // The customer pays with the email their limits were checked against
+        create_session.customer_email = email.as_deref();
+        create_session.customer_creation = Some(CheckoutSessionCustomerCreation::Always);
 
-        PaymentLink::create(&client, create_payment_link)
+        CheckoutSession::create(&client, create_session)
     }
     .await
-    .map_err(|e| error::ErrorInternalServerError(e.to_string()))?;
+    .map_err(link_failed)?;
 
-    Ok(HttpResponse::Ok().body(payment_link.url))
+    let url = session.url.ok_or_else(|| {
+        METRICS.checkout_link_failed();
+        error::ErrorInternalServerError("Stripe checkout session has no URL")
+    })?;
+
+    Ok(HttpResponse::Ok().body(url))
 }
 
 /// Receives (all) webhooks
@@ -184,15 +242,14 @@ pub async fn webhook(
         .map(|_| HttpResponse::Ok().finish())
 }
 
-/// Determines whether webhook is correct type: is a completed checkout session
+/// Determines whether webhook is correct type: a completed checkout session or
+/// a refund
 pub async fn parse_webhook(
     req: HttpRequest,
     payload: web::Bytes,
     pool: Arc<DbPool>,
     mailer: web::Data<Mailer>,
 ) -> Result<()> {
-    print_red("", "CURRENTLY IN 'handle_webhook'");
-
     let payload_str = std::str::from_utf8(payload.borrow()).map_err(|e| {
         error::ErrorInternalServerError(format!("Stripe payload is not UTF-8: {e}"))
     })?;
@@ -201,20 +258,38 @@ pub async fn parse_webhook(
 
     let event = Webhook::construct_event(payload_str, stripe_sig, ENV.stripe_key);
 
-    if let Ok(event) = event {
-        if let EventType::CheckoutSessionCompleted = event.type_ {
-            if let EventObject::CheckoutSession(session) = event.data.object {
-                handle_checkout(session, pool, mailer.into_inner()).await?;
-            }
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            METRICS.webhook_event("unknown", WebhookOutcome::Rejected);
+            log::warn!("Cannot construct webhook event, is the key correct? {e}");
+            return Ok(());
         }
-    } else {
-        print_red(
-            "FAILED TO CONSTRUCT WEBHOOK EVENT, IS YOUR KEY CORRECT?\nERROR_MESSSAGE:",
-            unsafe { &event.unwrap_err_unchecked() },
-        );
-    }
+    };
 
-    Ok(())
+    // Displayed quoted, IE "\"checkout.session.completed\""
+    let event_type = event.type_.to_string().trim_matches('"').to_string();
+    let handled = match (event.type_, event.data.object) {
+        (EventType::CheckoutSessionCompleted, EventObject::CheckoutSession(session)) => {
+            handle_checkout(session, pool, mailer.into_inner())
+                .await
+                .map(|_| WebhookOutcome::Handled)
+        }
+        (EventType::ChargeRefunded, EventObject::Charge(charge)) => match charge.payment_intent {
+            Some(payment_intent) => {
+                let refunded = charge.amount_refunded.max(0) as u32;
+                record_refund(pool, payment_intent.id().to_string(), refunded)
+                    .await
+                    .map(|_| WebhookOutcome::Handled)
+            }
+            None => Ok(WebhookOutcome::Ignored),
+        },
+        _ => Ok(WebhookOutcome::Ignored),
+    };
+    let outcome = handled.as_ref().copied().unwrap_or(WebhookOutcome::Failed);
+    METRICS.webhook_event(&event_type, outcome);
+    log::info!(event_type = event_type.as_str(), outcome:% = outcome; "Webhook event");
+    handled.map(|_| ())
 }
 
 fn make_address(stripe_address: stripe::Address, name: Arc<str>) -> Result<Address, String> {
@@ -264,29 +339,28 @@ async fn handle_checkout(
     let Shipping { address, name, .. } = shipping_info;
 
     // Collect user info - wrap everything in arc bc its all being passed to multiple async fns
-    let stripe_address = address.ok_or_else(|| {
-        error::ErrorInternalServerError(format!(
-            "Address not present in Stripe Order for {}",
-            name.clone().unwrap_or(String::from("")),
-        ))
-    })?;
+    // The error is logged, so it leaves out who the order was for
+    let stripe_address = address
+        .ok_or_else(|| error::ErrorInternalServerError("Address not present in Stripe Order"))?;
 
     let name = Arc::<str>::from(
         name.unwrap_or("Name not present in Stripe payload".to_string())
             .as_str(),
     );
 
-    let email = Arc::<str>::from(
-        session
-            .customer_email
-            .unwrap_or("Not present".to_string())
-            .as_str(),
-    );
+    // What the customer paid with, which is what they were checked out with
+    // when limits applied
+    let customer_email = session
+        .customer_details
+        .and_then(|details| details.email)
+        .or(session.customer_email);
+    let email = Arc::<str>::from(customer_email.as_deref().unwrap_or("Not present"));
 
     // Collecting user cart from session metadata
-    let cart = session.metadata.ok_or(error::ErrorBadRequest(
+    let mut cart = session.metadata.ok_or(error::ErrorBadRequest(
         "Session metadata not present: need user cart",
     ))?;
+    let funnel_session = cart.remove(funnel::METADATA_KEY);
 
     let cart = Arc::new(
         cart.iter()
@@ -303,13 +377,26 @@ async fn handle_checkout(
     let total = session
         .amount_total
         .map(|n| n as u32)
-        .unwrap_or_else(|| cart.clone().iter().map(|(_, item)| item.price).sum());
+        .unwrap_or_else(|| cart.values().map(|item| item.price).sum());
     let subtotal = session.amount_subtotal.unwrap_or_default() as u32;
+    let details = session.total_details.unwrap_or_default();
+    let payment = OrderPayment {
+        subtotal: subtotal as i32,
+        shipping: details.amount_shipping.unwrap_or_default() as i32,
+        tax: details.amount_tax as i32,
+        payment_intent: session.payment_intent.map(|intent| intent.id().to_string()),
+    };
 
-    #[cfg(debug_assertions)]
-    println!("Webhook endpoint received cart: {:#?}", cart);
+    let warnings = recheck_limits(&cart, customer_email, pool.clone()).await?;
 
     let address = Arc::new(make_address(stripe_address, name.clone()).unwrap_or_default());
+    log::info!(
+        email:% = Redacted(&*email),
+        address:% = Redacted(&*address),
+        items = cart.len(),
+        total;
+        "Checkout completed"
+    );
 
     // Get DB connections
     let cart_conn = pool
@@ -328,32 +415,95 @@ async fn handle_checkout(
         cart: (*cart).clone(),
     };
 
-    let order = rt::spawn(insert_order(
-        cart_conn,
-        cart.clone(),
-        total,
-        name,
-        email,
-        address,
-    ));
-    let stock = rt::spawn(dec_items(cart, stock_conn));
-    let confirmation = rt::spawn(mail::send::send_confirmation(user_data, mailer));
+    // Sales are recorded against the order, so stock is updated once it's saved
+    let alert_mailer = mailer.clone();
+    let order = request_id::spawn(async move {
+        let order_id = insert_order(
+            cart_conn,
+            cart.clone(),
+            total,
+            payment,
+            name,
+            email,
+            address,
+        )
+        .await?;
+        if !warnings.is_empty() {
+            flag_order(pool.clone(), order_id, warnings).await?;
+        }
+        if let Some(session) = funnel_session {
+            let items = cart.keys().copied().collect();
+            funnel::record_items(
+                &pool,
+                &session,
+                Stage::PurchaseCompleted,
+                items,
+                Some(order_id),
+            )
+            .await;
+        }
+        let low_stock = dec_items(cart, order_id, stock_conn).await?;
+
+        // The order is saved either way, a failed alert is only logged
+        if !low_stock.is_empty() {
+            let alert = mail::low_stock::LowStock::new(format!("Order #{order_id}"), low_stock);
+            if let Err(e) = mail::send::send_low_stock(alert, ENV.admin_email, alert_mailer).await {
+                log::error!(order_id; "Cannot send low stock alert: {e}");
+            }
+        }
+
+        Ok::<_, actix_web::Error>(())
+    });
+    let confirmation = request_id::spawn(mail::send::send_confirmation(user_data, mailer));
 
     // TODO: Improve w exponential backoff
-    match (order.await, stock.await, confirmation.await) {
-        (Err(e), _, _) => Err(error::ErrorInternalServerError(format!(
-            "Saving order failed: {e}"
-        ))),
-        (_, Err(e), _) => Err(error::ErrorInternalServerError(format!(
-            "Updating stock failed: {e}"
+    // Both the task and the order it saves can fail
+    let order = order
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|saved| saved.map_err(|e| e.to_string()));
+    // Failing the webhook would have Stripe resend it and save the order again,
+    // so a confirmation that couldn't be sent is only logged
+    let confirmation = confirmation.await.map(|sent| {
+        if let Err(e) = sent {
+            log::error!("Cannot send order confirmation: {e}");
+        }
+    });
+    match (order, confirmation) {
+        (Err(e), _) => Err(error::ErrorInternalServerError(format!(
+            "Saving order and updating stock failed: {e}"
         ))),
-        (_, _, Err(e)) => Err(error::ErrorInternalServerError(format!(
+        (_, Err(e)) => Err(error::ErrorInternalServerError(format!(
             "Sending confirmation email failed: {e}"
         ))),
         _ => Ok(()),
     }
 }
 
+/// The drop windows and per customer limits a paid cart breaks. They were
+/// checked when checkout started, but the window may have closed or another
+/// order used up the customer's limit since. Must run before the order is saved
+async fn recheck_limits(
+    cart: &HashMap<ItemId, Item>,
+    email: Option<String>,
+    pool: Arc<DbPool>,
+) -> Result<Vec<Warning>> {
+    let quantities = cart
+        .iter()
+        .map(|(id, item)| (*id, item.quantity))
+        .collect::<CartMap>();
+
+    let mut warnings = get_quote(&quantities, pool.clone())
+        .await?
+        .warnings
+        .into_iter()
+        .filter(|warning| matches!(warning, Warning::NotOnSale { .. }))
+        .collect::<Vec<Warning>>();
+    warnings.extend(customer_limit_warnings(&quantities, email, pool).await?);
+
+    Ok(warnings)
+}
+
 fn get_header_value<'b>(req: &'b HttpRequest, key: &'b str) -> Option<&'b str> {
     req.headers().get(key)?.to_str().ok()
 }
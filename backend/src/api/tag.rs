@@ -0,0 +1,120 @@
+use actix_web::{delete, error, get, put, web, HttpResponse, Result};
+use diesel::prelude::*;
+use model::{
+    schema::{product_tags, tags},
+    tag,
+};
+
+use crate::{request_id, DbPool};
+
+/// Replaces the tags of a product, creating any that don't exist yet
+pub fn set_tags(conn: &mut SqliteConnection, product_id: i32, names: &[String]) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        diesel::delete(product_tags::table.filter(product_tags::product_id.eq(product_id)))
+            .execute(conn)?;
+
+        for name in names {
+            diesel::insert_or_ignore_into(tags::table)
+                .values(tags::name.eq(name))
+                .execute(conn)?;
+            let tag_id = tags::table
+                .select(tags::id)
+                .filter(tags::name.eq(name))
+                .first::<i32>(conn)?;
+            diesel::insert_or_ignore_into(product_tags::table)
+                .values(tag::NewProductTag { product_id, tag_id })
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+#[get("/tags")]
+pub async fn get_tags(pool: web::Data<DbPool>) -> Result<web::Json<Vec<tag::Tag>>> {
+    let tags = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+        tags::table
+            .select(tag::TableTag::as_select())
+            .order(tags::name)
+            .get_results::<tag::TableTag>(&mut conn)
+            .map_err(|e| format!("Cannot fetch tags: {e}"))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .into_iter()
+    .map(tag::Tag::from)
+    .collect();
+
+    Ok(web::Json(tags))
+}
+
+#[put("/tags")]
+pub async fn put_tag(
+    pool: web::Data<DbPool>,
+    fields: web::Json<tag::NewTag>,
+) -> Result<HttpResponse> {
+    let fields = fields.into_inner();
+
+    let id = request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        diesel::insert_into(tags::table)
+            .values(&fields)
+            .returning(tags::id)
+            .get_result::<i32>(&mut conn)
+            .map_err(|_| "Cannot insert tag, is the name already taken?")
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(id))
+}
+
+#[put("/tags/{tag_id}")]
+pub async fn update_tag(
+    tag_id: web::Path<i32>,
+    fields: web::Json<tag::NewTag>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse> {
+    let tag_id = tag_id.into_inner();
+    let fields = fields.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB".to_string())?;
+        match diesel::update(tags::table.filter(tags::id.eq(tag_id)))
+            .set(&fields)
+            .execute(&mut conn)
+        {
+            Ok(0) => Err(format!("Tag {tag_id} does not exist")),
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("Cannot update tag {tag_id}")),
+        }
+    })
+    .await?
+    .map_err(error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Deletes a tag and removes it from every product
+#[delete("/tags/{tag_id}")]
+pub async fn delete_tag(tag_id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse> {
+    let tag_id = tag_id.into_inner();
+
+    request_id::block(move || {
+        let mut conn = pool.get().map_err(|_| "Cannot connect to DB")?;
+        conn.transaction(|conn| {
+            diesel::delete(product_tags::table.filter(product_tags::tag_id.eq(tag_id)))
+                .execute(conn)?;
+            diesel::delete(tags::table.filter(tags::id.eq(tag_id))).execute(conn)
+        })
+        .map_err(|_| "Cannot delete tag")
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,40 @@
+// Tells crawlers, uptime checkers and link previewers apart from visitors, so
+// they don't inflate visit counts
+use std::sync::LazyLock;
+
+use actix_web::{http::header, HttpRequest};
+
+/// Lowercase patterns from bots.txt
+static PATTERNS: LazyLock<Vec<String>> = LazyLock::new(|| {
+    include_str!("../bots.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+});
+
+/// Whether `user_agent` matches a pattern from bots.txt
+pub fn is_bot_agent(user_agent: &str) -> bool {
+    let user_agent = user_agent.to_lowercase();
+    PATTERNS
+        .iter()
+        .any(|pattern| user_agent.contains(pattern.as_str()))
+}
+
+/// Whether the request comes from a bot, by its user agent or, for bots
+/// posing as browsers, by missing headers every browser sends
+pub fn is_bot(req: &HttpRequest) -> bool {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    match header(header::USER_AGENT) {
+        Some(user_agent) => is_bot_agent(user_agent) || header(header::ACCEPT_LANGUAGE).is_none(),
+        None => true,
+    }
+}
@@ -0,0 +1,714 @@
+// Bulk import and export of the catalogue, shared by the admin API and the CLI
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use model::{
+    catalogue::{Action, CatalogueRow, Change, Format, ImportReport, RowError},
+    inventory,
+    item::{self, ItemFields, Options, SalesMode},
+    product::{self, ProductFields},
+    schema::{categories, kinds, product_tags, products, stock, tags},
+    ItemId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{
+    inventory::{insert_item, low_stock, record_movement, record_price},
+    tag::set_tags,
+};
+
+/// Separates tags, and options from each other, inside a CSV cell
+const LIST_SEPARATOR: &str = ";";
+
+/// Note on the corrections recorded when an import changes a quantity
+const IMPORT_NOTE: &str = "Catalogue import";
+
+/// One variant per row, products without variants get a row with only the
+/// product's columns filled in
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRow {
+    title: String,
+    description: String,
+    category: Option<String>,
+    /// IE "cats;new"
+    tags: Option<String>,
+    sku: Option<String>,
+    /// IE "size=A4;paper=silk"
+    options: Option<String>,
+    kind: Option<String>,
+    price: Option<u32>,
+    quantity: Option<i32>,
+    /// Missing from catalogues exported before thresholds existed
+    #[serde(default)]
+    low_stock_threshold: Option<u32>,
+    #[serde(default)]
+    sales_mode: Option<SalesMode>,
+    /// IE "2027-03-01"
+    #[serde(default)]
+    ships_on: Option<NaiveDate>,
+    #[serde(default)]
+    preorder_cap: Option<u32>,
+    /// IE "2026-11-27T17:00:00", in UTC
+    #[serde(default)]
+    available_from: Option<NaiveDateTime>,
+    #[serde(default)]
+    available_until: Option<NaiveDateTime>,
+    #[serde(default)]
+    max_per_order: Option<u32>,
+    #[serde(default)]
+    max_per_customer: Option<u32>,
+}
+
+/// Loads the whole catalogue in the format of stock.json, oldest products first
+pub fn export(conn: &mut SqliteConnection) -> Result<Vec<ProductFields>, String> {
+    let table_products = products::table
+        .left_join(categories::table)
+        .select((
+            product::TableProduct::as_select(),
+            categories::name.nullable(),
+        ))
+        .order(products::id)
+        .get_results::<(product::TableProduct, Option<String>)>(conn)
+        .map_err(|e| format!("Cannot fetch products: {e}"))?;
+    let product_tag_names = product_tags::table
+        .inner_join(tags::table)
+        .select((product_tags::product_id, tags::name))
+        .order(tags::name)
+        .get_results::<(i32, String)>(conn)
+        .map_err(|e| format!("Cannot fetch tags: {e}"))?;
+    let variants = stock::table
+        .inner_join(kinds::table)
+        .select((item::TableItem::as_select(), kinds::name))
+        .order(stock::id)
+        .get_results::<(item::TableItem, String)>(conn)
+        .map_err(|e| format!("Cannot fetch stock: {e}"))?;
+
+    let positions = table_products
+        .iter()
+        .enumerate()
+        .map(|(position, (p, _))| (p.id, position))
+        .collect::<HashMap<i32, usize>>();
+    let mut catalogue = table_products
+        .into_iter()
+        .map(|(p, category)| ProductFields {
+            title: p.title,
+            description: p.description,
+            variants: Vec::new(),
+            category,
+            tags: Vec::new(),
+        })
+        .collect::<Vec<ProductFields>>();
+
+    for (product_id, name) in product_tag_names {
+        if let Some(&position) = positions.get(&product_id) {
+            catalogue[position].tags.push(name);
+        }
+    }
+
+    for (table_item, kind) in variants {
+        if let Some(&position) = positions.get(&table_item.product_id) {
+            catalogue[position].variants.push(ItemFields {
+                options: item::parse_options(&table_item.options)?,
+                sku: table_item.sku,
+                kind,
+                price: table_item.price.map(|price| price as u32),
+                quantity: table_item.quantity,
+                low_stock_threshold: table_item
+                    .low_stock_threshold
+                    .map(|threshold| threshold as u32),
+                sales_mode: table_item.sales_mode.parse::<SalesMode>()?,
+                ships_on: table_item.ships_on,
+                preorder_cap: table_item.preorder_cap.map(|cap| cap as u32),
+                available_from: table_item.available_from,
+                available_until: table_item.available_until,
+                max_per_order: table_item.max_per_order.map(|max| max as u32),
+                max_per_customer: table_item.max_per_customer.map(|max| max as u32),
+            });
+        }
+    }
+
+    Ok(catalogue)
+}
+
+pub fn serialize(catalogue: &[ProductFields], format: Format) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Json => serde_json::to_vec_pretty(catalogue).map_err(|e| format!("{e}")),
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for fields in catalogue {
+                let tags = (!fields.tags.is_empty()).then(|| fields.tags.join(LIST_SEPARATOR));
+                let product_row = |variant: Option<&ItemFields>| CsvRow {
+                    title: fields.title.clone(),
+                    description: fields.description.clone(),
+                    category: fields.category.clone(),
+                    tags: tags.clone(),
+                    sku: variant.map(|variant| variant.sku.clone()),
+                    options: variant
+                        .filter(|variant| !variant.options.is_empty())
+                        .map(|variant| format_options(&variant.options)),
+                    kind: variant.map(|variant| variant.kind.clone()),
+                    price: variant.and_then(|variant| variant.price),
+                    quantity: variant.map(|variant| variant.quantity),
+                    low_stock_threshold: variant.and_then(|variant| variant.low_stock_threshold),
+                    sales_mode: variant.map(|variant| variant.sales_mode),
+                    ships_on: variant.and_then(|variant| variant.ships_on),
+                    preorder_cap: variant.and_then(|variant| variant.preorder_cap),
+                    available_from: variant.and_then(|variant| variant.available_from),
+                    available_until: variant.and_then(|variant| variant.available_until),
+                    max_per_order: variant.and_then(|variant| variant.max_per_order),
+                    max_per_customer: variant.and_then(|variant| variant.max_per_customer),
+                };
+
+                if fields.variants.is_empty() {
+                    writer.serialize(product_row(None))
+                } else {
+                    fields
+                        .variants
+                        .iter()
+                        .try_for_each(|variant| writer.serialize(product_row(Some(variant))))
+                }
+                .map_err(|e| format!("Cannot write CSV: {e}"))?;
+            }
+
+            writer
+                .into_inner()
+                .map_err(|e| format!("Cannot write CSV: {e}"))
+        }
+    }
+}
+
+/// {"paper": "silk", "size": "A4"} -> "paper=silk;size=A4"
+fn format_options(options: &Options) -> String {
+    options
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<String>>()
+        .join(LIST_SEPARATOR)
+}
+
+fn parse_options(options: &str) -> Result<Options, String> {
+    options
+        .split(LIST_SEPARATOR)
+        .filter(|option| !option.trim().is_empty())
+        .map(|option| {
+            option
+                .split_once('=')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| format!("Option \"{option}\" is not of the form name=value"))
+        })
+        .collect()
+}
+
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads an import into rows, or reports every row that cannot be read
+pub fn parse(bytes: &[u8], format: Format) -> Result<Vec<CatalogueRow>, Vec<RowError>> {
+    match format {
+        Format::Json => {
+            let catalogue = serde_json::from_slice::<Vec<ProductFields>>(bytes).map_err(|e| {
+                vec![RowError {
+                    row: e.line(),
+                    message: format!("Malformed JSON: {e}"),
+                }]
+            })?;
+
+            Ok(catalogue
+                .into_iter()
+                .enumerate()
+                .flat_map(|(position, fields)| {
+                    let row = |variant| CatalogueRow {
+                        row: position + 1,
+                        title: fields.title.clone(),
+                        description: fields.description.clone(),
+                        category: fields.category.clone(),
+                        tags: fields.tags.clone(),
+                        variant,
+                    };
+
+                    if fields.variants.is_empty() {
+                        vec![row(None)]
+                    } else {
+                        fields.variants.iter().cloned().map(Some).map(row).collect()
+                    }
+                })
+                .collect())
+        }
+        Format::Csv => {
+            let mut reader = csv::Reader::from_reader(bytes);
+            let mut rows = Vec::new();
+            let mut errors = Vec::new();
+
+            for (i, record) in reader.deserialize::<CsvRow>().enumerate() {
+                // The header is the first line
+                let line = i + 2;
+                match record
+                    .map_err(|e| format!("Malformed row: {e}"))
+                    .and_then(|record| csv_row(line, record))
+                {
+                    Ok(row) => rows.push(row),
+                    Err(message) => errors.push(RowError { row: line, message }),
+                }
+            }
+
+            if errors.is_empty() {
+                Ok(rows)
+            } else {
+                Err(errors)
+            }
+        }
+    }
+}
+
+fn csv_row(
+    row: usize,
+    CsvRow {
+        title,
+        description,
+        category,
+        tags,
+        sku,
+        options,
+        kind,
+        price,
+        quantity,
+        low_stock_threshold,
+        sales_mode,
+        ships_on,
+        preorder_cap,
+        available_from,
+        available_until,
+        max_per_order,
+        max_per_customer,
+    }: CsvRow,
+) -> Result<CatalogueRow, String> {
+    let variant = match (sku, kind, quantity) {
+        (None, None, None)
+            if options.is_none()
+                && price.is_none()
+                && low_stock_threshold.is_none()
+                && sales_mode.is_none()
+                && ships_on.is_none()
+                && preorder_cap.is_none()
+                && available_from.is_none()
+                && available_until.is_none()
+                && max_per_order.is_none()
+                && max_per_customer.is_none() =>
+        {
+            None
+        }
+        (Some(sku), Some(kind), Some(quantity)) => Some(ItemFields {
+            sku,
+            options: parse_options(options.as_deref().unwrap_or_default())?,
+            kind,
+            price,
+            quantity,
+            low_stock_threshold,
+            sales_mode: sales_mode.unwrap_or_default(),
+            ships_on,
+            preorder_cap,
+            available_from,
+            available_until,
+            max_per_order,
+            max_per_customer,
+        }),
+        _ => return Err("Variants need a sku, kind and quantity".to_string()),
+    };
+
+    Ok(CatalogueRow {
+        row,
+        title,
+        description,
+        category,
+        tags: parse_list(tags.as_deref().unwrap_or_default()),
+        variant,
+    })
+}
+
+struct ExistingProduct {
+    id: i32,
+    description: String,
+    category: Option<String>,
+    tags: BTreeSet<String>,
+}
+
+struct ExistingItem {
+    id: i32,
+    title: String,
+    options: Options,
+    kind: String,
+    price: Option<u32>,
+    quantity: i32,
+    low_stock_threshold: Option<u32>,
+    sales_mode: SalesMode,
+    ships_on: Option<NaiveDate>,
+    preorder_cap: Option<u32>,
+    available_from: Option<NaiveDateTime>,
+    available_until: Option<NaiveDateTime>,
+    max_per_order: Option<u32>,
+    max_per_customer: Option<u32>,
+}
+
+/// Upserts products by title and variants by SKU. Variants missing from the
+/// import are left alone. Changes are only applied if no row has an error and
+/// `dry_run` is false. Quantity changes are recorded as corrections, price
+/// changes as made by `actor`
+pub fn import(
+    conn: &mut SqliteConnection,
+    rows: &[CatalogueRow],
+    dry_run: bool,
+    actor: Option<&str>,
+) -> Result<ImportReport, String> {
+    let kind_ids = kinds::table
+        .select((kinds::name, kinds::id))
+        .get_results::<(String, i32)>(conn)
+        .map_err(|e| format!("Cannot fetch kinds: {e}"))?
+        .into_iter()
+        .collect::<HashMap<String, i32>>();
+    let category_ids = categories::table
+        .select((categories::name, categories::id))
+        .get_results::<(String, i32)>(conn)
+        .map_err(|e| format!("Cannot fetch categories: {e}"))?
+        .into_iter()
+        .collect::<HashMap<String, i32>>();
+
+    let mut existing_products = HashMap::<String, ExistingProduct>::new();
+    for (product, category) in products::table
+        .left_join(categories::table)
+        .select((
+            product::TableProduct::as_select(),
+            categories::name.nullable(),
+        ))
+        .get_results::<(product::TableProduct, Option<String>)>(conn)
+        .map_err(|e| format!("Cannot fetch products: {e}"))?
+    {
+        existing_products.insert(
+            product.title,
+            ExistingProduct {
+                id: product.id,
+                description: product.description,
+                category,
+                tags: BTreeSet::new(),
+            },
+        );
+    }
+    let titles = existing_products
+        .iter()
+        .map(|(title, product)| (product.id, title.clone()))
+        .collect::<HashMap<i32, String>>();
+    for (product_id, name) in product_tags::table
+        .inner_join(tags::table)
+        .select((product_tags::product_id, tags::name))
+        .get_results::<(i32, String)>(conn)
+        .map_err(|e| format!("Cannot fetch tags: {e}"))?
+    {
+        if let Some(product) = titles
+            .get(&product_id)
+            .and_then(|title| existing_products.get_mut(title))
+        {
+            product.tags.insert(name);
+        }
+    }
+
+    let mut existing_items = HashMap::<String, ExistingItem>::new();
+    for (table_item, kind) in stock::table
+        .inner_join(kinds::table)
+        .select((item::TableItem::as_select(), kinds::name))
+        .get_results::<(item::TableItem, String)>(conn)
+        .map_err(|e| format!("Cannot fetch stock: {e}"))?
+    {
+        existing_items.insert(
+            table_item.sku,
+            ExistingItem {
+                id: table_item.id,
+                title: titles
+                    .get(&table_item.product_id)
+                    .cloned()
+                    .unwrap_or_default(),
+                options: item::parse_options(&table_item.options)?,
+                kind,
+                price: table_item.price.map(|price| price as u32),
+                quantity: table_item.quantity,
+                low_stock_threshold: table_item
+                    .low_stock_threshold
+                    .map(|threshold| threshold as u32),
+                sales_mode: table_item.sales_mode.parse::<SalesMode>()?,
+                ships_on: table_item.ships_on,
+                preorder_cap: table_item.preorder_cap.map(|cap| cap as u32),
+                available_from: table_item.available_from,
+                available_until: table_item.available_until,
+                max_per_order: table_item.max_per_order.map(|max| max as u32),
+                max_per_customer: table_item.max_per_customer.map(|max| max as u32),
+            },
+        );
+    }
+
+    let mut report = ImportReport::default();
+    // Rows of the products and variants to write, in the order they were read
+    let mut product_writes = Vec::<&CatalogueRow>::new();
+    let mut item_writes = Vec::<&CatalogueRow>::new();
+    let mut first_rows = HashMap::<&str, &CatalogueRow>::new();
+    let mut skus = HashSet::<&str>::new();
+
+    for row in rows {
+        let mut errors = Vec::new();
+        let tag_set = row.tags.iter().cloned().collect::<BTreeSet<String>>();
+
+        if row.title.trim().is_empty() {
+            errors.push("Title is empty".to_string());
+        }
+        if let Some(ref category) = row.category {
+            if !category_ids.contains_key(category) {
+                errors.push(format!("Unknown category {category}"));
+            }
+        }
+
+        match first_rows.get(row.title.as_str()) {
+            Some(first) => {
+                let first_tags = first.tags.iter().cloned().collect::<BTreeSet<String>>();
+                if first.description != row.description
+                    || first.category != row.category
+                    || first_tags != tag_set
+                {
+                    errors.push(format!(
+                        "Product details differ from row {} with the same title",
+                        first.row
+                    ));
+                }
+            }
+            None if errors.is_empty() => {
+                first_rows.insert(&row.title, row);
+                match existing_products.get(&row.title) {
+                    Some(existing) => {
+                        let mut fields = Vec::new();
+                        if existing.description != row.description {
+                            fields.push("description".to_string());
+                        }
+                        if existing.category != row.category {
+                            fields.push("category".to_string());
+                        }
+                        if existing.tags != tag_set {
+                            fields.push("tags".to_string());
+                        }
+
+                        if fields.is_empty() {
+                            report.unchanged += 1;
+                        } else {
+                            product_writes.push(row);
+                            report.changes.push(Change {
+                                row: row.row,
+                                title: row.title.clone(),
+                                sku: None,
+                                action: Action::Update,
+                                fields,
+                            });
+                        }
+                    }
+                    None => {
+                        product_writes.push(row);
+                        report.changes.push(Change {
+                            row: row.row,
+                            title: row.title.clone(),
+                            sku: None,
+                            action: Action::Create,
+                            fields: Vec::new(),
+                        });
+                    }
+                }
+            }
+            None => (),
+        }
+
+        if let Some(ref variant) = row.variant {
+            if variant.sku.trim().is_empty() {
+                errors.push("SKU is empty".to_string());
+            } else if !skus.insert(&variant.sku) {
+                errors.push(format!("SKU {} appears more than once", variant.sku));
+            }
+            if !kind_ids.contains_key(&variant.kind) {
+                errors.push(format!("Unknown kind {}", variant.kind));
+            }
+            if let Err(e) = variant.validate() {
+                errors.push(e);
+            }
+
+            match existing_items.get(&variant.sku) {
+                Some(existing) if existing.title != row.title => errors.push(format!(
+                    "SKU {} belongs to product {}",
+                    variant.sku, existing.title
+                )),
+                _ if !errors.is_empty() => (),
+                Some(existing) => {
+                    let mut fields = Vec::new();
+                    if existing.options != variant.options {
+                        fields.push("options".to_string());
+                    }
+                    if existing.kind != variant.kind {
+                        fields.push("kind".to_string());
+                    }
+                    if existing.price != variant.price {
+                        fields.push("price".to_string());
+                    }
+                    if existing.quantity != variant.quantity {
+                        fields.push("quantity".to_string());
+                    }
+                    if existing.low_stock_threshold != variant.low_stock_threshold {
+                        fields.push("low_stock_threshold".to_string());
+                    }
+                    if existing.sales_mode != variant.sales_mode {
+                        fields.push("sales_mode".to_string());
+                    }
+                    if existing.ships_on != variant.ships_on {
+                        fields.push("ships_on".to_string());
+                    }
+                    if existing.preorder_cap != variant.preorder_cap {
+                        fields.push("preorder_cap".to_string());
+                    }
+                    if existing.available_from != variant.available_from {
+                        fields.push("available_from".to_string());
+                    }
+                    if existing.available_until != variant.available_until {
+                        fields.push("available_until".to_string());
+                    }
+                    if existing.max_per_order != variant.max_per_order {
+                        fields.push("max_per_order".to_string());
+                    }
+                    if existing.max_per_customer != variant.max_per_customer {
+                        fields.push("max_per_customer".to_string());
+                    }
+
+                    if fields.is_empty() {
+                        report.unchanged += 1;
+                    } else {
+                        item_writes.push(row);
+                        report.changes.push(Change {
+                            row: row.row,
+                            title: row.title.clone(),
+                            sku: Some(variant.sku.clone()),
+                            action: Action::Update,
+                            fields,
+                        });
+                    }
+                }
+                None => {
+                    item_writes.push(row);
+                    report.changes.push(Change {
+                        row: row.row,
+                        title: row.title.clone(),
+                        sku: Some(variant.sku.clone()),
+                        action: Action::Create,
+                        fields: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        report
+            .errors
+            .extend(errors.into_iter().map(|message| RowError {
+                row: row.row,
+                message,
+            }));
+    }
+
+    if dry_run || !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    conn.transaction(|conn| {
+        let mut product_ids = existing_products
+            .iter()
+            .map(|(title, product)| (title.clone(), product.id))
+            .collect::<HashMap<String, i32>>();
+
+        for row in product_writes {
+            let fields = ProductFields {
+                title: row.title.clone(),
+                description: row.description.clone(),
+                variants: Vec::new(),
+                category: row.category.clone(),
+                tags: row.tags.clone(),
+            };
+            let new_product = product::NewProduct::new(
+                &fields,
+                row.category
+                    .as_ref()
+                    .and_then(|category| category_ids.get(category).copied()),
+            );
+
+            let product_id = match product_ids.get(&row.title) {
+                Some(&id) => {
+                    diesel::update(products::table.filter(products::id.eq(id)))
+                        .set(new_product)
+                        .execute(conn)?;
+                    id
+                }
+                None => diesel::insert_into(products::table)
+                    .values(new_product)
+                    .returning(products::id)
+                    .get_result::<i32>(conn)?,
+            };
+            set_tags(conn, product_id, &row.tags)?;
+            product_ids.insert(row.title.clone(), product_id);
+        }
+
+        for row in item_writes {
+            let Some(ref variant) = row.variant else {
+                continue;
+            };
+            let new_item =
+                item::NewItem::new(variant, product_ids[&row.title], kind_ids[&variant.kind]);
+
+            match existing_items.get(&variant.sku) {
+                Some(existing) => {
+                    // Sales may have landed since the diff was taken, the
+                    // correction is against the quantity as it is now
+                    let before = stock::table
+                        .filter(stock::id.eq(existing.id))
+                        .select(stock::quantity)
+                        .first::<i32>(conn)?;
+                    let delta = new_item.quantity - before;
+                    diesel::update(stock::table.filter(stock::id.eq(existing.id)))
+                        .set(new_item.changes)
+                        .execute(conn)?;
+                    record_price(conn, existing.id, actor)?;
+
+                    if delta != 0 {
+                        let after = record_movement(
+                            conn,
+                            &inventory::NewMovement {
+                                note: Some(IMPORT_NOTE),
+                                ..inventory::NewMovement::new(
+                                    existing.id,
+                                    delta,
+                                    inventory::Reason::Correction,
+                                )
+                            },
+                        )?;
+                        if item::is_back_in_stock(before, after) {
+                            report.restocked.push(existing.id as ItemId);
+                        }
+                        report
+                            .low_stock
+                            .extend(low_stock(conn, existing.id, before, after)?);
+                    }
+                }
+                None => {
+                    insert_item(conn, new_item, actor)?;
+                }
+            }
+        }
+
+        Ok::<_, diesel::result::Error>(())
+    })
+    .map_err(|e| format!("Cannot apply import: {e}"))?;
+
+    report.applied = true;
+    Ok(report)
+}
@@ -0,0 +1,141 @@
+// Admin subcommands, run in place of the server when kiggyserve is given arguments
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use model::catalogue::Format;
+
+use crate::{catalogue, DbPool};
+
+const USAGE: &str = "Usage:
+  kiggyserve                                          Run the server
+  kiggyserve export [--format csv|json] [FILE]        Export the catalogue to FILE or stdout
+  kiggyserve import [--format csv|json] [--dry-run] FILE
+                                                      Upsert the catalogue from FILE, matching
+                                                      products by title and variants by SKU";
+
+/// Recorded as the actor of price changes made from the command line
+const CLI_ACTOR: &str = "cli";
+
+struct Args {
+    format: Option<Format>,
+    dry_run: bool,
+    file: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        format: None,
+        dry_run: false,
+        file: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let format = args.next().ok_or("--format needs a value")?;
+                parsed.format = Some(format.parse::<Format>()?);
+            }
+            "--dry-run" => parsed.dry_run = true,
+            file if parsed.file.is_none() && !file.starts_with("--") => {
+                parsed.file = Some(file.to_string())
+            }
+            other => return Err(format!("Unexpected argument {other}")),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// --format if given, otherwise guessed from the file's extension
+fn format(args: &Args) -> Format {
+    args.format.unwrap_or_else(|| {
+        args.file
+            .as_deref()
+            .and_then(|file| Path::new(file).extension())
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.parse::<Format>().ok())
+            .unwrap_or_default()
+    })
+}
+
+fn run_command(command: &str, args: &[String], pool: &DbPool) -> Result<(), String> {
+    let args = parse_args(args)?;
+    let format = format(&args);
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+
+    match command {
+        "export" => {
+            let bytes = catalogue::serialize(&catalogue::export(&mut conn)?, format)?;
+            match args.file {
+                Some(file) => {
+                    std::fs::write(&file, bytes).map_err(|e| format!("Cannot write {file}: {e}"))
+                }
+                None => io::stdout()
+                    .write_all(&bytes)
+                    .map_err(|e| format!("Cannot write catalogue: {e}")),
+            }
+        }
+        "import" => {
+            let bytes = match args.file {
+                Some(ref file) if file != "-" => {
+                    std::fs::read(file).map_err(|e| format!("Cannot read {file}: {e}"))?
+                }
+                _ => {
+                    let mut bytes = Vec::new();
+                    io::stdin()
+                        .read_to_end(&mut bytes)
+                        .map_err(|e| format!("Cannot read catalogue: {e}"))?;
+                    bytes
+                }
+            };
+
+            let report = match catalogue::parse(&bytes, format) {
+                Ok(rows) => catalogue::import(&mut conn, &rows, args.dry_run, Some(CLI_ACTOR))?,
+                Err(errors) => model::catalogue::ImportReport {
+                    errors,
+                    ..Default::default()
+                },
+            };
+
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).map_err(|e| format!("{e}"))?
+            );
+
+            if report.errors.is_empty() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "Import has {} errors, nothing was imported",
+                    report.errors.len()
+                ))
+            }
+        }
+        other => Err(format!("Unknown command {other}\n\n{USAGE}")),
+    }
+}
+
+/// Runs the subcommand named by the first argument, exiting with an error
+/// status if it fails
+pub fn run(args: &[String], pool: &DbPool) -> io::Result<()> {
+    let Some((command, args)) = args.split_first() else {
+        return Ok(());
+    };
+
+    if command == "--help" || command == "help" {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    if let Err(e) = run_command(command, args, pool) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+// Resolves the address of the client behind the reverse proxy, forwarding
+// headers are only believed when they come from a trusted proxy
+use std::{net::IpAddr, str::FromStr};
+
+use actix_web::{
+    http::header::{HeaderMap, HeaderName},
+    web, HttpRequest,
+};
+use ipnetwork::IpNetwork;
+
+/// Networks whose forwarding headers are trusted, IE the nginx container's
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpNetwork>);
+
+/// Comma separated CIDRs or single addresses, IE "127.0.0.1,172.16.0.0/12"
+pub fn parse_networks(s: &str) -> Result<Vec<IpNetwork>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(|network| {
+            network
+                .parse::<IpNetwork>()
+                .map_err(|_| format!("Invalid network {network}"))
+        })
+        .collect()
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_networks(s).map(TrustedProxies)
+    }
+}
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// The client's address given the connection's `peer`. Forwarded hops are
+    /// followed back from the peer until one isn't a trusted proxy, that one is
+    /// the client. `Forwarded` takes precedence over `X-Forwarded-For`, which
+    /// takes precedence over `X-Real-IP`
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.contains(client) {
+            return client;
+        }
+
+        let Some(hops) = forwarded_hops(headers) else {
+            return client;
+        };
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(ip) if self.contains(ip) => client = ip,
+                Some(ip) => return ip,
+                // An unknown or obfuscated hop can't be followed any further
+                None => return client,
+            }
+        }
+
+        client
+    }
+}
+
+/// Every value of the header `name`, in order, split on commas
+fn header_list(headers: &HeaderMap, name: HeaderName) -> Option<Vec<String>> {
+    let values = headers
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<String>>();
+
+    (!values.is_empty()).then_some(values)
+}
+
+/// The addresses a request was forwarded for, client first. `None` entries
+/// couldn't be parsed. `None` overall if no forwarding header was sent. nginx
+/// overwrites `Forwarded` with the connecting address, so a client can't smuggle
+/// its own through
+fn forwarded_hops(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    if let Some(elements) = header_list(headers, actix_web::http::header::FORWARDED) {
+        // IE `for=192.0.2.60;proto=http;by=203.0.113.43, for="[2001:db8::1]:4711"`
+        return Some(
+            elements
+                .iter()
+                .map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.split_once('='))
+                        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                        .and_then(|(_, value)| parse_node(value))
+                })
+                .collect(),
+        );
+    }
+
+    header_list(headers, HeaderName::from_static("x-forwarded-for"))
+        .or_else(|| header_list(headers, HeaderName::from_static("x-real-ip")))
+        .map(|hops| hops.iter().map(|hop| parse_node(hop)).collect())
+}
+
+/// Parses an address that may be quoted, bracketed or followed by a port
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip.to_canonical());
+    }
+
+    let host = match node.strip_prefix('[') {
+        // IE "[2001:db8::1]:4711"
+        Some(bracketed) => bracketed.split_once(']').map(|(host, _)| host)?,
+        // IE "192.0.2.43:47011"
+        None => node.rsplit_once(':').map(|(host, _)| host)?,
+    };
+    host.parse::<IpAddr>().ok().map(|ip| ip.to_canonical())
+}
+
+/// The client's address, for metrics and logs. Apps without trusted proxies
+/// configured use the connection's peer
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+
+    Some(match req.app_data::<web::Data<TrustedProxies>>() {
+        Some(proxies) => proxies.client_ip(peer, req.headers()),
+        None => peer.to_canonical(),
+    })
+}
@@ -6,6 +6,23 @@ pub struct Env {
     pub completion_redirect: &'static str,
     pub mail_user: &'static str,
     pub mail_pass: &'static str,
+    /// Directory uploaded product images and their resized copies are stored in
+    pub image_dir: &'static str,
+    /// Comma separated addresses low stock alerts are sent to
+    pub admin_email: &'static str,
+    /// Where the shop is served, IE "https://kiggyshop.com", for links in emails
+    pub public_url: &'static str,
+    /// MaxMind format city database visitors are located with, IE
+    /// "/var/lib/kiggyserve/GeoLite2-City.mmdb". Replacing the file reloads it
+    pub geoip_database: &'static str,
+    /// Comma separated CIDRs of the reverse proxies whose forwarding headers
+    /// are trusted, IE "127.0.0.1/32,172.16.0.0/12"
+    pub trusted_proxies: &'static str,
+    /// Days visits are kept individually before being rolled up by day
+    pub visit_retention_days: &'static str,
+    /// Comma separated CIDRs of the clients allowed to scrape `/metrics`, IE
+    /// Prometheus'. Empty disables the route
+    pub metrics_allowed: &'static str,
 }
 
 impl Env {
@@ -19,6 +36,13 @@ impl Env {
             let completion_redirect = dotenvy_macro::dotenv!("COMPLETION_REDIRECT");
             let mail_user = dotenvy_macro::dotenv!("MAIL_USER");
             let mail_pass = dotenvy_macro::dotenv!("MAIL_PASS");
+            let image_dir = dotenvy_macro::dotenv!("IMAGE_DIR");
+            let admin_email = dotenvy_macro::dotenv!("ADMIN_EMAIL");
+            let public_url = dotenvy_macro::dotenv!("PUBLIC_URL");
+            let geoip_database = dotenvy_macro::dotenv!("GEOIP_DATABASE");
+            let trusted_proxies = dotenvy_macro::dotenv!("TRUSTED_PROXIES");
+            let visit_retention_days = dotenvy_macro::dotenv!("VISIT_RETENTION_DAYS");
+            let metrics_allowed = dotenvy_macro::dotenv!("METRICS_ALLOWED");
 
             Self {
                 database_url,
@@ -27,6 +51,13 @@ impl Env {
                 completion_redirect,
                 mail_user,
                 mail_pass,
+                image_dir,
+                admin_email,
+                public_url,
+                geoip_database,
+                trusted_proxies,
+                visit_retention_days,
+                metrics_allowed,
             }
         }
         #[cfg(not(any(debug_assertions, test)))]
@@ -38,6 +69,13 @@ impl Env {
             let completion_redirect = std::env!("COMPLETION_REDIRECT");
             let mail_user = std::env!("MAIL_USER");
             let mail_pass = std::env!("MAIL_PASS");
+            let image_dir = std::env!("IMAGE_DIR");
+            let admin_email = std::env!("ADMIN_EMAIL");
+            let public_url = std::env!("PUBLIC_URL");
+            let geoip_database = std::env!("GEOIP_DATABASE");
+            let trusted_proxies = std::env!("TRUSTED_PROXIES");
+            let visit_retention_days = std::env!("VISIT_RETENTION_DAYS");
+            let metrics_allowed = std::env!("METRICS_ALLOWED");
 
             Self {
                 database_url,
@@ -46,6 +84,13 @@ impl Env {
                 completion_redirect,
                 mail_user,
                 mail_pass,
+                image_dir,
+                admin_email,
+                public_url,
+                geoip_database,
+                trusted_proxies,
+                visit_retention_days,
+                metrics_allowed,
             }
         }
     }
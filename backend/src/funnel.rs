@@ -0,0 +1,304 @@
+// Conversion funnel from browsing the catalogue to buying. Sessions are random
+// IDs kept in a cookie, events aren't tied to visits or addresses
+use std::collections::HashMap;
+
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    HttpMessage, HttpRequest,
+};
+use chrono::{NaiveDate, Utc};
+use diesel::{
+    prelude::*,
+    sql_types::{Date, Integer, Nullable, Text},
+};
+use model::{
+    catalogue::Format,
+    funnel::{FunnelReport, FunnelStep, NewFunnelEvent, ProductFunnel, Stage},
+    product::ProductId,
+    schema::{funnel_events, products, stock},
+    ItemId,
+};
+use serde::Serialize;
+
+use crate::{bots, request_id, visits, DbPool};
+
+pub const SESSION_COOKIE: &str = "kiggy_session";
+
+/// Key of the funnel session in checkout sessions' metadata, the rest is the cart
+pub const METADATA_KEY: &str = "funnel_session";
+
+/// Stages reported per product, catalogue views aren't of any one product
+const PRODUCT_STAGES: [Stage; 3] = [
+    Stage::ItemView,
+    Stage::CheckoutStarted,
+    Stage::PurchaseCompleted,
+];
+
+/// A session started by a request without a session cookie
+#[derive(Clone)]
+struct NewSession(String);
+
+/// Bots and visitors who opted out of tracking have no session
+fn tracked(req: &HttpRequest) -> bool {
+    !visits::opted_out(req) && !bots::is_bot(req)
+}
+
+/// Starts a session for requests without one, returning the cookie to set. The
+/// cookie lasts until the browser is closed
+pub fn start_session(req: &HttpRequest) -> Option<Cookie<'static>> {
+    if req.cookie(SESSION_COOKIE).is_some() || !tracked(req) {
+        return None;
+    }
+
+    let session = uuid::Uuid::new_v4().simple().to_string();
+    req.extensions_mut().insert(NewSession(session.clone()));
+    Some(
+        Cookie::build(SESSION_COOKIE, session)
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .finish(),
+    )
+}
+
+/// The request's session, `None` if it isn't tracked
+pub fn session(req: &HttpRequest) -> Option<String> {
+    if !tracked(req) {
+        return None;
+    }
+    if let Some(NewSession(session)) = req.extensions().get::<NewSession>() {
+        return Some(session.clone());
+    }
+
+    req.cookie(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .filter(|session| !session.is_empty() && session.len() <= 64)
+}
+
+fn event(session: &str, stage: Stage) -> NewFunnelEvent {
+    NewFunnelEvent {
+        session: session.to_owned(),
+        stage: stage.to_string(),
+        product_id: None,
+        item_id: None,
+        order_id: None,
+        time: Utc::now().naive_utc(),
+    }
+}
+
+/// Inserts the events `events` makes, failing to is only logged as a missed
+/// event doesn't matter
+async fn insert<F>(pool: &DbPool, events: F)
+where
+    F: FnOnce(&mut SqliteConnection) -> QueryResult<Vec<NewFunnelEvent>> + Send + 'static,
+{
+    let pool = pool.clone();
+    let inserted = request_id::block(move || {
+        let mut conn = pool
+            .get()
+            .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+        let events = events(&mut conn).map_err(|e| format!("Cannot fetch items: {e}"))?;
+        diesel::insert_into(funnel_events::table)
+            .values(events)
+            .execute(&mut conn)
+            .map_err(|e| format!("Cannot insert events: {e}"))
+    })
+    .await;
+
+    match inserted {
+        Ok(Ok(_)) => (),
+        Ok(Err(e)) => log::warn!("Cannot record funnel event: {e}"),
+        Err(e) => log::warn!("Cannot record funnel event: {e}"),
+    }
+}
+
+/// Records a view of the catalogue, or of a product
+pub async fn record_view(
+    pool: &DbPool,
+    session: &str,
+    stage: Stage,
+    product_id: Option<ProductId>,
+) {
+    let event = NewFunnelEvent {
+        product_id: product_id.map(|id| id as i32),
+        ..event(session, stage)
+    };
+    insert(pool, move |_| Ok(vec![event])).await
+}
+
+/// Records `stage` once for each item, with the items' products
+pub async fn record_items(
+    pool: &DbPool,
+    session: &str,
+    stage: Stage,
+    items: Vec<ItemId>,
+    order_id: Option<i32>,
+) {
+    let event = NewFunnelEvent {
+        order_id,
+        ..event(session, stage)
+    };
+    insert(pool, move |conn| {
+        let items = items.into_iter().map(|id| id as i32).collect::<Vec<i32>>();
+        let products = stock::table
+            .filter(stock::id.eq_any(&items))
+            .select((stock::id, stock::product_id))
+            .get_results::<(i32, i32)>(conn)?
+            .into_iter()
+            .collect::<HashMap<i32, i32>>();
+
+        Ok(items
+            .into_iter()
+            .map(|item_id| NewFunnelEvent {
+                product_id: products.get(&item_id).copied(),
+                item_id: Some(item_id),
+                ..event.clone()
+            })
+            .collect())
+    })
+    .await
+}
+
+#[derive(QueryableByName)]
+struct StageSessions {
+    /// `None` for the overall funnel
+    #[diesel(sql_type = Nullable<Integer>)]
+    product_id: Option<i32>,
+    #[diesel(sql_type = Text)]
+    stage: String,
+    #[diesel(sql_type = Integer)]
+    sessions: i32,
+}
+
+/// Each stage's sessions and the share of the previous stage's they are
+fn steps(sessions: &HashMap<Stage, u32>, stages: &[Stage]) -> Vec<FunnelStep> {
+    let mut previous = None;
+    stages
+        .iter()
+        .map(|stage| {
+            let count = sessions.get(stage).copied().unwrap_or_default();
+            let conversion = match previous {
+                None => 1.0,
+                Some(0) => 0.0,
+                Some(previous) => count as f64 / previous as f64,
+            };
+            previous = Some(count);
+            FunnelStep {
+                stage: *stage,
+                sessions: count,
+                conversion,
+            }
+        })
+        .collect()
+}
+
+/// Sessions reaching each stage between `from` and `to` inclusive
+pub fn report(
+    conn: &mut SqliteConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> QueryResult<FunnelReport> {
+    let rows = diesel::sql_query(
+        "select null as product_id, stage, count(distinct session) as sessions \
+         from funnel_events where date(time) between ? and ? \
+         group by stage \
+         union all \
+         select product_id, stage, count(distinct session) as sessions \
+         from funnel_events where date(time) between ? and ? and product_id is not null \
+         group by product_id, stage",
+    )
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load::<StageSessions>(conn)?;
+
+    let mut overall = HashMap::new();
+    let mut by_product = HashMap::<i32, HashMap<Stage, u32>>::new();
+    for row in rows {
+        let Ok(stage) = row.stage.parse::<Stage>() else {
+            continue;
+        };
+        let sessions = match row.product_id {
+            Some(product_id) => by_product.entry(product_id).or_default(),
+            None => &mut overall,
+        };
+        sessions.insert(stage, row.sessions.max(0) as u32);
+    }
+
+    let titles = products::table
+        .filter(products::id.eq_any(by_product.keys().copied().collect::<Vec<i32>>()))
+        .select((products::id, products::title))
+        .get_results::<(i32, String)>(conn)?
+        .into_iter()
+        .collect::<HashMap<i32, String>>();
+    let mut products = by_product
+        .into_iter()
+        .map(|(product_id, sessions)| ProductFunnel {
+            product_id: product_id as ProductId,
+            // Deleted products keep their events but lose their title
+            title: titles.get(&product_id).cloned().unwrap_or_default(),
+            steps: steps(&sessions, &PRODUCT_STAGES),
+        })
+        .collect::<Vec<ProductFunnel>>();
+    let sessions_at = |product: &ProductFunnel, stage| {
+        product
+            .steps
+            .iter()
+            .find(|step| step.stage == stage)
+            .map(|step| step.sessions)
+            .unwrap_or_default()
+    };
+    products.sort_by(|a, b| {
+        sessions_at(b, Stage::PurchaseCompleted)
+            .cmp(&sessions_at(a, Stage::PurchaseCompleted))
+            .then_with(|| sessions_at(b, Stage::ItemView).cmp(&sessions_at(a, Stage::ItemView)))
+            .then_with(|| a.product_id.cmp(&b.product_id))
+    });
+
+    Ok(FunnelReport {
+        from,
+        to,
+        steps: steps(&overall, &Stage::ALL),
+        products,
+    })
+}
+
+/// One row per step, product columns are empty for the overall funnel
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    product_id: Option<ProductId>,
+    title: Option<&'a str>,
+    stage: Stage,
+    sessions: u32,
+    conversion: f64,
+}
+
+pub fn serialize(report: &FunnelReport, format: Format) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Json => serde_json::to_vec_pretty(report).map_err(|e| format!("{e}")),
+        Format::Csv => {
+            let overall = report.steps.iter().map(|step| (None, step));
+            let products = report
+                .products
+                .iter()
+                .flat_map(|product| product.steps.iter().map(move |step| (Some(product), step)));
+
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for (product, step) in overall.chain(products) {
+                writer
+                    .serialize(CsvRow {
+                        product_id: product.map(|product| product.product_id),
+                        title: product.map(|product| product.title.as_str()),
+                        stage: step.stage,
+                        sessions: step.sessions,
+                        conversion: step.conversion,
+                    })
+                    .map_err(|e| format!("Cannot write CSV: {e}"))?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| format!("Cannot write CSV: {e}"))
+        }
+    }
+}
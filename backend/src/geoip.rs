@@ -0,0 +1,121 @@
+// Offline IP geolocation from a MaxMind format (.mmdb) database, visitors'
+// addresses never leave the server
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use maxminddb::{geoip2, Reader};
+use model::user;
+
+/// How often the database file is checked for a newer copy
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Loaded {
+    reader: Arc<Reader<Vec<u8>>>,
+    modified: Option<SystemTime>,
+}
+
+/// Shared by every worker. Lookups resolve to an empty location while the
+/// database is missing or unreadable
+pub struct GeoIp {
+    path: PathBuf,
+    loaded: RwLock<Option<Loaded>>,
+}
+
+impl GeoIp {
+    /// Loads the database at `path`, failing to is logged but not fatal
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let geoip = Self {
+            path: path.into(),
+            loaded: RwLock::new(None),
+        };
+        if let Err(e) = geoip.reload() {
+            log::warn!("GeoIP database not loaded: {e}");
+        }
+        geoip
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Reads the database file again, the copy in use is kept if the new one
+    /// cannot be read
+    pub fn reload(&self) -> Result<(), String> {
+        let modified = self.modified();
+        let reader = Reader::open_readfile(&self.path)
+            .map_err(|e| format!("Cannot read {}: {e}", self.path.display()))?;
+
+        let mut loaded = self
+            .loaded
+            .write()
+            .map_err(|_| "GeoIP lock poisoned".to_string())?;
+        *loaded = Some(Loaded {
+            reader: Arc::new(reader),
+            modified,
+        });
+        Ok(())
+    }
+
+    /// Reloads the database if its file changed since it was last read,
+    /// returning whether it did
+    pub fn reload_if_changed(&self) -> Result<bool, String> {
+        let loaded_modified = match self.loaded.read() {
+            Ok(loaded) => loaded.as_ref().and_then(|loaded| loaded.modified),
+            Err(_) => return Err("GeoIP lock poisoned".to_string()),
+        };
+        match self.modified() {
+            Some(modified) if Some(modified) != loaded_modified => self.reload().map(|_| true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Checks for a newer database every `RELOAD_INTERVAL`, for as long as the
+    /// server runs
+    pub async fn watch(geoip: Arc<GeoIp>) {
+        let mut interval = actix_web::rt::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = geoip.reload_if_changed() {
+                log::error!("Cannot reload GeoIP database: {e}");
+            }
+        }
+    }
+
+    /// Where `ip` is, as much of it as the database knows. Private addresses
+    /// and addresses missing from the database get an empty location
+    pub fn locate(&self, ip: IpAddr) -> user::Location {
+        let reader = match self.loaded.read() {
+            Ok(loaded) => match loaded.as_ref() {
+                Some(loaded) => loaded.reader.clone(),
+                None => return user::Location::default(),
+            },
+            Err(_) => return user::Location::default(),
+        };
+
+        let Ok(city) = reader.lookup::<geoip2::City>(ip) else {
+            return user::Location::default();
+        };
+        let english = |names: Option<std::collections::BTreeMap<&str, &str>>| {
+            names.and_then(|names| names.get("en").map(|name| name.to_string()))
+        };
+
+        user::Location {
+            country: city
+                .country
+                .and_then(|country| english(country.names))
+                .unwrap_or_default(),
+            state: city
+                .subdivisions
+                .and_then(|subdivisions| subdivisions.into_iter().next())
+                .and_then(|subdivision| english(subdivision.names))
+                .unwrap_or_default(),
+            city: city.city.and_then(|city| english(city.names)),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// Logs are JSON lines, one per record, with the request they're part of and any
+// key-values logged alongside the message. Levels are set per module through
+// `RUST_LOG`, IE "info,kiggyserve::api::stripe=debug,actix_server=warn".
+// Emails are redacted from every line, postal addresses are only logged through
+// `Redacted`
+use std::{fmt, io::Write, net::IpAddr, time::Duration};
+
+use actix_web::{dev::ServiceResponse, http::header};
+use chrono::{SecondsFormat, Utc};
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level,
+};
+use model::address::Address;
+use serde_json::{Map, Value as Json};
+
+use crate::request_id::RequestId;
+
+/// Levels used when `RUST_LOG` isn't set
+const DEFAULT_FILTER: &str = "info";
+
+pub fn init() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(DEFAULT_FILTER))
+        .format(|buf, record| {
+            let line = to_json(record, RequestId::current(), Utc::now().naive_utc());
+            writeln!(buf, "{line}")
+        })
+        .init();
+}
+
+/// The JSON line of `record`. Key-values can't replace the standard fields
+pub fn to_json(
+    record: &log::Record,
+    request_id: Option<RequestId>,
+    time: chrono::NaiveDateTime,
+) -> String {
+    let mut fields = Map::new();
+    let _ = record.key_values().visit(&mut Fields(&mut fields));
+
+    fields.insert(
+        "time".to_string(),
+        Json::String(time.and_utc().to_rfc3339_opts(SecondsFormat::Millis, true)),
+    );
+    fields.insert(
+        "level".to_string(),
+        Json::String(record.level().to_string()),
+    );
+    fields.insert(
+        "target".to_string(),
+        Json::String(record.target().to_string()),
+    );
+    fields.insert(
+        "request_id".to_string(),
+        request_id.map_or(Json::Null, |id| Json::String(id.to_string())),
+    );
+    fields.insert(
+        "message".to_string(),
+        Json::String(redact_emails(&record.args().to_string())),
+    );
+
+    Json::Object(fields).to_string()
+}
+
+struct Fields<'a>(&'a mut Map<String, Json>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(n) = value.to_u64() {
+            Json::from(n)
+        } else if let Some(n) = value.to_i64() {
+            Json::from(n)
+        } else if let Some(b) = value.to_bool() {
+            Json::from(b)
+        } else if let Some(n) = value.to_f64() {
+            Json::from(n)
+        } else {
+            Json::String(redact_emails(&value.to_string()))
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Characters of the part of an email before the @
+fn is_local(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._%+-".contains(c)
+}
+
+fn is_domain(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ".-".contains(c)
+}
+
+/// Keeps the first character of the part before the @ and the domain, IE
+/// "kiggy@kiggy.shop" -> "k***@kiggy.shop"
+pub fn redact_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{first}***@{domain}")
+        }
+        None => "***".to_string(),
+    }
+}
+
+/// `text` with every email in it redacted
+pub fn redact_emails(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('@') {
+        let (before, after) = rest.split_at(at);
+        let local_start = before
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_local(*c))
+            .last()
+            .map_or(before.len(), |(i, _)| i);
+        let domain_len = after[1..]
+            .find(|c| !is_domain(c))
+            .unwrap_or(after.len() - 1);
+        let domain = after[1..1 + domain_len].trim_end_matches('.');
+
+        if local_start < before.len() && domain.contains('.') {
+            redacted.push_str(&before[..local_start]);
+            redacted.push_str(&redact_email(&rest[local_start..at + 1 + domain.len()]));
+            rest = &rest[at + 1 + domain.len()..];
+        } else {
+            redacted.push_str(&rest[..at + 1]);
+            rest = &rest[at + 1..];
+        }
+    }
+    redacted.push_str(rest);
+
+    redacted
+}
+
+/// Logs customers' details without identifying them. Emails keep their domain,
+/// addresses only their city and state
+pub struct Redacted<T>(pub T);
+
+impl fmt::Display for Redacted<&str> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&redact_email(self.0))
+    }
+}
+
+impl fmt::Display for Redacted<&Address> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.0.city, self.0.state)
+    }
+}
+
+/// Logs a request once it's been responded to, server errors as errors. The
+/// query string is left out as it may identify customers
+pub fn log_request(response: &ServiceResponse, elapsed: Duration, client: Option<IpAddr>) {
+    let req = response.request();
+    let status = response.status();
+    let level = if status.is_server_error() {
+        Level::Error
+    } else {
+        Level::Info
+    };
+    let message = match response.response().error() {
+        Some(e) => format!("{} {} failed: {e}", req.method(), req.path()),
+        None => format!("{} {}", req.method(), req.path()),
+    };
+
+    log::log!(
+        target: "kiggyserve::request",
+        level,
+        method = req.method().as_str(),
+        path = req.path(),
+        route = req.match_pattern().as_deref().unwrap_or_default(),
+        status = status.as_u16(),
+        duration_ms = elapsed.as_secs_f64() * 1000.0,
+        client_ip = client.map(|ip| ip.to_string()).unwrap_or_default(),
+        user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .unwrap_or_default();
+        "{message}"
+    );
+}
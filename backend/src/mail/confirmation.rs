@@ -9,16 +9,27 @@ pub struct Item {
     price: f64,
     quantity: u32,
     total: u32,
+    /// Expected ship date of pre-ordered or backordered lines, IE "March 3, 2027"
+    ships_on: Option<String>,
 }
 
-impl From<(&item::Item, &Quantity)> for Item {
-    fn from((item, quantity): (&item::Item, &Quantity)) -> Self {
+fn format_ship_date(date: &chrono::NaiveDate) -> String {
+    date.format("%B %-d, %Y").to_string()
+}
+
+impl From<(&item::PricedItem, &Quantity)> for Item {
+    fn from((item, quantity): (&item::PricedItem, &Quantity)) -> Self {
         let price = item.price();
         Self {
             title: item.title.clone(),
             price: price as f64 / 100f64,
             quantity: *quantity,
             total: price * quantity,
+            ships_on: (item.awaiting_stock(*quantity) > 0)
+                .then_some(item.item.ships_on)
+                .flatten()
+                .as_ref()
+                .map(format_ship_date),
         }
     }
 }
@@ -50,8 +61,12 @@ impl Confirmation {
         ]);
 
         for item in cart {
+            let title = match item.ships_on {
+                Some(ref date) => format!("{} (ships around {date})", item.title),
+                None => item.title.clone(),
+            };
             table.add_row(prettytable::row![
-                item.title.clone(),
+                title,
                 item.price.to_string(),
                 item.quantity.to_string(),
                 item.total.to_string(),
@@ -86,12 +101,14 @@ impl From<&stripe::User> for Confirmation {
                         title,
                         price,
                         quantity,
+                        ships_on,
                     },
                 )| Item {
                     title: title.clone(),
                     price: (*price as f64) / 100f64,
                     quantity: *quantity,
                     total: *total,
+                    ships_on: ships_on.as_ref().map(format_ship_date),
                 },
             )
             .collect::<Vec<Item>>();
@@ -0,0 +1,49 @@
+use model::inventory;
+
+/// Sent to the admins when a sale or stock change takes items down to their
+/// low stock threshold
+#[derive(Debug, Clone, askama::Template)]
+#[template(path = "low_stock.html")]
+pub struct LowStock {
+    /// What took the items down, IE "Order #12"
+    cause: String,
+    items: Vec<inventory::LowStock>,
+}
+
+impl LowStock {
+    pub fn new(cause: String, items: Vec<inventory::LowStock>) -> Self {
+        Self { cause, items }
+    }
+
+    pub fn subject(&self) -> String {
+        if self.items.iter().any(|item| item.quantity <= 0) {
+            "Items have sold out".to_string()
+        } else {
+            "Stock is running low".to_string()
+        }
+    }
+
+    pub fn render_plaintext(&self) -> String {
+        let mut table = prettytable::Table::new();
+        table.add_row(prettytable::row!["Title", "SKU", "Remaining", "Threshold"]);
+
+        for item in &self.items {
+            let remaining = match item.quantity {
+                0 => "Sold out".to_string(),
+                owed @ ..0 => format!("Sold out, {} owed", -owed),
+                quantity => quantity.to_string(),
+            };
+            table.add_row(prettytable::row![
+                item.title,
+                item.sku,
+                remaining,
+                item.threshold
+            ]);
+        }
+
+        format!(
+            "{} left these items at or below their low stock threshold:\n\n{table}",
+            self.cause
+        )
+    }
+}
@@ -1,3 +1,5 @@
 pub mod confirmation;
+pub mod low_stock;
+pub mod notification;
 pub mod send;
 pub mod shipped;
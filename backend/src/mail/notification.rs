@@ -0,0 +1,57 @@
+/// Asks someone who signed up for a back in stock email to confirm their address
+#[derive(Debug, Clone, askama::Template)]
+#[template(path = "notification_confirm.html")]
+pub struct ConfirmNotification {
+    pub email: String,
+    title: String,
+    confirm_link: String,
+    unsubscribe_link: String,
+}
+
+impl ConfirmNotification {
+    /// `base_url` is where the shop is served, IE "https://kiggyshop.com"
+    pub fn new(base_url: &str, email: String, title: String, token: &str) -> Self {
+        Self {
+            email,
+            title,
+            confirm_link: format!("{base_url}/api/notify/{token}/confirm"),
+            unsubscribe_link: format!("{base_url}/api/notify/{token}/unsubscribe"),
+        }
+    }
+
+    pub fn render_plaintext(&self) -> String {
+        format!(
+            "Please confirm you'd like to be emailed when {} is back in stock by visiting {}\n\nIf you didn't sign up, ignore this email or visit {}",
+            self.title, self.confirm_link, self.unsubscribe_link
+        )
+    }
+}
+
+/// Tells a confirmed signup their product has been restocked. The link goes to
+/// the restocked item, which is what the storefront's product pages are keyed by
+#[derive(Debug, Clone, askama::Template)]
+#[template(path = "back_in_stock.html")]
+pub struct BackInStock {
+    pub email: String,
+    pub title: String,
+    product_link: String,
+    unsubscribe_link: String,
+}
+
+impl BackInStock {
+    pub fn new(base_url: &str, email: String, title: String, item: i32, token: &str) -> Self {
+        Self {
+            email,
+            title,
+            product_link: format!("{base_url}/products/{item}"),
+            unsubscribe_link: format!("{base_url}/api/notify/{token}/unsubscribe"),
+        }
+    }
+
+    pub fn render_plaintext(&self) -> String {
+        format!(
+            "Good news, {} is back in stock! Get it at {}\n\nTo stop hearing about restocks, visit {}",
+            self.title, self.product_link, self.unsubscribe_link
+        )
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{api::stripe::User, Mailer};
+use crate::{api::stripe::User, monitoring::METRICS, Mailer};
 use actix_web::Result;
 
 use askama::Template;
@@ -8,10 +8,17 @@ use lettre::{
     AsyncTransport, Message,
 };
 
-use super::{confirmation, shipped};
+use super::{confirmation, low_stock, notification, shipped};
 
 use std::sync::Arc;
 
+/// Sends `email`, counting it as sent or failed under `kind` for `/metrics`
+async fn deliver(mailer: &Mailer, email: Message, kind: &'static str) -> Result<(), String> {
+    let sent = mailer.send(email).await;
+    METRICS.email(kind, sent.is_ok());
+    sent.map(|_| ()).map_err(|e| e.to_string())
+}
+
 pub async fn send_confirmation(user: User, mailer: Arc<Mailer>) -> Result<(), String> {
     let confirmation = confirmation::Confirmation::from(&user);
     let html = SinglePart::html(confirmation.render().unwrap());
@@ -28,10 +35,7 @@ pub async fn send_confirmation(user: User, mailer: Arc<Mailer>) -> Result<(), St
         )
         .unwrap();
 
-    match mailer.send(email).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
-    }
+    deliver(&mailer, email, "confirmation").await
 }
 
 pub async fn send_tracking(shipping: shipped::Shipped, mailer: Arc<Mailer>) -> Result<(), String> {
@@ -49,8 +53,88 @@ pub async fn send_tracking(shipping: shipped::Shipped, mailer: Arc<Mailer>) -> R
         )
         .unwrap();
 
-    match mailer.send(email).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
+    deliver(&mailer, email, "tracking").await
+}
+
+/// Alerts every address in `admins`, a comma separated list. Nothing is sent
+/// if the list is empty
+pub async fn send_low_stock(
+    alert: low_stock::LowStock,
+    admins: &str,
+    mailer: Arc<Mailer>,
+) -> Result<(), String> {
+    let html = SinglePart::html(alert.render().map_err(|e| e.to_string())?);
+    let plaintext = SinglePart::plain(alert.render_plaintext());
+
+    let mut builder = Message::builder()
+        .from("KiggyShop <kiggyshop@gmail.com>".parse().unwrap())
+        .subject(alert.subject());
+    let mut recipients = 0;
+    for admin in admins.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+        builder = builder.to(admin
+            .parse()
+            .map_err(|e| format!("Invalid admin email {admin}: {e}"))?);
+        recipients += 1;
+    }
+    if recipients == 0 {
+        return Ok(());
     }
+
+    let email = builder
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(html)
+                .singlepart(plaintext),
+        )
+        .map_err(|e| e.to_string())?;
+
+    deliver(&mailer, email, "low_stock").await
+}
+
+pub async fn send_notification_confirmation(
+    confirmation: notification::ConfirmNotification,
+    mailer: Arc<Mailer>,
+) -> Result<(), String> {
+    let html = SinglePart::html(confirmation.render().map_err(|e| e.to_string())?);
+    let plaintext = SinglePart::plain(confirmation.render_plaintext());
+
+    let email = Message::builder()
+        .from("KiggyShop <kiggyshop@gmail.com>".parse().unwrap())
+        .to(confirmation
+            .email
+            .parse()
+            .map_err(|e| format!("Invalid email {}: {e}", confirmation.email))?)
+        .subject("Confirm your back in stock alert")
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(html)
+                .singlepart(plaintext),
+        )
+        .map_err(|e| e.to_string())?;
+
+    deliver(&mailer, email, "notification_confirmation").await
+}
+
+pub async fn send_back_in_stock(
+    restock: notification::BackInStock,
+    mailer: Arc<Mailer>,
+) -> Result<(), String> {
+    let html = SinglePart::html(restock.render().map_err(|e| e.to_string())?);
+    let plaintext = SinglePart::plain(restock.render_plaintext());
+
+    let email = Message::builder()
+        .from("KiggyShop <kiggyshop@gmail.com>".parse().unwrap())
+        .to(restock
+            .email
+            .parse()
+            .map_err(|e| format!("Invalid email {}: {e}", restock.email))?)
+        .subject(format!("{} is back in stock!", restock.title))
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(html)
+                .singlepart(plaintext),
+        )
+        .map_err(|e| e.to_string())?;
+
+    deliver(&mailer, email, "back_in_stock").await
 }
@@ -1,42 +1,97 @@
+mod analytics;
 pub mod api;
+mod bots;
+mod catalogue;
+mod cli;
+mod client_ip;
 mod env;
+mod funnel;
+mod geoip;
+mod logging;
 pub mod mail;
+mod monitoring;
+mod request_id;
+mod sales;
 #[cfg(test)]
 mod tests;
-mod utils;
+mod user_agent;
+mod visits;
 
 use crate::api::{
-    order::{delete_order, get_orders, order_shipped},
-    stock::{delete_items, get_item, get_stock, put_item, update_item},
+    analytics::{get_funnel, get_sales, get_visits},
+    cart::quote_cart,
+    catalogue::{export_catalogue, import_catalogue},
+    category::{delete_category, get_categories, put_category, update_category},
+    collection::{
+        delete_collection, get_collection, get_collections, put_collection, update_collection,
+    },
+    image::{delete_image, order_images, upload_images},
+    inventory::{adjust_item, get_movements, get_prices},
+    kind::{get_kinds, put_kind, update_kind},
+    monitoring::get_metrics,
+    notification::{
+        confirm_notification, confirm_notification_page, notify_signup, unsubscribe_notification,
+        unsubscribe_notification_page,
+    },
+    order::{delete_order, get_order, get_orders, order_shipped},
+    stock::{
+        archive_items, delete_items, delete_products, get_product, get_stock, put_item,
+        put_product, search_stock, unarchive_items, update_item, update_product,
+    },
     stripe::{checkout, webhook},
+    tag::{delete_tag, get_tags, put_tag, update_tag},
 };
 
 use env::Env;
+use request_id::RequestId;
 
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::{dev::Service, http::header::HeaderValue, web, App, HttpMessage, HttpServer};
 
 use diesel::{
+    connection::SimpleConnection,
     r2d2::{self, ConnectionManager},
     SqliteConnection,
 };
 use lettre::{transport::smtp::authentication::Credentials, AsyncSmtpTransport, Tokio1Executor};
+use std::time::Instant;
 
 pub type DbPool = r2d2::Pool<r2d2::ConnectionManager<SqliteConnection>>;
 pub type DbConn = r2d2::PooledConnection<ConnectionManager<SqliteConnection>>;
 pub type Mailer = AsyncSmtpTransport<Tokio1Executor>;
 
+/// Makes SQLite wait on locks rather than failing immediately. Visits are
+/// located locally and written straight away, concurrently with the requests
+/// that trigger them
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionOptions;
+
+impl r2d2::CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute("PRAGMA busy_timeout = 5000;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
 const ADDRESS_PORT: (&str, u16) = ("0.0.0.0", 3000);
 pub const ENV: Env = Env::new();
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
-    env_logger::init();
+    logging::init();
+
+    std::fs::create_dir_all(ENV.image_dir)?;
 
     let manager = r2d2::ConnectionManager::<SqliteConnection>::new(ENV.database_url);
     let pool = r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
         .build(manager)
         .expect("Error initializing DB pool");
 
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if !args.is_empty() {
+        return cli::run(&args, &pool);
+    }
+
     let (mail_user, mail_pass) = {
         #[cfg(any(debug_assertions, test))]
         {
@@ -58,27 +113,136 @@ async fn main() -> Result<(), std::io::Error> {
         .credentials(creds)
         .build();
 
-    HttpServer::new(move || {
-        let logger = Logger::default();
+    let trusted_proxies = web::Data::new(
+        ENV.trusted_proxies
+            .parse::<client_ip::TrustedProxies>()
+            .expect("Error parsing TRUSTED_PROXIES"),
+    );
+    let metrics_access = web::Data::new(
+        ENV.metrics_allowed
+            .parse::<monitoring::MetricsAccess>()
+            .expect("Error parsing METRICS_ALLOWED"),
+    );
+    let retention_days = ENV
+        .visit_retention_days
+        .parse::<u32>()
+        .expect("Error parsing VISIT_RETENTION_DAYS");
+    actix_web::rt::spawn(visits::enforce_retention(pool.clone(), retention_days));
+
+    let geoip = web::Data::new(geoip::GeoIp::open(ENV.geoip_database));
+    actix_web::rt::spawn(geoip::GeoIp::watch(geoip.clone().into_inner()));
 
+    HttpServer::new(move || {
         App::new()
-            .wrap(logger)
+            // Times requests for /metrics, by the route they matched
+            .wrap_fn(|req, srv| {
+                let start = Instant::now();
+                let method = req.method().to_string();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await?;
+                    let route = response.request().match_pattern();
+                    monitoring::METRICS.observe_request(
+                        &method,
+                        route.as_deref().unwrap_or(monitoring::UNMATCHED_ROUTE),
+                        response.status().as_u16(),
+                        start.elapsed(),
+                    );
+                    Ok(response)
+                }
+            })
+            // Tags the request and the work it starts with an ID, and logs it
+            .wrap_fn(|req, srv| {
+                let id = RequestId::from_headers(req.headers());
+                req.extensions_mut().insert(id.clone());
+                let start = Instant::now();
+                let client = client_ip::client_ip(req.request());
+                let response = {
+                    let _entered = id.enter();
+                    srv.call(req)
+                };
+                request_id::scope(Some(id.clone()), async move {
+                    let mut response = response.await?;
+                    if let Ok(value) = HeaderValue::from_str(&id.to_string()) {
+                        response.headers_mut().insert(request_id::HEADER, value);
+                    }
+                    logging::log_request(&response, start.elapsed(), client);
+                    Ok(response)
+                })
+            })
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(ENV.clone()))
             .app_data(web::Data::new(mailer.clone()))
+            .app_data(geoip.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(metrics_access.clone())
             .service(
                 web::scope("/api")
+                    // Gives visitors a funnel session on their first request
+                    .wrap_fn(|req, srv| {
+                        let cookie = funnel::start_session(req.request());
+                        let response = srv.call(req);
+                        async move {
+                            let mut response = response.await?;
+                            if let Some(cookie) = cookie {
+                                response.response_mut().add_cookie(&cookie)?;
+                            }
+                            Ok(response)
+                        }
+                    })
                     .service(get_stock)
                     .service(get_orders)
+                    .service(get_order)
                     .service(order_shipped)
                     .service(delete_order)
-                    .service(update_item)
-                    .service(get_item)
+                    .service(search_stock)
+                    .service(get_product)
+                    .service(notify_signup)
+                    .service(confirm_notification_page)
+                    .service(confirm_notification)
+                    .service(unsubscribe_notification_page)
+                    .service(unsubscribe_notification)
+                    .service(put_product)
+                    .service(update_product)
+                    .service(delete_products)
                     .service(put_item)
+                    .service(update_item)
                     .service(delete_items)
+                    .service(archive_items)
+                    .service(unarchive_items)
+                    .service(get_movements)
+                    .service(get_prices)
+                    .service(export_catalogue)
+                    .service(import_catalogue)
+                    .service(get_visits)
+                    .service(get_funnel)
+                    .service(get_sales)
+                    .service(adjust_item)
+                    .service(upload_images)
+                    .service(order_images)
+                    .service(delete_image)
+                    .service(actix_files::Files::new("/images", ENV.image_dir))
+                    .service(get_kinds)
+                    .service(put_kind)
+                    .service(update_kind)
+                    .service(get_categories)
+                    .service(put_category)
+                    .service(update_category)
+                    .service(delete_category)
+                    .service(get_tags)
+                    .service(put_tag)
+                    .service(update_tag)
+                    .service(delete_tag)
+                    .service(get_collections)
+                    .service(get_collection)
+                    .service(put_collection)
+                    .service(update_collection)
+                    .service(delete_collection)
+                    .service(quote_cart)
                     .service(checkout),
             )
             .service(webhook)
+            .service(get_metrics)
     })
     .bind(ADDRESS_PORT)?
     .run()
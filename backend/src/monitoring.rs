@@ -0,0 +1,288 @@
+// Operational metrics, served to Prometheus in its text format. Counts are kept
+// in memory since start up, Prometheus copes with them resetting on restarts
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
+
+use ipnetwork::IpNetwork;
+
+use crate::client_ip;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Upper bounds of the request latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests that matched no route, so scanners probing random
+/// paths don't make a series each
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Networks allowed to scrape `/metrics`, nobody if empty
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricsAccess(Vec<IpNetwork>);
+
+impl FromStr for MetricsAccess {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        client_ip::parse_networks(s).map(MetricsAccess)
+    }
+}
+
+impl MetricsAccess {
+    pub fn is_enabled(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// What became of a Stripe webhook event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WebhookOutcome {
+    Handled,
+    /// Of a type the shop doesn't act on
+    Ignored,
+    Failed,
+    /// Its signature didn't check out
+    Rejected,
+}
+
+impl fmt::Display for WebhookOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WebhookOutcome::Handled => "handled",
+            WebhookOutcome::Ignored => "ignored",
+            WebhookOutcome::Failed => "failed",
+            WebhookOutcome::Rejected => "rejected",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Observations in each bucket and the ones below it
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Connections of the DB pool, IE from r2d2's `Pool::state`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolUsage {
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub max_size: u32,
+}
+
+impl<M: diesel::r2d2::ManageConnection> From<&diesel::r2d2::Pool<M>> for PoolUsage {
+    fn from(pool: &diesel::r2d2::Pool<M>) -> Self {
+        let state = pool.state();
+        Self {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            max_size: pool.max_size(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// By method, route and status
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// By method and route
+    latencies: Mutex<BTreeMap<(String, String), Histogram>>,
+    /// By event type and outcome
+    webhook_events: Mutex<BTreeMap<(String, WebhookOutcome), u64>>,
+    /// By kind of email and whether it was sent
+    emails: Mutex<BTreeMap<(&'static str, bool), u64>>,
+    checkout_link_failures: AtomicU64,
+}
+
+/// Metrics are only ever added to, so ones a panicking thread left behind are
+/// still good
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Escapes a label value, IE quotes and backslashes in routes
+fn label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Writes a metric's `# HELP` and `# TYPE` lines
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+impl Metrics {
+    /// `route` is the pattern the request matched, IE "/api/stock/{id}"
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        *lock(&self.requests)
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        lock(&self.latencies)
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// `event_type` is Stripe's, IE "checkout.session.completed"
+    pub fn webhook_event(&self, event_type: &str, outcome: WebhookOutcome) {
+        *lock(&self.webhook_events)
+            .entry((event_type.to_string(), outcome))
+            .or_default() += 1;
+    }
+
+    /// `kind` names the email, IE "confirmation"
+    pub fn email(&self, kind: &'static str, sent: bool) {
+        *lock(&self.emails).entry((kind, sent)).or_default() += 1;
+    }
+
+    pub fn checkout_link_failed(&self) {
+        self.checkout_link_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Every metric in Prometheus' text exposition format
+    pub fn render(&self, pool: PoolUsage) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "kiggyserve_http_requests_total",
+            "counter",
+            "HTTP requests by method, route and status.",
+        );
+        for ((method, route, status), count) in lock(&self.requests).iter() {
+            let _ = writeln!(
+                out,
+                "kiggyserve_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{status}\"}} {count}",
+                label(method),
+                label(route),
+            );
+        }
+
+        header(
+            &mut out,
+            "kiggyserve_http_request_duration_seconds",
+            "histogram",
+            "HTTP request latency by method and route.",
+        );
+        for ((method, route), histogram) in lock(&self.latencies).iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", label(method), label(route));
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "kiggyserve_http_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "kiggyserve_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "kiggyserve_http_request_duration_seconds_sum{{{labels}}} {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "kiggyserve_http_request_duration_seconds_count{{{labels}}} {}",
+                histogram.count
+            );
+        }
+
+        for (name, help, value) in [
+            (
+                "kiggyserve_db_pool_connections",
+                "Open DB connections, in use or idle.",
+                pool.connections,
+            ),
+            (
+                "kiggyserve_db_pool_idle_connections",
+                "Idle DB connections.",
+                pool.idle_connections,
+            ),
+            (
+                "kiggyserve_db_pool_max_connections",
+                "Most DB connections the pool opens.",
+                pool.max_size,
+            ),
+        ] {
+            header(&mut out, name, "gauge", help);
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        header(
+            &mut out,
+            "kiggyserve_webhook_events_total",
+            "counter",
+            "Stripe webhook events by type and outcome.",
+        );
+        for ((event_type, outcome), count) in lock(&self.webhook_events).iter() {
+            let _ = writeln!(
+                out,
+                "kiggyserve_webhook_events_total{{type=\"{}\",outcome=\"{outcome}\"}} {count}",
+                label(event_type),
+            );
+        }
+
+        header(
+            &mut out,
+            "kiggyserve_emails_total",
+            "counter",
+            "Emails by kind and whether they were sent or failed.",
+        );
+        for ((kind, sent), count) in lock(&self.emails).iter() {
+            let outcome = if *sent { "sent" } else { "failed" };
+            let _ = writeln!(
+                out,
+                "kiggyserve_emails_total{{kind=\"{kind}\",outcome=\"{outcome}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "kiggyserve_checkout_link_failures_total",
+            "counter",
+            "Checkouts whose Stripe checkout session couldn't be created.",
+        );
+        let _ = writeln!(
+            out,
+            "kiggyserve_checkout_link_failures_total {}",
+            self.checkout_link_failures.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
@@ -0,0 +1,129 @@
+// Correlation IDs tying a request's logs together, including those of the DB
+// calls and tasks it starts. The ID is kept in a thread local while the
+// request's work runs, so it follows work moved to other threads by `block` and
+// `spawn` rather than `web::block` and `rt::spawn`
+use std::{
+    cell::RefCell,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    error::BlockingError,
+    http::header::{HeaderMap, HeaderName},
+    rt, web,
+};
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming ID that's kept, longer ones are replaced
+const MAX_LENGTH: usize = 64;
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestId>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(Arc<str>);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4().simple().to_string().into())
+    }
+
+    /// The ID a proxy or client sent, if it's short and printable ASCII, so it
+    /// can't forge log lines. Otherwise a new one
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(HEADER)
+            .and_then(|id| id.to_str().ok())
+            .map(str::trim)
+            .filter(|id| {
+                !id.is_empty() && id.len() <= MAX_LENGTH && id.chars().all(|c| c.is_ascii_graphic())
+            })
+            .map(|id| Self(id.into()))
+            .unwrap_or_default()
+    }
+
+    /// The ID of the request whose work is running on this thread
+    pub fn current() -> Option<RequestId> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Makes this the current ID until the guard is dropped
+    pub fn enter(&self) -> Entered {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        Entered { previous }
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Restores the previous request ID when dropped
+pub struct Entered {
+    previous: Option<RequestId>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+/// A future that's polled with its request's ID current
+pub struct InRequest<F> {
+    id: Option<RequestId>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for InRequest<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _entered = self.id.as_ref().map(RequestId::enter);
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+/// Runs `future` as part of the request `id`, nothing changes if `None`
+pub fn scope<F: Future>(id: Option<RequestId>, future: F) -> InRequest<F> {
+    InRequest {
+        id,
+        inner: Box::pin(future),
+    }
+}
+
+/// `rt::spawn`, keeping the current request's ID
+pub fn spawn<F>(future: F) -> rt::task::JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    rt::spawn(scope(RequestId::current(), future))
+}
+
+/// `web::block`, keeping the current request's ID
+pub fn block<F, R>(f: F) -> impl Future<Output = Result<R, BlockingError>>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let id = RequestId::current();
+    web::block(move || {
+        let _entered = id.as_ref().map(RequestId::enter);
+        f()
+    })
+}
@@ -0,0 +1,296 @@
+// Sales reporting for the admin dashboard, from orders as they were placed and
+// their lines as they were sold
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use chrono::NaiveDate;
+use diesel::{
+    prelude::*,
+    sql_types::{Date, Integer, Text},
+};
+use model::{
+    analytics::Interval,
+    catalogue::Format,
+    sales::{CustomerSales, ItemSales, KindSales, PeriodSales, SalesReport, Takings},
+    ItemId, Quantity,
+};
+use serde::Serialize;
+
+/// How many customers reports list
+pub const TOP_CUSTOMERS: usize = 10;
+
+#[derive(QueryableByName)]
+struct OrderRow {
+    #[diesel(sql_type = Date)]
+    day: NaiveDate,
+    #[diesel(sql_type = Text)]
+    email: String,
+    #[diesel(sql_type = Integer)]
+    total: i32,
+    #[diesel(sql_type = Integer)]
+    refunded: i32,
+    #[diesel(sql_type = Integer)]
+    shipping: i32,
+    #[diesel(sql_type = Integer)]
+    tax: i32,
+}
+
+impl From<&OrderRow> for Takings {
+    fn from(row: &OrderRow) -> Self {
+        let revenue = row.total.max(0) as u32;
+        let refunded = row.refunded.max(0) as u32;
+        Takings {
+            orders: 1,
+            revenue,
+            refunded,
+            net_revenue: revenue.saturating_sub(refunded),
+            shipping: row.shipping.max(0) as u32,
+            tax: row.tax.max(0) as u32,
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct ItemRow {
+    #[diesel(sql_type = Integer)]
+    item_id: i32,
+    #[diesel(sql_type = Text)]
+    title: String,
+    /// '' for items deleted before kinds were kept
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Integer)]
+    units: i32,
+    #[diesel(sql_type = Integer)]
+    revenue: i32,
+}
+
+#[derive(QueryableByName)]
+struct KindRow {
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Integer)]
+    units: i32,
+    #[diesel(sql_type = Integer)]
+    revenue: i32,
+}
+
+/// Sales of orders placed between `from` and `to` inclusive
+pub fn report(
+    conn: &mut SqliteConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+    interval: Interval,
+) -> QueryResult<SalesReport> {
+    let orders = diesel::sql_query(
+        "select date(created) as day, lower(email) as email, total, refunded, shipping, tax \
+         from orders where date(created) between ? and ?",
+    )
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load::<OrderRow>(conn)?;
+
+    // Lines keep the title and kind they were sold under, the latest one
+    // describes the item
+    let items = diesel::sql_query(
+        "select carts.item_id, \
+         (select c.title from carts c join orders o on o.id = c.order_id \
+          where c.item_id = carts.item_id order by o.created desc, c.id desc limit 1) as title, \
+         (select c.kind from carts c join orders o on o.id = c.order_id \
+          where c.item_id = carts.item_id order by o.created desc, c.id desc limit 1) as kind, \
+         sum(carts.quantity) as units, sum(carts.line_total) as revenue \
+         from carts join orders on orders.id = carts.order_id \
+         where date(orders.created) between ? and ? \
+         group by carts.item_id",
+    )
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load::<ItemRow>(conn)?;
+
+    // Each line counts towards the kind it was sold as, even if its item has
+    // been moved to another kind since
+    let kinds = diesel::sql_query(
+        "select carts.kind, sum(carts.quantity) as units, sum(carts.line_total) as revenue \
+         from carts join orders on orders.id = carts.order_id \
+         where date(orders.created) between ? and ? \
+         group by carts.kind",
+    )
+    .bind::<Date, _>(from)
+    .bind::<Date, _>(to)
+    .load::<KindRow>(conn)?;
+
+    Ok(summarize(&orders, items, kinds, from, to, interval))
+}
+
+fn summarize(
+    orders: &[OrderRow],
+    items: Vec<ItemRow>,
+    kinds: Vec<KindRow>,
+    from: NaiveDate,
+    to: NaiveDate,
+    interval: Interval,
+) -> SalesReport {
+    let mut periods = interval
+        .periods(from, to)
+        .into_iter()
+        .map(|start| (start, Takings::default()))
+        .collect::<BTreeMap<NaiveDate, Takings>>();
+    let mut total = Takings::default();
+    let mut refunds = 0;
+    let mut customers = BTreeMap::<&str, Takings>::new();
+    for order in orders {
+        let takings = Takings::from(order);
+        if takings.refunded > 0 {
+            refunds += 1;
+        }
+
+        total += takings;
+        *periods.entry(interval.period_start(order.day)).or_default() += takings;
+        *customers.entry(&order.email).or_default() += takings;
+    }
+
+    let mut items = items
+        .into_iter()
+        .map(|row| ItemSales {
+            item_id: row.item_id as ItemId,
+            title: row.title,
+            kind: row.kind,
+            units: row.units.max(0) as Quantity,
+            revenue: row.revenue.max(0) as u32,
+        })
+        .collect::<Vec<ItemSales>>();
+    items.sort_by_key(|item| (Reverse(item.revenue), item.item_id));
+
+    let mut kinds = kinds
+        .into_iter()
+        .map(|row| KindSales {
+            kind: row.kind,
+            units: row.units.max(0) as Quantity,
+            revenue: row.revenue.max(0) as u32,
+        })
+        .collect::<Vec<KindSales>>();
+    kinds.sort_by(|a, b| (Reverse(a.revenue), &a.kind).cmp(&(Reverse(b.revenue), &b.kind)));
+
+    let mut customers = customers.into_iter().collect::<Vec<(&str, Takings)>>();
+    customers.sort_by_key(|(_, takings)| Reverse(takings.net_revenue));
+
+    SalesReport {
+        from,
+        to,
+        interval,
+        total,
+        average_order_value: total.revenue.checked_div(total.orders).unwrap_or_default(),
+        refunds,
+        periods: periods
+            .into_iter()
+            .map(|(start, takings)| PeriodSales { start, takings })
+            .collect(),
+        items,
+        kinds,
+        customers: customers
+            .into_iter()
+            .take(TOP_CUSTOMERS)
+            .map(|(email, takings)| CustomerSales {
+                email: email.to_string(),
+                orders: takings.orders,
+                revenue: takings.revenue,
+                refunded: takings.refunded,
+            })
+            .collect(),
+    }
+}
+
+/// One row per count, `breakdown` says what `name` is: "total", the interval
+/// for periods, "average_order_value", "refunds", "item", "kind" or
+/// "customer". Columns that don't apply to a breakdown are empty
+#[derive(Debug, Default, Serialize)]
+struct CsvRow<'a> {
+    breakdown: &'a str,
+    name: String,
+    /// Only filled in for items
+    item_id: Option<ItemId>,
+    orders: Option<u32>,
+    units: Option<Quantity>,
+    revenue: Option<u32>,
+    refunded: Option<u32>,
+    net_revenue: Option<u32>,
+    shipping: Option<u32>,
+    tax: Option<u32>,
+}
+
+impl<'a> CsvRow<'a> {
+    fn takings(breakdown: &'a str, name: String, takings: Takings) -> Self {
+        Self {
+            breakdown,
+            name,
+            orders: Some(takings.orders),
+            revenue: Some(takings.revenue),
+            refunded: Some(takings.refunded),
+            net_revenue: Some(takings.net_revenue),
+            shipping: Some(takings.shipping),
+            tax: Some(takings.tax),
+            ..Default::default()
+        }
+    }
+}
+
+pub fn serialize(report: &SalesReport, format: Format) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Json => serde_json::to_vec_pretty(report).map_err(|e| format!("{e}")),
+        Format::Csv => {
+            let interval = report.interval.to_string();
+            let rows =
+                [
+                    CsvRow::takings("total", String::new(), report.total),
+                    CsvRow {
+                        breakdown: "average_order_value",
+                        revenue: Some(report.average_order_value),
+                        ..Default::default()
+                    },
+                    CsvRow {
+                        breakdown: "refunds",
+                        orders: Some(report.refunds),
+                        refunded: Some(report.total.refunded),
+                        ..Default::default()
+                    },
+                ]
+                .into_iter()
+                .chain(report.periods.iter().map(|period| {
+                    CsvRow::takings(&interval, period.start.to_string(), period.takings)
+                }))
+                .chain(report.items.iter().map(|item| CsvRow {
+                    breakdown: "item",
+                    name: item.title.clone(),
+                    item_id: Some(item.item_id),
+                    units: Some(item.units),
+                    revenue: Some(item.revenue),
+                    ..Default::default()
+                }))
+                .chain(report.kinds.iter().map(|kind| CsvRow {
+                    breakdown: "kind",
+                    name: kind.kind.clone(),
+                    units: Some(kind.units),
+                    revenue: Some(kind.revenue),
+                    ..Default::default()
+                }))
+                .chain(report.customers.iter().map(|customer| CsvRow {
+                    breakdown: "customer",
+                    name: customer.email.clone(),
+                    orders: Some(customer.orders),
+                    revenue: Some(customer.revenue),
+                    refunded: Some(customer.refunded),
+                    net_revenue: Some(customer.revenue.saturating_sub(customer.refunded)),
+                    ..Default::default()
+                }));
+
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in rows {
+                writer
+                    .serialize(row)
+                    .map_err(|e| format!("Cannot write CSV: {e}"))?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| format!("Cannot write CSV: {e}"))
+        }
+    }
+}
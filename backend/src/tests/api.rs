@@ -1,18 +1,56 @@
 #[cfg(test)]
 mod tests {
-    use diesel::{r2d2::ConnectionManager, QueryDsl, RunQueryDsl};
-    use std::collections::HashMap;
+    use diesel::{
+        r2d2::ConnectionManager, ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper,
+    };
 
     use crate::{
-        api::{order::delete_order, stock::get_stock},
+        api::{
+            analytics::{get_funnel, get_sales, get_visits},
+            cart::{customer_limit_warnings, quote_cart},
+            catalogue::{export_catalogue, import_catalogue},
+            category::put_category,
+            image::upload_images,
+            inventory::{adjust_item, get_movements, get_prices},
+            kind::update_kind,
+            monitoring::get_metrics,
+            notification::{
+                confirm_notification, confirm_notification_page, due_notifications, notify_signup,
+                unsubscribe_notification, unsubscribe_notification_page,
+            },
+            order::{delete_order, flag_order, get_order, get_orders, insert_order, record_refund},
+            stock::{
+                archive_items, dec_items, delete_items, delete_products, get_product, get_stock,
+                put_product, search_stock, unarchive_items, update_item,
+            },
+            stripe,
+        },
+        env::Env,
         tests::test_db,
+        ConnectionOptions, Mailer,
+    };
+    use actix_web::{
+        http::{header, StatusCode},
+        test, web, App,
     };
-    use actix_web::{test, web, App};
     use diesel::SqliteConnection;
     use model::{
-        item::{Item, NewItem},
-        order::{NewOrder, Order},
-        ItemId,
+        analytics::{
+            CountryVisits, DeviceVisits, Interval, PeriodVisits, ReferrerVisits, StateVisits,
+            Visits, VisitsReport,
+        },
+        catalogue::{Action, ImportReport},
+        funnel::{FunnelReport, FunnelStep, ProductFunnel, Stage},
+        image::Image,
+        inventory::{ItemHistory, LowStock, Reason},
+        order::{NewOrder, Order, OrderDetail, OrderLine, OrderPayment, TableOrder},
+        price::PriceHistory,
+        product::{ListedProduct, Product, ProductId, StockPage},
+        quote::{Quote, Warning},
+        sales::{CustomerSales, ItemSales, KindSales, PeriodSales, SalesReport, Takings},
+        search::SearchResults,
+        user::{DailyUniqueVisitors, DailyVisits, Device},
+        CartMap,
     };
 
     fn create_db_pool() -> (
@@ -25,33 +63,1815 @@ mod tests {
         (
             db,
             r2d2::Pool::builder()
+                .connection_customizer(Box::new(ConnectionOptions))
                 .build(manager)
                 .expect("INVALID DB URL // DB POOL CANNOT BE BUILT"),
         )
     }
 
+    /// Never connects, emails sent in the background just fail
+    fn test_mailer() -> Mailer {
+        Mailer::builder_dangerous("localhost").build()
+    }
+
     #[actix_web::test]
     async fn test_get_stock() {
         let (db, pool) = create_db_pool();
 
-        let stock = include_str!("../../stock.json");
-        let stock: Vec<Item> = serde_json::from_str(stock).expect("Cannot deserialize stock.json");
-        let stock: Vec<NewItem> = stock.iter().map(NewItem::from).collect();
-
         let mut conn = db.connection();
-        diesel::insert_into(model::schema::stock::table)
-            .values(stock)
-            .execute(&mut conn)
-            .expect("Cannot insert stock.json into DB");
+        test_db::insert_stock(&mut conn);
         let app =
             test::init_service(App::new().app_data(web::Data::new(pool)).service(get_stock)).await;
         let req = test::TestRequest::get().uri("/stock").to_request();
         let dummy = test::call_service(&app, req).await;
         assert!(dummy.status().is_success());
         let req = test::TestRequest::get().uri("/stock").to_request();
-        let _: HashMap<ItemId, Item> = test::try_call_and_read_body_json(&app, req)
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize body");
+        assert!(stock
+            .products
+            .iter()
+            .all(|listed| listed.product.variants.len() == 1));
+    }
+
+    #[actix_web::test]
+    async fn test_stock_pages() {
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        let stock_len = test_db::insert_stock(&mut conn);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(put_product)
+                .service(get_stock),
+        )
+        .await;
+
+        let mut titles = Vec::new();
+        for page in 1..=3 {
+            let req = test::TestRequest::get()
+                .uri(&format!("/stock?sort=title&limit=5&page={page}"))
+                .to_request();
+            let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+                .await
+                .expect("Cannot deserialize body");
+            assert_eq!(stock.total as usize, stock_len);
+            titles.extend(
+                stock
+                    .products
+                    .into_iter()
+                    .map(|listed| listed.product.title),
+            );
+        }
+        let mut sorted = titles.clone();
+        sorted.sort();
+        assert_eq!(titles.len(), stock_len);
+        assert_eq!(titles, sorted);
+
+        let req = test::TestRequest::get().uri("/stock").to_request();
+        let response = test::call_service(&app, req).await;
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .expect("Missing ETag")
+            .clone();
+        assert!(response.headers().contains_key(header::LAST_MODIFIED));
+
+        let req = test::TestRequest::get()
+            .uri("/stock")
+            .insert_header((header::IF_NONE_MATCH, etag.clone()))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "aardvark",
+                "description": "",
+                "variants": [{ "sku": "AARD", "kind": "SmallPrint", "quantity": 0 }]
+            }))
+            .to_request();
+        let aardvark: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+
+        let req = test::TestRequest::get()
+            .uri("/stock")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Stale ETag was not refreshed");
+        assert_eq!(stock.products[0].id, aardvark);
+
+        let req = test::TestRequest::get()
+            .uri("/stock?in_stock=true&sort=price")
+            .to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize body");
+        assert_eq!(stock.total as usize, stock_len);
+        assert!(stock.products.iter().all(|listed| listed.id != aardvark));
+
+        // A drop being released changes the listing without anything being edited
+        let released = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(2);
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "aardwolf",
+                "description": "",
+                "variants": [{
+                    "sku": "AARW",
+                    "kind": "SmallPrint",
+                    "quantity": 1,
+                    "available_from": released.format("%Y-%m-%dT%H:%M:%S").to_string()
+                }]
+            }))
+            .to_request();
+        let aardwolf: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let on_sale = |stock: &StockPage| {
+            stock
+                .products
+                .iter()
+                .find(|listed| listed.id == aardwolf)
+                .map(|listed| listed.product.variants.values().all(|item| item.on_sale))
+        };
+
+        let req = test::TestRequest::get()
+            .uri("/stock?sort=newest")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        let stock: StockPage = test::read_body_json(response).await;
+        assert_eq!(on_sale(&stock), Some(false));
+
+        actix_web::rt::time::sleep(std::time::Duration::from_secs(3)).await;
+        let req = test::TestRequest::get()
+            .uri("/stock?sort=newest")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("ETag was not refreshed by the drop's release");
+        assert_eq!(on_sale(&stock), Some(true));
+    }
+
+    #[actix_web::test]
+    async fn test_cart_quote() {
+        let (db, pool) = create_db_pool();
+
+        let mut conn = db.connection();
+        test_db::insert_stock(&mut conn);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(quote_cart),
+        )
+        .await;
+
+        let cart = CartMap::from([(1, 2), (8, 21), (404, 1)]);
+        let req = test::TestRequest::post()
+            .uri("/cart/quote")
+            .set_json(&cart)
+            .to_request();
+        let quote: Quote = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize quote");
+
+        assert_eq!(quote.lines.len(), 2);
+        assert_eq!(quote.lines[0].total, 2 * 20_00);
+        assert_eq!(quote.subtotal, 2 * 20_00 + 21 * 7_00);
+        assert_eq!(
+            quote.total,
+            quote.subtotal + quote.tax + quote.shipping().amount
+        );
+        assert_eq!(
+            quote.warnings,
+            vec![
+                Warning::InsufficientStock {
+                    item: 8,
+                    requested: 21,
+                    available: 20
+                },
+                Warning::NotFound { item: 404 }
+            ]
+        );
+        assert!(!quote.is_orderable());
+
+        // Totals that can't be charged are warned about rather than overflowing
+        for (cart, warning) in [
+            (
+                CartMap::from([(1, 3_000_000)]),
+                Warning::LineTooLarge {
+                    item: 1,
+                    requested: 3_000_000,
+                },
+            ),
+            (
+                CartMap::from([(1, 2_000_000), (8, 500_000)]),
+                Warning::TotalTooLarge,
+            ),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/cart/quote")
+                .set_json(&cart)
+                .to_request();
+            let quote: Quote = test::try_call_and_read_body_json(&app, req)
+                .await
+                .expect("Cannot deserialize quote");
+            assert_eq!(quote.warnings.last(), Some(&warning));
+            assert!(!quote.is_orderable());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_put_product_variants() {
+        let (_db, pool) = create_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(put_product)
+                .service(get_product)
+                .service(quote_cart),
+        )
+        .await;
+
+        let product = serde_json::json!({
+            "title": "moth",
+            "description": "moth print",
+            "variants": [
+                { "sku": "MOTH-A4", "options": { "size": "A4" }, "kind": "BigPrint", "quantity": 3 },
+                { "sku": "MOTH-A6", "options": { "size": "A6" }, "kind": "SmallPrint", "price": 5_00, "quantity": 10 }
+            ]
+        });
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(&product)
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/stock/{product_id}"))
+            .to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        assert_eq!(product.variants.len(), 2);
+
+        let (&small, small_variant) = product
+            .variants
+            .iter()
+            .find(|(_, variant)| variant.sku == "MOTH-A6")
+            .expect("Variant missing");
+        assert_eq!(small_variant.price, 5_00);
+
+        let req = test::TestRequest::post()
+            .uri("/cart/quote")
+            .set_json(CartMap::from([(small, 2)]))
+            .to_request();
+        let quote: Quote = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize quote");
+        assert_eq!(quote.lines[0].title, "moth (A6)");
+        assert_eq!(quote.subtotal, 2 * 5_00);
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "moth 2",
+                "description": "",
+                "variants": [{ "sku": "X", "kind": "Sticker", "quantity": 1 }]
+            }))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_stock_filters() {
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        let stock_len = test_db::insert_stock(&mut conn);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(put_category)
+                .service(put_product)
+                .service(get_stock),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/categories")
+            .set_json(serde_json::json!({ "name": "buttons" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "frog",
+                "description": "frog button",
+                "category": "buttons",
+                "tags": ["frogs", "new"],
+                "variants": [{ "sku": "FROG", "kind": "Button", "quantity": 5 }]
+            }))
+            .to_request();
+        let frog: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+
+        for uri in [
+            "/stock?category=buttons",
+            "/stock?tag=frogs",
+            "/stock?kind=Button&tag=new",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+                .await
+                .expect("Cannot deserialize body");
+            assert_eq!(stock.products.len(), 1, "{uri}");
+            let ListedProduct { id, product } = &stock.products[0];
+            assert_eq!(*id, frog);
+            assert_eq!(product.category.as_deref(), Some("buttons"));
+            assert_eq!(product.tags, vec!["frogs", "new"]);
+        }
+
+        let req = test::TestRequest::get().uri("/stock?tag=none").to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize body");
+        assert!(stock.products.is_empty());
+
+        let req = test::TestRequest::get().uri("/stock").to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize body");
+        assert_eq!(stock.total as usize, stock_len + 1);
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "toad",
+                "description": "",
+                "category": "nonexistent"
+            }))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_search_stock() {
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        test_db::insert_stock(&mut conn);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(put_product)
+                .service(search_stock),
+        )
+        .await;
+
+        // "spiral", "spirit" and "spore" all start with "sp"
+        let req = test::TestRequest::get()
+            .uri("/stock/search?q=spi")
+            .to_request();
+        let results: SearchResults = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize search results");
+        assert_eq!(results.total, 2);
+        assert!(results
+            .hits
+            .iter()
+            .all(|hit| hit.title.starts_with("<mark>spi")));
+
+        let req = test::TestRequest::get()
+            .uri("/stock/search?q=sp&per_page=2&page=2")
+            .to_request();
+        let results: SearchResults = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize search results");
+        assert_eq!((results.total, results.page, results.hits.len()), (3, 2, 1));
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "bat",
+                "description": "a bat print",
+                "tags": ["spooky"]
+            }))
+            .to_request();
+        let bat: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+
+        // Tags are indexed as they are set
+        let req = test::TestRequest::get()
+            .uri("/stock/search?q=spooky")
+            .to_request();
+        let results: SearchResults = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize search results");
+        assert_eq!(results.hits.len(), 1);
+        assert_eq!(results.hits[0].id, bat);
+        assert_eq!(results.hits[0].product.tags, vec!["spooky"]);
+
+        // FTS5 syntax is searched for literally rather than rejected
+        let req = test::TestRequest::get()
+            .uri("/stock/search?q=%22cat%20OR%20(")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success());
+
+        let req = test::TestRequest::get()
+            .uri("/stock/search?q=%20")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_inventory_ledger() {
+        let (db, pool) = create_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(test_mailer()))
+                .service(put_product)
+                .service(get_product)
+                .service(update_item)
+                .service(get_movements)
+                .service(adjust_item)
+                .service(delete_order),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .insert_header(("X-Admin-User", "kiggy"))
+            .set_json(serde_json::json!({
+                "title": "owl",
+                "description": "",
+                "variants": [{ "sku": "OWL", "kind": "SmallPrint", "quantity": 3 }]
+            }))
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let req = test::TestRequest::get()
+            .uri(&format!("/stock/{product_id}"))
+            .to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        let item_id = *product.variants.keys().next().unwrap();
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/variants/{item_id}"))
+            .set_json(serde_json::json!({ "sku": "OWL", "kind": "SmallPrint", "quantity": 5 }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/variants/{item_id}/movements"))
+            .set_json(serde_json::json!({ "delta": -1, "reason": "Damage", "note": "bent" }))
+            .to_request();
+        let quantity: i32 = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot adjust item");
+        assert_eq!(quantity, 4);
+
+        for adjustment in [
+            serde_json::json!({ "delta": -10, "reason": "Damage" }),
+            serde_json::json!({ "delta": 1, "reason": "Damage" }),
+            serde_json::json!({ "delta": -1, "reason": "Sale" }),
+        ] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/variants/{item_id}/movements"))
+                .set_json(&adjustment)
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{adjustment}");
+        }
+
+        // Cancelling an unshipped order returns its items
+        let mut conn = db.connection();
+        let order_id = diesel::insert_into(model::schema::orders::table)
+            .values(NewOrder {
+                name: "",
+                total: 0,
+                email: "",
+                shipped: false,
+            })
+            .returning(model::schema::orders::id)
+            .get_result::<i32>(&mut conn)
+            .expect("Cannot insert order");
+        diesel::insert_into(model::schema::carts::table)
+            .values(model::cart::NewCart::new(order_id, item_id as i32, 2, 700, "", "").unwrap())
+            .execute(&mut conn)
+            .expect("Cannot insert cart");
+        let req = test::TestRequest::delete()
+            .uri(&format!("/orders/{order_id}"))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        assert_eq!(
+            model::schema::carts::table
+                .filter(model::schema::carts::order_id.eq(order_id))
+                .count()
+                .get_result::<i64>(&mut conn),
+            Ok(0)
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/variants/{item_id}/movements"))
+            .to_request();
+        let history: ItemHistory = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch item history");
+        assert_eq!(
+            history
+                .movements
+                .iter()
+                .map(|movement| (movement.reason, movement.delta))
+                .collect::<Vec<_>>(),
+            vec![
+                (Reason::Restock, 3),
+                (Reason::Correction, 2),
+                (Reason::Damage, -1),
+                (Reason::CancellationReturn, 2),
+            ]
+        );
+        assert_eq!(history.movements[3].order, Some(order_id as u32));
+        assert_eq!(history.quantity, 6);
+        assert!(history.is_consistent());
+    }
+
+    #[actix_web::test]
+    async fn test_low_stock() {
+        let (_db, pool) = create_db_pool();
+        let stock_pool = pool.clone();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(put_product)
+                .service(get_product)
+                .service(quote_cart),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "fox",
+                "description": "",
+                "variants": [
+                    { "sku": "FOX-S", "kind": "SmallPrint", "quantity": 3, "low_stock_threshold": 2 },
+                    { "sku": "FOX-B", "kind": "BigPrint", "quantity": 1 }
+                ]
+            }))
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let product_request = || {
+            test::TestRequest::get()
+                .uri(&format!("/stock/{product_id}"))
+                .to_request()
+        };
+        let product: Product = test::try_call_and_read_body_json(&app, product_request())
+            .await
+            .expect("Cannot fetch product");
+        let id_of = |sku: &str| {
+            *product
+                .variants
+                .iter()
+                .find(|(_, item)| item.sku == sku)
+                .unwrap()
+                .0
+        };
+        let (small, big) = (id_of("FOX-S"), id_of("FOX-B"));
+
+        let sale = |quantities: Vec<(model::ItemId, u32)>| {
+            std::sync::Arc::new(
+                quantities
+                    .into_iter()
+                    .map(|(id, quantity)| {
+                        (
+                            id,
+                            stripe::Item {
+                                title: "fox".to_string(),
+                                price: 0,
+                                quantity,
+                                ships_on: None,
+                            },
+                        )
+                    })
+                    .collect(),
+            )
+        };
+
+        // Only the sale that crosses a threshold alerts, the kind's threshold
+        // of 0 alerts when an item sells out
+        let mut low_stock = dec_items(
+            sale(vec![(small, 1), (big, 1)]),
+            1,
+            stock_pool.get().unwrap(),
+        )
+        .await
+        .expect("Cannot record sale");
+        low_stock.sort_by_key(|item| item.item);
+        let mut expected = vec![
+            LowStock {
+                item: small,
+                title: "fox".to_string(),
+                sku: "FOX-S".to_string(),
+                quantity: 2,
+                threshold: 2,
+            },
+            LowStock {
+                item: big,
+                title: "fox".to_string(),
+                sku: "FOX-B".to_string(),
+                quantity: 0,
+                threshold: 0,
+            },
+        ];
+        expected.sort_by_key(|item| item.item);
+        assert_eq!(low_stock, expected);
+
+        let low_stock = dec_items(sale(vec![(small, 1)]), 2, stock_pool.get().unwrap())
+            .await
+            .expect("Cannot record sale");
+        assert!(low_stock.is_empty());
+
+        // Sold out items are still listed, but cannot be ordered
+        let product: Product = test::try_call_and_read_body_json(&app, product_request())
+            .await
+            .expect("Cannot fetch product");
+        assert!(product.variants[&big].sold_out);
+        assert_eq!(product.variants[&big].quantity, 0);
+        assert!(!product.variants[&small].sold_out);
+
+        let req = test::TestRequest::post()
+            .uri("/cart/quote")
+            .set_json(CartMap::from([(big, 1)]))
+            .to_request();
+        let quote: Quote = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot quote cart");
+        assert_eq!(
+            quote.warnings,
+            vec![Warning::InsufficientStock {
+                item: big,
+                requested: 1,
+                available: 0
+            }]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_back_in_stock_signup() {
+        let (db, pool) = create_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(test_mailer()))
+                .service(put_product)
+                .service(get_product)
+                .service(notify_signup)
+                .service(confirm_notification_page)
+                .service(confirm_notification)
+                .service(unsubscribe_notification_page)
+                .service(unsubscribe_notification),
+        )
+        .await;
+
+        let mut product_ids = Vec::new();
+        for (title, sku) in [("hare", "HARE"), ("crow", "CROW")] {
+            let req = test::TestRequest::put()
+                .uri("/stock")
+                .set_json(serde_json::json!({
+                    "title": title,
+                    "description": "",
+                    "variants": [
+                        { "sku": format!("{sku}-S"), "kind": "SmallPrint", "quantity": 0 },
+                        { "sku": format!("{sku}-B"), "kind": "BigPrint", "quantity": 0 }
+                    ]
+                }))
+                .to_request();
+            let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+                .await
+                .expect("Cannot create product");
+            product_ids.push(product_id);
+        }
+        let (hare, crow) = (product_ids[0], product_ids[1]);
+        let req = test::TestRequest::get()
+            .uri(&format!("/stock/{hare}"))
+            .to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        let id_of = |sku: &str| {
+            *product
+                .variants
+                .iter()
+                .find(|(_, item)| item.sku == sku)
+                .unwrap()
+                .0
+        };
+        let (small, big) = (id_of("HARE-S"), id_of("HARE-B"));
+
+        for (product_id, body, status) in [
+            (
+                hare,
+                serde_json::json!({ "email": "not an email" }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                404,
+                serde_json::json!({ "email": "a@kiggy.shop" }),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                crow,
+                serde_json::json!({ "email": "a@kiggy.shop", "item": small }),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                hare,
+                serde_json::json!({ "email": "a@kiggy.shop" }),
+                StatusCode::ACCEPTED,
+            ),
+            (
+                hare,
+                serde_json::json!({ "email": " a@kiggy.shop " }),
+                StatusCode::ACCEPTED,
+            ),
+            (
+                hare,
+                serde_json::json!({ "email": "b@kiggy.shop", "item": big }),
+                StatusCode::ACCEPTED,
+            ),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/stock/{product_id}/notify"))
+                .set_json(&body)
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                status,
+                "{body}"
+            );
+        }
+
+        let mut conn = db.connection();
+        let mut signups = model::schema::stock_notifications::table
+            .select(model::notification::TableNotification::as_select())
+            .order(model::schema::stock_notifications::email)
+            .get_results::<model::notification::TableNotification>(&mut conn)
+            .expect("Cannot fetch signups");
+        // Signing up twice doesn't duplicate the signup
+        assert_eq!(signups.len(), 2);
+        let (product_signup, item_signup) = (signups.remove(0), signups.remove(0));
+
+        // Repeat signups only resend the confirmation once the last one is a
+        // while old
+        let resend = || {
+            test::TestRequest::post()
+                .uri(&format!("/stock/{hare}/notify"))
+                .set_json(serde_json::json!({ "email": "a@kiggy.shop" }))
+                .to_request()
+        };
+        let sent_at = |conn: &mut SqliteConnection| {
+            model::schema::stock_notifications::table
+                .find(product_signup.id)
+                .select(model::schema::stock_notifications::confirmation_sent)
+                .first::<Option<chrono::NaiveDateTime>>(conn)
+                .unwrap()
+                .unwrap()
+        };
+        let first_sent = sent_at(&mut conn);
+        assert_eq!(
+            test::call_service(&app, resend()).await.status(),
+            StatusCode::ACCEPTED
+        );
+        assert_eq!(sent_at(&mut conn), first_sent);
+        let long_ago = first_sent - chrono::Duration::hours(1);
+        diesel::update(model::schema::stock_notifications::table.find(product_signup.id))
+            .set(model::schema::stock_notifications::confirmation_sent.eq(long_ago))
+            .execute(&mut conn)
+            .unwrap();
+        assert_eq!(
+            test::call_service(&app, resend()).await.status(),
+            StatusCode::ACCEPTED
+        );
+        assert!(sent_at(&mut conn) > long_ago);
+
+        // Unconfirmed signups aren't emailed
+        assert!(due_notifications(&mut conn, &[small as i32])
+            .unwrap()
+            .is_empty());
+
+        // Following the emailed links only shows a form to post back
+        for action in ["confirm", "unsubscribe"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/notify/{}/{action}", product_signup.token))
+                .to_request();
+            let page = test::call_and_read_body(&app, req).await;
+            assert!(std::str::from_utf8(&page)
+                .unwrap()
+                .contains(r#"<form method="post">"#));
+        }
+        assert!(due_notifications(&mut conn, &[small as i32])
+            .unwrap()
+            .is_empty());
+
+        for token in [&product_signup.token, &item_signup.token] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/notify/{token}/confirm"))
+                .to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+        }
+        let req = test::TestRequest::post()
+            .uri("/notify/nonsense/confirm")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        // Product wide signups hear about any variant, each signup once
+        let due = due_notifications(&mut conn, &[small as i32]).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].email, "a@kiggy.shop");
+        assert_eq!(due[0].title, "hare");
+        // Storefront product pages are keyed by item
+        assert!(due[0]
+            .render_plaintext()
+            .contains(&format!("/products/{small}\n")));
+        let due = due_notifications(&mut conn, &[small as i32, big as i32]).unwrap();
+        assert_eq!(
+            due.iter()
+                .map(|restock| restock.email.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["a@kiggy.shop", "b@kiggy.shop"]
+        );
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/notify/{}/unsubscribe", product_signup.token))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let due = due_notifications(&mut conn, &[small as i32, big as i32]).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].email, "b@kiggy.shop");
+    }
+
+    #[actix_web::test]
+    async fn test_preorders() {
+        let (db, pool) = create_db_pool();
+        let stock_pool = pool.clone();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(test_mailer()))
+                .service(put_product)
+                .service(get_product)
+                .service(quote_cart)
+                .service(get_orders)
+                .service(adjust_item)
+                .service(get_movements)
+                .service(update_item),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "moth",
+                "description": "",
+                "variants": [{ "sku": "MOTH-X", "kind": "SmallPrint", "quantity": 0, "sales_mode": "PreOrder" }]
+            }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "moth",
+                "description": "",
+                "variants": [
+                    {
+                        "sku": "MOTH-P",
+                        "kind": "SmallPrint",
+                        "quantity": 1,
+                        "sales_mode": "PreOrder",
+                        "ships_on": "2027-03-01",
+                        "preorder_cap": 2
+                    },
+                    { "sku": "MOTH-B", "kind": "BigPrint", "quantity": 0, "sales_mode": "Backorder" },
+                    { "sku": "MOTH-N", "kind": "Button", "quantity": 0 },
+                    { "sku": "MOTH-S", "kind": "Button", "quantity": 5, "options": { "finish": "shiny" } }
+                ]
+            }))
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let req = test::TestRequest::get()
+            .uri(&format!("/stock/{product_id}"))
+            .to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        let id_of = |sku: &str| {
+            *product
+                .variants
+                .iter()
+                .find(|(_, item)| item.sku == sku)
+                .unwrap()
+                .0
+        };
+        let (preorder, backorder, normal, shiny) = (
+            id_of("MOTH-P"),
+            id_of("MOTH-B"),
+            id_of("MOTH-N"),
+            id_of("MOTH-S"),
+        );
+        assert!(product.variants[&normal].sold_out);
+        assert!(!product.variants[&preorder].sold_out);
+        assert!(!product.variants[&backorder].sold_out);
+
+        let quote = |cart: CartMap| {
+            test::TestRequest::post()
+                .uri("/cart/quote")
+                .set_json(cart)
+                .to_request()
+        };
+        let ship_date = chrono::NaiveDate::from_ymd_opt(2027, 3, 1);
+
+        // Only the units beyond those on hand are pre-ordered, up to the cap
+        let quoted: Quote = test::try_call_and_read_body_json(
+            &app,
+            quote(CartMap::from([(preorder, 3), (backorder, 5)])),
+        )
+        .await
+        .expect("Cannot quote cart");
+        assert!(quoted.warnings.is_empty(), "{:?}", quoted.warnings);
+        let line = |item| quoted.lines.iter().find(|line| line.item == item).unwrap();
+        assert_eq!(
+            (line(preorder).awaiting_stock, line(preorder).ships_on),
+            (2, ship_date)
+        );
+        assert_eq!(
+            (line(backorder).awaiting_stock, line(backorder).ships_on),
+            (5, None)
+        );
+        let quoted: Quote = test::try_call_and_read_body_json(
+            &app,
+            quote(CartMap::from([(preorder, 4), (normal, 1)])),
+        )
+        .await
+        .expect("Cannot quote cart");
+        assert_eq!(
+            quoted.warnings,
+            vec![
+                Warning::InsufficientStock {
+                    item: preorder.min(normal),
+                    requested: if preorder < normal { 4 } else { 1 },
+                    available: if preorder < normal { 3 } else { 0 },
+                },
+                Warning::InsufficientStock {
+                    item: preorder.max(normal),
+                    requested: if preorder < normal { 1 } else { 4 },
+                    available: if preorder < normal { 0 } else { 3 },
+                },
+            ]
+        );
+
+        // Sales record which lines are waiting on stock
+        let mut conn = db.connection();
+        let mut order_ids = Vec::new();
+        for (item, quantity) in [(preorder, 3), (shiny, 1)] {
+            let order_id = diesel::insert_into(model::schema::orders::table)
+                .values(NewOrder {
+                    name: "",
+                    total: 0,
+                    email: "",
+                    shipped: false,
+                })
+                .returning(model::schema::orders::id)
+                .get_result::<i32>(&mut conn)
+                .expect("Cannot insert order");
+            diesel::insert_into(model::schema::carts::table)
+                .values(
+                    model::cart::NewCart::new(order_id, item as i32, quantity as i32, 700, "", "")
+                        .unwrap(),
+                )
+                .execute(&mut conn)
+                .expect("Cannot insert cart");
+            let cart = std::sync::Arc::new(std::collections::HashMap::from([(
+                item,
+                stripe::Item {
+                    title: "moth".to_string(),
+                    price: 0,
+                    quantity,
+                    ships_on: None,
+                },
+            )]));
+            dec_items(cart, order_id, stock_pool.get().unwrap())
+                .await
+                .expect("Cannot record sale");
+            order_ids.push(order_id);
+        }
+        let awaiting = model::schema::carts::table
+            .filter(model::schema::carts::order_id.eq(order_ids[0]))
+            .select((
+                model::schema::carts::awaiting_stock,
+                model::schema::carts::ships_on,
+            ))
+            .first::<(i32, Option<chrono::NaiveDate>)>(&mut conn)
+            .expect("Cannot fetch cart");
+        assert_eq!(awaiting, (2, ship_date));
+
+        let listed = |filter: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/orders/{filter}"))
+                .to_request()
+        };
+        let ids = |orders: Vec<TableOrder>| orders.into_iter().map(|o| o.id).collect::<Vec<i32>>();
+        let waiting: Vec<TableOrder> =
+            test::try_call_and_read_body_json(&app, listed("AwaitingStock"))
+                .await
+                .expect("Cannot list orders");
+        assert_eq!(ids(waiting), vec![order_ids[0]]);
+        let ready: Vec<TableOrder> = test::try_call_and_read_body_json(&app, listed("ReadyToShip"))
+            .await
+            .expect("Cannot list orders");
+        assert_eq!(ids(ready), vec![order_ids[1]]);
+
+        // Editing an item below zero keeps what's owed on it
+        let edit = |sku: &str, item: model::ItemId, sales_mode: &str, quantity: i32| {
+            test::TestRequest::put()
+                .uri(&format!("/variants/{item}"))
+                .set_json(serde_json::json!({
+                    "sku": sku,
+                    "kind": "SmallPrint",
+                    "price": 900,
+                    "quantity": quantity,
+                    "sales_mode": sales_mode,
+                    "ships_on": "2027-03-01",
+                    "preorder_cap": 2
+                }))
+                .to_request()
+        };
+        let res = test::call_service(&app, edit("MOTH-P", preorder, "PreOrder", -2)).await;
+        assert!(res.status().is_success());
+        let waiting: Vec<TableOrder> =
+            test::try_call_and_read_body_json(&app, listed("AwaitingStock"))
+                .await
+                .expect("Cannot list orders");
+        assert_eq!(ids(waiting), vec![order_ids[0]]);
+        let res = test::call_service(&app, edit("MOTH-N", normal, "Normal", -1)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let exported = crate::catalogue::export(&mut conn).expect("Cannot export catalogue");
+        let exported = exported
+            .iter()
+            .flat_map(|product| &product.variants)
+            .find(|variant| variant.sku == "MOTH-P")
+            .unwrap();
+        assert_eq!(exported.quantity, -2);
+        // Owed units are audited as they are
+        let req = test::TestRequest::get()
+            .uri(&format!("/variants/{preorder}/movements"))
+            .to_request();
+        let history: ItemHistory = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch movements");
+        assert_eq!(history.quantity, -2);
+        assert!(history.is_consistent());
+
+        // Restocking can start below zero, and makes the order ready once covered
+        let req = test::TestRequest::put()
+            .uri(&format!("/variants/{preorder}/movements"))
+            .set_json(serde_json::json!({ "delta": 2, "reason": "Restock" }))
+            .to_request();
+        let quantity: i32 = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot restock item");
+        assert_eq!(quantity, 0);
+        let ready: Vec<TableOrder> = test::try_call_and_read_body_json(&app, listed("ReadyToShip"))
+            .await
+            .expect("Cannot list orders");
+        assert_eq!(ids(ready), order_ids);
+
+        let confirmation = crate::mail::confirmation::Confirmation::from(&stripe::User {
+            name: "kiggy".to_string(),
+            address: None,
+            email: "kiggy@kiggy.shop".to_string(),
+            total: 700,
+            subtotal: 700,
+            cart: std::collections::HashMap::from([(
+                preorder,
+                stripe::Item {
+                    title: "moth".to_string(),
+                    price: 700,
+                    quantity: 1,
+                    ships_on: ship_date,
+                },
+            )]),
+        });
+        assert!(confirmation
+            .render_plaintext()
+            .contains("moth (ships around March 1, 2027)"));
+    }
+
+    #[actix_web::test]
+    async fn test_limited_drops() {
+        let (db, pool) = create_db_pool();
+        let limit_pool = std::sync::Arc::new(pool.clone());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(put_product)
+                .service(get_product)
+                .service(quote_cart),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "comet",
+                "description": "",
+                "variants": [{
+                    "sku": "COMET-X",
+                    "kind": "SmallPrint",
+                    "quantity": 1,
+                    "available_from": "2030-01-02T00:00:00",
+                    "available_until": "2030-01-01T00:00:00"
+                }]
+            }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "comet",
+                "description": "",
+                "variants": [
+                    {
+                        "sku": "COMET-U",
+                        "kind": "SmallPrint",
+                        "quantity": 10,
+                        "available_from": "2999-01-01T17:00:00"
+                    },
+                    {
+                        "sku": "COMET-E",
+                        "kind": "BigPrint",
+                        "quantity": 10,
+                        "available_until": "2000-01-01T00:00:00"
+                    },
+                    {
+                        "sku": "COMET-L",
+                        "kind": "Button",
+                        "quantity": 10,
+                        "available_from": "2000-01-01T00:00:00",
+                        "max_per_order": 2,
+                        "max_per_customer": 3
+                    }
+                ]
+            }))
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let req = test::TestRequest::get()
+            .uri(&format!("/stock/{product_id}"))
+            .to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        let id_of = |sku: &str| {
+            *product
+                .variants
+                .iter()
+                .find(|(_, item)| item.sku == sku)
+                .unwrap()
+                .0
+        };
+        let (unreleased, ended, limited) = (id_of("COMET-U"), id_of("COMET-E"), id_of("COMET-L"));
+        assert!(!product.variants[&unreleased].on_sale);
+        assert!(!product.variants[&ended].on_sale);
+        assert!(product.variants[&limited].on_sale);
+
+        let quote = |cart: CartMap| {
+            test::TestRequest::post()
+                .uri("/cart/quote")
+                .set_json(cart)
+                .to_request()
+        };
+        let quoted: Quote =
+            test::try_call_and_read_body_json(&app, quote(CartMap::from([(unreleased, 1)])))
+                .await
+                .expect("Cannot quote cart");
+        assert_eq!(
+            quoted.warnings,
+            vec![Warning::NotOnSale {
+                item: unreleased,
+                available_from: chrono::NaiveDate::from_ymd_opt(2999, 1, 1)
+                    .and_then(|date| date.and_hms_opt(17, 0, 0)),
+                available_until: None,
+            }]
+        );
+        let quoted: Quote =
+            test::try_call_and_read_body_json(&app, quote(CartMap::from([(limited, 3)])))
+                .await
+                .expect("Cannot quote cart");
+        assert_eq!(
+            quoted.warnings,
+            vec![Warning::OrderLimit {
+                item: limited,
+                requested: 3,
+                limit: 2,
+            }]
+        );
+
+        // Earlier orders count towards the limit whatever case the email was in
+        let mut conn = db.connection();
+        let order_id = diesel::insert_into(model::schema::orders::table)
+            .values(NewOrder {
+                name: "",
+                total: 0,
+                email: "Reseller@Kiggy.shop",
+                shipped: false,
+            })
+            .returning(model::schema::orders::id)
+            .get_result::<i32>(&mut conn)
+            .expect("Cannot insert order");
+        diesel::insert_into(model::schema::carts::table)
+            .values(model::cart::NewCart::new(order_id, limited as i32, 2, 700, "", "").unwrap())
+            .execute(&mut conn)
+            .expect("Cannot insert cart");
+
+        let cart = CartMap::from([(limited, 2), (ended, 1)]);
+        let warnings = customer_limit_warnings(&cart, None, limit_pool.clone())
+            .await
+            .expect("Cannot check limits");
+        assert_eq!(warnings, vec![Warning::EmailRequired { item: limited }]);
+        let warnings = customer_limit_warnings(
+            &cart,
+            Some("reseller@kiggy.shop".to_string()),
+            limit_pool.clone(),
+        )
+        .await
+        .expect("Cannot check limits");
+        assert_eq!(
+            warnings,
+            vec![Warning::CustomerLimit {
+                item: limited,
+                requested: 2,
+                purchased: 2,
+                limit: 3,
+            }]
+        );
+        let warnings = customer_limit_warnings(
+            &CartMap::from([(limited, 1)]),
+            Some("reseller@kiggy.shop".to_string()),
+            limit_pool.clone(),
+        )
+        .await
+        .expect("Cannot check limits");
+        assert!(warnings.is_empty());
+
+        // Orders paid for despite breaking limits are flagged with why
+        let broken = vec![Warning::CustomerLimit {
+            item: limited,
+            requested: 2,
+            purchased: 2,
+            limit: 3,
+        }];
+        flag_order(limit_pool, order_id, broken.clone())
+            .await
+            .expect("Cannot flag order");
+        let flagged = model::schema::orders::table
+            .select(model::schema::orders::warnings)
+            .filter(model::schema::orders::id.eq(order_id))
+            .get_result::<Option<String>>(&mut conn)
+            .expect("Cannot fetch order");
+        assert_eq!(
+            flagged.map(|warnings| serde_json::from_str::<Vec<Warning>>(&warnings).unwrap()),
+            Some(broken)
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_archive_items() {
+        let (db, pool) = create_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(Env::default()))
+                .service(put_product)
+                .service(search_stock)
+                .service(get_product)
+                .service(get_stock)
+                .service(quote_cart)
+                .service(delete_products)
+                .service(delete_items)
+                .service(archive_items)
+                .service(unarchive_items),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "owl",
+                "description": "",
+                "variants": [
+                    { "sku": "OWL-S", "kind": "SmallPrint", "quantity": 3 },
+                    { "sku": "OWL-B", "kind": "BigPrint", "quantity": 3 }
+                ]
+            }))
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let load_product = || {
+            test::TestRequest::get()
+                .uri(&format!("/stock/{product_id}"))
+                .to_request()
+        };
+        let product: Product = test::try_call_and_read_body_json(&app, load_product())
+            .await
+            .expect("Cannot fetch product");
+        let id_of = |sku: &str| {
+            *product
+                .variants
+                .iter()
+                .find(|(_, item)| item.sku == sku)
+                .unwrap()
+                .0
+        };
+        let (small, big) = (id_of("OWL-S"), id_of("OWL-B"));
+
+        let mut conn = db.connection();
+        let order_id = diesel::insert_into(model::schema::orders::table)
+            .values(NewOrder {
+                name: "",
+                total: 0,
+                email: "",
+                shipped: false,
+            })
+            .returning(model::schema::orders::id)
+            .get_result::<i32>(&mut conn)
+            .expect("Cannot insert order");
+        diesel::insert_into(model::schema::carts::table)
+            .values(model::cart::NewCart::new(order_id, small as i32, 1, 700, "", "").unwrap())
+            .execute(&mut conn)
+            .expect("Cannot insert cart");
+
+        // Ordered items can only be archived
+        let req = test::TestRequest::delete()
+            .uri("/variants")
+            .set_json([small])
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CONFLICT
+        );
+        let req = test::TestRequest::delete()
+            .uri("/stock")
+            .set_json([product_id])
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CONFLICT
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/variants/archive")
+            .set_json([small])
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let product: Product = test::try_call_and_read_body_json(&app, load_product())
+            .await
+            .expect("Cannot fetch product");
+        assert_eq!(product.variants.keys().collect::<Vec<_>>(), vec![&big]);
+        let req = test::TestRequest::post()
+            .uri("/cart/quote")
+            .set_json(CartMap::from([(small, 1)]))
+            .to_request();
+        let quoted: Quote = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot quote cart");
+        assert_eq!(quoted.warnings, vec![Warning::NotFound { item: small }]);
+        let archived = model::schema::carts::table
+            .inner_join(model::schema::stock::table)
+            .filter(model::schema::carts::order_id.eq(order_id))
+            .select(model::schema::stock::sku)
+            .first::<String>(&mut conn)
+            .expect("Cannot join archived item");
+        assert_eq!(archived, "OWL-S");
+
+        // Products with every variant archived are only listed to admins
+        let req = test::TestRequest::delete()
+            .uri("/variants")
+            .set_json([big])
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let listed = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, listed("/stock"))
+            .await
+            .expect("Cannot list stock");
+        assert_eq!(stock.total, 0);
+        let results: SearchResults =
+            test::try_call_and_read_body_json(&app, listed("/stock/search?q=owl"))
+                .await
+                .expect("Cannot search stock");
+        assert_eq!((results.total, results.hits.len()), (0, 0));
+        let stock: StockPage =
+            test::try_call_and_read_body_json(&app, listed("/stock?archived=true"))
+                .await
+                .expect("Cannot list stock");
+        assert_eq!(stock.total, 1);
+        assert!(stock.products[0].product.variants[&small]
+            .archived
+            .is_some());
+
+        let req = test::TestRequest::post()
+            .uri("/variants/unarchive")
+            .set_json([small])
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let product: Product = test::try_call_and_read_body_json(&app, load_product())
+            .await
+            .expect("Cannot fetch product");
+        assert!(product.variants[&small].archived.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_price_history() {
+        let (db, pool) = create_db_pool();
+        let order_pool = pool.clone();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(test_mailer()))
+                .service(put_product)
+                .service(get_product)
+                .service(update_item)
+                .service(update_kind)
+                .service(get_prices)
+                .service(get_order),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/stock")
+            .set_json(serde_json::json!({
+                "title": "lark",
+                "description": "",
+                "variants": [
+                    { "sku": "LARK-B", "kind": "Button", "quantity": 5 },
+                    { "sku": "LARK-S", "kind": "Button", "quantity": 5, "price": 450 }
+                ]
+            }))
+            .to_request();
+        let product_id: ProductId = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot create product");
+        let req = test::TestRequest::get()
+            .uri(&format!("/stock/{product_id}"))
+            .to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        let id_of = |sku: &str| {
+            *product
+                .variants
+                .iter()
+                .find(|(_, item)| item.sku == sku)
+                .unwrap()
+                .0
+        };
+        let (plain, special) = (id_of("LARK-B"), id_of("LARK-S"));
+
+        let cart = std::sync::Arc::new(std::collections::HashMap::from([(
+            plain,
+            stripe::Item {
+                title: "lark".to_string(),
+                price: 300,
+                quantity: 2,
+                ships_on: None,
+            },
+        )]));
+        let address = serde_json::from_str::<Order>(include_str!("./mock_order.json"))
+            .expect("Cannot deserialize mock order")
+            .address;
+        let order_id = insert_order(
+            order_pool.get().unwrap(),
+            cart,
+            600,
+            OrderPayment::default(),
+            "kiggy".into(),
+            "kiggy@kiggy.shop".into(),
+            std::sync::Arc::new(address),
+        )
+        .await
+        .expect("Cannot insert order");
+
+        // Only price changes are recorded, against every item that uses the kind
+        let req = test::TestRequest::put()
+            .uri("/kinds/2")
+            .insert_header(("X-Admin-User", "kiggy"))
+            .set_json(serde_json::json!({
+                "name": "Button",
+                "display_name": "Button",
+                "price": 350,
+                "shipping_class": "Flat"
+            }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::put()
+            .uri("/kinds/2")
+            .set_json(serde_json::json!({
+                "name": "Button",
+                "display_name": "Button",
+                "price": 3_000_000_000u32,
+                "shipping_class": "Flat"
+            }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        let req = test::TestRequest::put()
+            .uri(&format!("/variants/{special}"))
+            .set_json(serde_json::json!({ "sku": "LARK-S", "kind": "Button", "quantity": 5, "price": 450 }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let prices = |item| {
+            test::TestRequest::get()
+                .uri(&format!("/variants/{item}/prices"))
+                .to_request()
+        };
+        let history: PriceHistory = test::try_call_and_read_body_json(&app, prices(plain))
+            .await
+            .expect("Cannot fetch prices");
+        assert_eq!(
+            history
+                .changes
+                .iter()
+                .map(|change| (change.price, change.actor.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![(300, None), (350, None)]
+        );
+        let history: PriceHistory = test::try_call_and_read_body_json(&app, prices(special))
+            .await
+            .expect("Cannot fetch prices");
+        assert_eq!(history.changes.len(), 1);
+
+        // Orders keep the price and title they were sold at
+        let mut conn = db.connection();
+        diesel::update(model::schema::products::table)
+            .set(model::schema::products::title.eq("skylark"))
+            .execute(&mut conn)
+            .expect("Cannot rename product");
+        let req = test::TestRequest::get()
+            .uri(&format!("/orders/{order_id}/detail"))
+            .to_request();
+        let detail: OrderDetail = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch order");
+        assert_eq!(detail.order.email, "kiggy@kiggy.shop");
+        assert_eq!(
+            detail.lines,
+            vec![OrderLine {
+                item: plain,
+                title: "lark".to_string(),
+                unit_price: 300,
+                quantity: 2,
+                line_total: 600,
+                awaiting_stock: 0,
+                ships_on: None,
+            }]
+        );
+        let req = test::TestRequest::get()
+            .uri("/orders/999/detail")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_catalogue_import_export() {
+        let (_db, pool) = create_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(test_mailer()))
+                .service(export_catalogue)
+                .service(import_catalogue)
+                .service(get_stock),
+        )
+        .await;
+
+        let stock_json = include_str!("../../stock.json");
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=json&dry_run=true")
+            .set_payload(stock_json)
+            .to_request();
+        let report: ImportReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize import report");
+        assert!(!report.applied);
+        assert!(report.errors.is_empty());
+        assert!(report
+            .changes
+            .iter()
+            .all(|change| change.action == Action::Create));
+        let req = test::TestRequest::get().uri("/stock").to_request();
+        let stock: StockPage = test::try_call_and_read_body_json(&app, req)
             .await
             .expect("Cannot deserialize body");
+        assert_eq!(stock.total, 0);
+
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=json")
+            .set_payload(stock_json)
+            .to_request();
+        let report: ImportReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize import report");
+        assert!(report.applied);
+
+        // Exports import back without changing anything
+        let req = test::TestRequest::get()
+            .uri("/catalogue?format=csv")
+            .to_request();
+        let csv = test::call_and_read_body(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=csv&dry_run=true")
+            .set_payload(csv.clone())
+            .to_request();
+        let report: ImportReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize import report");
+        assert!(report.changes.is_empty(), "{report:?}");
+        assert!(report.errors.is_empty(), "{report:?}");
+
+        let csv = String::from_utf8(csv.to_vec()).expect("CSV is not UTF-8");
+        let restocked = csv.replacen("KS-0001,,BigPrint,,20", "KS-0001,,BigPrint,,25", 1);
+        assert_ne!(csv, restocked);
+        let invalid = format!("{restocked}dog,,,,KS-9999,,Mug,,1,,,,,,,,\n");
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=csv")
+            .set_payload(invalid)
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let report: ImportReport = test::read_body_json(response).await;
+        assert!(!report.applied);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].message.contains("Mug"));
+
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=csv")
+            .set_payload(restocked)
+            .to_request();
+        let report: ImportReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize import report");
+        assert!(report.applied);
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].sku.as_deref(), Some("KS-0001"));
+        assert_eq!(report.changes[0].fields, vec!["quantity"]);
+        assert!(report.restocked.is_empty());
+
+        // Only imports taking a sold out item above zero count as restocks, and
+        // selling out is alerted like it is for sales
+        let sold_out = csv.replacen("KS-0001,,BigPrint,,20", "KS-0001,,BigPrint,,0", 1);
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=csv")
+            .set_payload(sold_out)
+            .to_request();
+        let report: ImportReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize import report");
+        assert!(report.restocked.is_empty());
+        assert_eq!(report.low_stock.len(), 1);
+        assert_eq!(report.low_stock[0].sku, "KS-0001");
+        assert_eq!(report.low_stock[0].quantity, 0);
+        let req = test::TestRequest::put()
+            .uri("/catalogue?format=csv")
+            .set_payload(csv)
+            .to_request();
+        let report: ImportReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot deserialize import report");
+        assert_eq!(report.restocked.len(), 1);
+        assert!(report.low_stock.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_upload_images() {
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        test_db::insert_stock(&mut conn);
+
+        let image_dir =
+            std::env::temp_dir().join(format!("kiggyshop_images_{}", std::process::id()));
+        let env = Env {
+            image_dir: Box::leak(image_dir.to_str().unwrap().to_string().into_boxed_str()),
+            ..Default::default()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(env))
+                .service(upload_images)
+                .service(get_product),
+        )
+        .await;
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbaImage::new(800, 600)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .expect("Cannot encode test image");
+
+        let boundary = "kiggyboundary";
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"moth.png\"\r\nContent-Type: image/png\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(png.get_ref());
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let req = test::TestRequest::post()
+            .uri("/stock/1/images")
+            .insert_header((
+                "Content-Type",
+                format!("multipart/form-data; boundary={boundary}"),
+            ))
+            .set_payload(body)
+            .to_request();
+        let images: Vec<Image> = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot upload image");
+        assert_eq!(images.len(), 1);
+
+        let key = images[0]
+            .thumbnail
+            .jpeg
+            .split('/')
+            .rev()
+            .nth(1)
+            .expect("Malformed image URL");
+        let thumbnail = image::open(image_dir.join(key).join("thumbnail.jpg"))
+            .expect("Thumbnail was not written");
+        assert_eq!((thumbnail.width(), thumbnail.height()), (400, 300));
+        assert!(image_dir.join(key).join("web.webp").exists());
+
+        let req = test::TestRequest::get().uri("/stock/1").to_request();
+        let product: Product = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch product");
+        assert_eq!(product.images, images);
+
+        std::fs::remove_dir_all(image_dir).unwrap();
     }
 
     #[actix_web::test]
@@ -75,7 +1895,8 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .service(delete_order)
-                .app_data(web::Data::new(pool)),
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(test_mailer())),
         )
         .await;
         let req = test::TestRequest::delete().uri("/orders/1").to_request();
@@ -85,4 +1906,608 @@ mod tests {
         let mut conn = db.connection();
         assert_eq!(model::schema::orders::table.count().first(&mut conn), Ok(0))
     }
+
+    #[actix_web::test]
+    async fn test_visit_analytics() {
+        use model::schema::{daily_unique_visitors, daily_visits, users};
+
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        let day = |day| chrono::NaiveDate::from_ymd_opt(2026, 10, day).unwrap();
+
+        let visits = [
+            (
+                6,
+                "a",
+                "UK",
+                "England",
+                "Linux",
+                Some("duckduckgo.com"),
+                false,
+            ),
+            (
+                6,
+                "a",
+                "UK",
+                "England",
+                "Linux",
+                Some("duckduckgo.com"),
+                false,
+            ),
+            // The same visitor coming back directly
+            (6, "a", "UK", "England", "Linux", None, false),
+            (7, "b", "US", "Washington", "Mac", None, false),
+            (
+                13,
+                "c",
+                "UK",
+                "Scotland",
+                "Android",
+                Some("example.org"),
+                false,
+            ),
+            (13, "d", "US", "Washington", "Linux", None, true),
+        ];
+        for (visit_day, visitor, country, state, device, referrer, is_bot) in visits {
+            diesel::insert_into(users::table)
+                .values((
+                    users::visitor.eq(visitor),
+                    users::device.eq(device),
+                    users::time.eq(day(visit_day).and_hms_opt(12, 0, 0)),
+                    users::country.eq(country),
+                    users::state.eq(state),
+                    users::referrer.eq(referrer),
+                    users::is_bot.eq(is_bot),
+                ))
+                .execute(&mut conn)
+                .expect("Cannot insert visit");
+        }
+        // Rolled up visits count too, the first is before the range
+        let rollup = |visit_day, device: &str, referrer: &str, visits| DailyVisits {
+            day: day(visit_day),
+            country: "UK".to_string(),
+            state: "England".to_string(),
+            device: device.to_string(),
+            referrer: referrer.to_string(),
+            visits,
+        };
+        diesel::insert_into(daily_visits::table)
+            .values([
+                rollup(1, "Linux", "", 5),
+                rollup(5, "Windows", "duckduckgo.com", 3),
+            ])
+            .execute(&mut conn)
+            .expect("Cannot insert rollups");
+        let unique = |visit_day, breakdown: &str, country: &str, name: &str, unique_visitors| {
+            DailyUniqueVisitors {
+                day: day(visit_day),
+                breakdown: breakdown.to_string(),
+                country: country.to_string(),
+                name: name.to_string(),
+                unique_visitors,
+            }
+        };
+        diesel::insert_into(daily_unique_visitors::table)
+            .values([
+                unique(1, "total", "", "", 4),
+                unique(5, "total", "", "", 2),
+                unique(5, "device", "", "Windows", 2),
+                unique(5, "country", "", "UK", 2),
+                unique(5, "state", "UK", "England", 2),
+                unique(5, "referrer", "", "duckduckgo.com", 2),
+            ])
+            .execute(&mut conn)
+            .expect("Cannot insert rollups");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .service(get_visits),
+        )
+        .await;
+
+        let visits = |visits, unique_visitors| Visits {
+            visits,
+            unique_visitors,
+        };
+        let req = test::TestRequest::get()
+            .uri("/analytics/visits?from=2026-10-05&to=2026-10-18&interval=week")
+            .to_request();
+        let report: VisitsReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch visits");
+        assert_eq!(
+            report,
+            VisitsReport {
+                from: day(5),
+                to: day(18),
+                interval: Interval::Week,
+                total: visits(8, 5),
+                periods: vec![
+                    PeriodVisits {
+                        start: day(5),
+                        visits: visits(7, 4),
+                    },
+                    PeriodVisits {
+                        start: day(12),
+                        visits: visits(1, 1),
+                    },
+                ],
+                devices: [
+                    (Device::Linux, visits(3, 1)),
+                    (Device::Windows, visits(3, 2)),
+                    (Device::Mac, visits(1, 1)),
+                    (Device::Android, visits(1, 1)),
+                ]
+                .map(|(device, visits)| DeviceVisits { device, visits })
+                .to_vec(),
+                countries: [("UK", visits(7, 4)), ("US", visits(1, 1))]
+                    .map(|(country, visits)| CountryVisits {
+                        country: country.to_string(),
+                        visits,
+                    })
+                    .to_vec(),
+                states: [
+                    ("UK", "England", visits(6, 3)),
+                    ("UK", "Scotland", visits(1, 1)),
+                    ("US", "Washington", visits(1, 1)),
+                ]
+                .map(|(country, state, visits)| StateVisits {
+                    country: country.to_string(),
+                    state: state.to_string(),
+                    visits,
+                })
+                .to_vec(),
+                referrers: [
+                    ("duckduckgo.com", visits(5, 3)),
+                    ("example.org", visits(1, 1))
+                ]
+                .map(|(referrer, visits)| ReferrerVisits {
+                    referrer: referrer.to_string(),
+                    visits,
+                })
+                .to_vec(),
+            }
+        );
+
+        // Bots are only counted if asked for
+        let req = test::TestRequest::get()
+            .uri("/analytics/visits?from=2026-10-05&to=2026-10-18&bots=true")
+            .to_request();
+        let report: VisitsReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch visits");
+        assert_eq!(report.total, visits(9, 6));
+
+        let req = test::TestRequest::get()
+            .uri("/analytics/visits?from=2026-10-05&to=2026-10-06&format=csv")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/csv"
+        );
+        let csv = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert_eq!(
+            csv,
+            "breakdown,name,country,visits,unique_visitors\n\
+             total,,,6,3\n\
+             day,2026-10-05,,3,2\n\
+             day,2026-10-06,,3,1\n\
+             device,Linux,,3,1\n\
+             device,Windows,,3,2\n\
+             country,UK,,6,3\n\
+             state,England,UK,6,3\n\
+             referrer,duckduckgo.com,,5,3\n"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/analytics/visits?from=2026-10-06&to=2026-10-05")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_funnel() {
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        test_db::insert_stock(&mut conn);
+        let item_id = model::schema::stock::table
+            .filter(model::schema::stock::product_id.eq(1))
+            .select(model::schema::stock::id)
+            .first::<i32>(&mut conn)
+            .expect("Cannot fetch item");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(get_stock)
+                .service(get_product)
+                .service(get_funnel),
+        )
+        .await;
+
+        let browse = |uri: &str, session: &str| {
+            test::TestRequest::get()
+                .uri(uri)
+                .insert_header((header::USER_AGENT, "Mozilla/5.0 (X11; Linux x86_64)"))
+                .insert_header((header::ACCEPT_LANGUAGE, "en"))
+                .cookie(actix_web::cookie::Cookie::new(
+                    crate::funnel::SESSION_COOKIE,
+                    session.to_string(),
+                ))
+        };
+        for (uri, session) in [
+            ("/stock", "a"),
+            ("/stock/1", "a"),
+            ("/stock/1", "a"),
+            ("/stock/1", "b"),
+            // Later pages and missing products aren't views
+            ("/stock?page=2", "c"),
+            ("/stock/999", "c"),
+        ] {
+            test::call_service(&app, browse(uri, session).to_request()).await;
+        }
+        let req = browse("/stock/1", "d").insert_header(("DNT", "1"));
+        test::call_service(&app, req.to_request()).await;
+        // Revalidating the listing is still a view
+        let req = browse("/stock", "e").insert_header((header::IF_NONE_MATCH, "*"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        crate::funnel::record_items(
+            &pool,
+            "a",
+            Stage::CheckoutStarted,
+            vec![item_id as u32],
+            None,
+        )
+        .await;
+        crate::funnel::record_items(
+            &pool,
+            "a",
+            Stage::PurchaseCompleted,
+            vec![item_id as u32],
+            Some(1),
+        )
+        .await;
+
+        let step = |stage, sessions, conversion| FunnelStep {
+            stage,
+            sessions,
+            conversion,
+        };
+        let req = test::TestRequest::get()
+            .uri("/analytics/funnel")
+            .to_request();
+        let report: FunnelReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch funnel");
+        assert_eq!(
+            report.steps,
+            vec![
+                step(Stage::CatalogueView, 2, 1.0),
+                step(Stage::ItemView, 2, 1.0),
+                step(Stage::CheckoutStarted, 1, 0.5),
+                step(Stage::PurchaseCompleted, 1, 1.0),
+            ]
+        );
+        let title = model::schema::products::table
+            .find(1)
+            .select(model::schema::products::title)
+            .first::<String>(&mut conn)
+            .expect("Cannot fetch product");
+        assert_eq!(
+            report.products,
+            vec![ProductFunnel {
+                product_id: 1,
+                title,
+                steps: vec![
+                    step(Stage::ItemView, 2, 1.0),
+                    step(Stage::CheckoutStarted, 1, 0.5),
+                    step(Stage::PurchaseCompleted, 1, 1.0),
+                ],
+            }]
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/analytics/funnel?format=csv")
+            .to_request();
+        let csv = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert_eq!(
+            csv.lines().take(3).collect::<Vec<&str>>(),
+            [
+                "product_id,title,stage,sessions,conversion",
+                ",,catalogue_view,2,1.0",
+                ",,item_view,2,1.0",
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_sales() {
+        use model::schema::{carts, kinds, orders, stock};
+
+        let (db, pool) = create_db_pool();
+        let mut conn = db.connection();
+        test_db::insert_stock(&mut conn);
+        let items = stock::table
+            .inner_join(kinds::table)
+            .select((stock::id, kinds::name))
+            .order(stock::id)
+            .get_results::<(i32, String)>(&mut conn)
+            .expect("Cannot fetch items");
+        let (a, a_kind) = items[0].clone();
+        let (b, b_kind) = items
+            .iter()
+            .find(|(_, kind)| *kind != a_kind)
+            .cloned()
+            .expect("Cannot find items of two kinds");
+
+        // (email, placed on, total, shipping, tax, lines of (item, quantity, price, title))
+        let placed = [
+            (
+                "Kiggy@kiggy.shop",
+                (2026, 10, 5),
+                1000,
+                200,
+                0,
+                vec![(a, 2, 400, "old")],
+            ),
+            (
+                "kiggy@kiggy.shop",
+                (2026, 10, 20),
+                700,
+                200,
+                0,
+                vec![(b, 1, 500, "b")],
+            ),
+            (
+                "moth@kiggy.shop",
+                (2026, 11, 2),
+                1500,
+                300,
+                100,
+                vec![(a, 1, 400, "new"), (b, 2, 400, "b")],
+            ),
+            // Outside the range
+            (
+                "moth@kiggy.shop",
+                (2026, 12, 15),
+                900,
+                0,
+                0,
+                vec![(a, 1, 900, "new")],
+            ),
+        ];
+        for (i, (email, (year, month, day), total, shipping, tax, lines)) in
+            placed.into_iter().enumerate()
+        {
+            let order_id = diesel::insert_into(orders::table)
+                .values((
+                    NewOrder {
+                        name: "kiggy",
+                        email,
+                        total,
+                        shipped: false,
+                    },
+                    OrderPayment {
+                        subtotal: total - shipping - tax,
+                        shipping,
+                        tax,
+                        payment_intent: Some(format!("pi_{i}")),
+                    },
+                ))
+                .returning(orders::id)
+                .get_result::<i32>(&mut conn)
+                .expect("Cannot insert order");
+            let created = chrono::NaiveDate::from_ymd_opt(year, month, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap();
+            diesel::update(orders::table.find(order_id))
+                .set(orders::created.eq(created))
+                .execute(&mut conn)
+                .expect("Cannot date order");
+            for (item, quantity, price, title) in lines {
+                let kind = if item == a { &a_kind } else { &b_kind };
+                diesel::insert_into(carts::table)
+                    .values(
+                        model::cart::NewCart::new(order_id, item, quantity, price, title, kind)
+                            .unwrap(),
+                    )
+                    .execute(&mut conn)
+                    .expect("Cannot insert cart");
+            }
+        }
+        record_refund(std::sync::Arc::new(pool.clone()), "pi_1".to_string(), 700)
+            .await
+            .expect("Cannot record refund");
+        // Lines stay under the kind they were sold as
+        let b_kind_id = stock::table
+            .find(b)
+            .select(stock::kind)
+            .first::<i32>(&mut conn)
+            .expect("Cannot fetch kind");
+        diesel::update(stock::table.find(a))
+            .set(stock::kind.eq(b_kind_id))
+            .execute(&mut conn)
+            .expect("Cannot change kind");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(get_sales),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/analytics/sales?from=2026-10-01&to=2026-11-30&interval=month")
+            .to_request();
+        let report: SalesReport = test::try_call_and_read_body_json(&app, req)
+            .await
+            .expect("Cannot fetch sales");
+        let takings = |orders, revenue, refunded, shipping, tax| Takings {
+            orders,
+            revenue,
+            refunded,
+            net_revenue: revenue - refunded,
+            shipping,
+            tax,
+        };
+        assert_eq!(report.total, takings(3, 3200, 700, 700, 100));
+        assert_eq!(report.average_order_value, 1066);
+        assert_eq!(report.refunds, 1);
+        assert_eq!(
+            report.periods,
+            vec![
+                PeriodSales {
+                    start: chrono::NaiveDate::from_ymd_opt(2026, 10, 1).unwrap(),
+                    takings: takings(2, 1700, 700, 400, 0),
+                },
+                PeriodSales {
+                    start: chrono::NaiveDate::from_ymd_opt(2026, 11, 1).unwrap(),
+                    takings: takings(1, 1500, 0, 300, 100),
+                },
+            ]
+        );
+        // Items are named by the title they were last sold under
+        assert_eq!(
+            report.items,
+            vec![
+                ItemSales {
+                    item_id: b as u32,
+                    title: "b".to_string(),
+                    kind: b_kind.clone(),
+                    units: 3,
+                    revenue: 1300,
+                },
+                ItemSales {
+                    item_id: a as u32,
+                    title: "new".to_string(),
+                    kind: a_kind.clone(),
+                    units: 3,
+                    revenue: 1200,
+                },
+            ]
+        );
+        assert_eq!(
+            report.kinds,
+            vec![
+                KindSales {
+                    kind: b_kind,
+                    units: 3,
+                    revenue: 1300,
+                },
+                KindSales {
+                    kind: a_kind,
+                    units: 3,
+                    revenue: 1200,
+                },
+            ]
+        );
+        // Customers are told apart by email regardless of case, ranked after refunds
+        assert_eq!(
+            report.customers,
+            vec![
+                CustomerSales {
+                    email: "moth@kiggy.shop".to_string(),
+                    orders: 1,
+                    revenue: 1500,
+                    refunded: 0,
+                },
+                CustomerSales {
+                    email: "kiggy@kiggy.shop".to_string(),
+                    orders: 2,
+                    revenue: 1700,
+                    refunded: 700,
+                },
+            ]
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/analytics/sales?from=2026-10-01&to=2026-11-30&format=csv")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(
+            res.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"sales-2026-10-01-2026-11-30.csv\""
+        );
+        let csv = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert_eq!(
+            csv.lines().take(4).collect::<Vec<&str>>(),
+            [
+                "breakdown,name,item_id,orders,units,revenue,refunded,net_revenue,shipping,tax",
+                "total,,,3,,3200,700,2500,700,100",
+                "average_order_value,,,,,1066,,,,",
+                "refunds,,,1,,,700,,,",
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let (_db, pool) = create_db_pool();
+        let scrape = |peer: &str| {
+            test::TestRequest::get()
+                .uri("/metrics")
+                .peer_addr(peer.parse().unwrap())
+                .to_request()
+        };
+
+        // Without networks allowed to scrape there are no metrics
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(get_metrics),
+        )
+        .await;
+        let res = test::call_service(&app, scrape("127.0.0.1:9090")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(
+                    "127.0.0.1/32"
+                        .parse::<crate::monitoring::MetricsAccess>()
+                        .unwrap(),
+                ))
+                .app_data(web::Data::new(
+                    "172.16.0.0/12"
+                        .parse::<crate::client_ip::TrustedProxies>()
+                        .unwrap(),
+                ))
+                .service(get_metrics),
+        )
+        .await;
+        let res = test::call_service(&app, scrape("203.0.113.7:9090")).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Forwarding headers don't count, even from a trusted proxy
+        let req = test::TestRequest::get()
+            .uri("/metrics")
+            .peer_addr("172.18.0.5:9090".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "127.0.0.1"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = test::call_service(&app, scrape("127.0.0.1:9090")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4"));
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(body.contains(&format!(
+            "kiggyserve_db_pool_max_connections {}",
+            pool.max_size()
+        )));
+    }
 }
@@ -0,0 +1,61 @@
+use actix_web::test::TestRequest;
+
+use crate::bots;
+
+const FIREFOX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0";
+const SAFARI: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 18_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.0 Mobile/15E148 Safari/604.1";
+
+#[test]
+fn test_bot_agents() {
+    let cases = [
+        (FIREFOX, false),
+        (SAFARI, false),
+        (
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+            true,
+        ),
+        ("facebookexternalhit/1.1", true),
+        (
+            "Mozilla/5.0 (compatible; UptimeRobot/2.0; http://www.uptimerobot.com/)",
+            true,
+        ),
+        ("WhatsApp/2.23.20.0", true),
+        ("curl/8.5.0", true),
+        ("python-requests/2.31.0", true),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) HeadlessChrome/130.0.0.0 Safari/537.36",
+            true,
+        ),
+    ];
+
+    for (user_agent, is_bot) in cases {
+        assert_eq!(bots::is_bot_agent(user_agent), is_bot, "{user_agent}");
+    }
+}
+
+#[test]
+fn test_bot_requests() {
+    let cases: [(&[(&str, &str)], bool); 4] = [
+        (
+            &[("User-Agent", FIREFOX), ("Accept-Language", "en-GB")],
+            false,
+        ),
+        (
+            &[("User-Agent", "curl/8.5.0"), ("Accept-Language", "en")],
+            true,
+        ),
+        // Browsers always send both
+        (&[("User-Agent", FIREFOX)], true),
+        (&[("Accept-Language", "en-GB")], true),
+    ];
+
+    for (headers, is_bot) in cases {
+        let req = headers
+            .iter()
+            .fold(TestRequest::default(), |req, header| {
+                req.insert_header(*header)
+            })
+            .to_http_request();
+        assert_eq!(bots::is_bot(&req), is_bot, "{headers:?}");
+    }
+}
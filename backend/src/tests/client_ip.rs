@@ -0,0 +1,178 @@
+use std::net::IpAddr;
+
+use actix_web::{http::header::HeaderMap, test::TestRequest, web};
+
+use crate::client_ip::{client_ip, TrustedProxies};
+
+/// Peer, forwarding headers and the client they resolve to
+type Case<'a> = (&'a str, &'a [(&'a str, &'a str)], &'a str);
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[test]
+fn test_parse_trusted_proxies() {
+    let proxies = "127.0.0.1, 172.16.0.0/12,,::1/128"
+        .parse::<TrustedProxies>()
+        .expect("Cannot parse proxies");
+    assert!(proxies.contains(ip("127.0.0.1")));
+    assert!(proxies.contains(ip("172.18.0.5")));
+    assert!(proxies.contains(ip("::1")));
+    assert!(proxies.contains(ip("::ffff:172.18.0.5")));
+    assert!(!proxies.contains(ip("127.0.0.2")));
+    assert!(!proxies.contains(ip("172.32.0.1")));
+
+    assert_eq!("".parse::<TrustedProxies>(), Ok(TrustedProxies::default()));
+    assert!("10.0.0.0/8,nginx".parse::<TrustedProxies>().is_err());
+}
+
+#[test]
+fn test_client_ip() {
+    let proxies = "127.0.0.1,172.16.0.0/12".parse::<TrustedProxies>().unwrap();
+
+    let cases: &[Case] = &[
+        ("203.0.113.9", &[], "203.0.113.9"),
+        // Untrusted peers can't claim to be someone else
+        (
+            "203.0.113.9",
+            &[("X-Forwarded-For", "198.51.100.1")],
+            "203.0.113.9",
+        ),
+        (
+            "203.0.113.9",
+            &[("X-Real-IP", "198.51.100.1")],
+            "203.0.113.9",
+        ),
+        ("172.18.0.2", &[], "172.18.0.2"),
+        (
+            "172.18.0.2",
+            &[("X-Real-IP", "198.51.100.1")],
+            "198.51.100.1",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "198.51.100.1")],
+            "198.51.100.1",
+        ),
+        // Hops added by the client itself are ignored
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "10.9.9.9, 198.51.100.1")],
+            "198.51.100.1",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "198.51.100.1, 127.0.0.1")],
+            "198.51.100.1",
+        ),
+        (
+            "172.18.0.2",
+            &[
+                ("X-Forwarded-For", "10.9.9.9"),
+                ("X-Forwarded-For", "198.51.100.1"),
+            ],
+            "198.51.100.1",
+        ),
+        // Every hop trusted, the first is as far back as it goes
+        (
+            "127.0.0.1",
+            &[("X-Forwarded-For", "172.18.0.3")],
+            "172.18.0.3",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "garbage")],
+            "172.18.0.2",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "198.51.100.1, garbage")],
+            "172.18.0.2",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "198.51.100.1:4711")],
+            "198.51.100.1",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "2001:db8::7")],
+            "2001:db8::7",
+        ),
+        // Forwarded takes precedence, nginx replaces whatever the client sent
+        (
+            "172.18.0.2",
+            &[
+                ("Forwarded", r#"for="198.51.100.2""#),
+                ("X-Forwarded-For", "127.0.0.1, 198.51.100.1"),
+            ],
+            "198.51.100.2",
+        ),
+        (
+            "172.18.0.2",
+            &[("Forwarded", "for=198.51.100.2;proto=https")],
+            "198.51.100.2",
+        ),
+        (
+            "172.18.0.2",
+            &[("Forwarded", r#"for="[2001:db8::1]:4711", for=172.18.0.9"#)],
+            "2001:db8::1",
+        ),
+        (
+            "172.18.0.2",
+            &[("Forwarded", r#"proto=http;For="198.51.100.3:80""#)],
+            "198.51.100.3",
+        ),
+        ("172.18.0.2", &[("Forwarded", "for=unknown")], "172.18.0.2"),
+        (
+            "172.18.0.2",
+            &[("Forwarded", "for=_hidden, for=198.51.100.4")],
+            "198.51.100.4",
+        ),
+        (
+            "172.18.0.2",
+            &[("X-Forwarded-For", "[2001:db8::1]:4711")],
+            "2001:db8::1",
+        ),
+        (
+            "::ffff:172.18.0.2",
+            &[("X-Real-IP", "198.51.100.1")],
+            "198.51.100.1",
+        ),
+    ];
+
+    for (peer, headers, client) in cases {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers.iter() {
+            map.append(name.parse().unwrap(), value.parse().unwrap());
+        }
+        assert_eq!(
+            proxies.client_ip(ip(peer), &map),
+            ip(client),
+            "{peer} {headers:?}"
+        );
+    }
+}
+
+#[test]
+fn test_client_ip_of_request() {
+    let forwarded = || {
+        TestRequest::default()
+            .peer_addr("127.0.0.1:54321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1"))
+    };
+
+    // Without trusted proxies the peer is the client
+    let req = forwarded().to_http_request();
+    assert_eq!(client_ip(&req), Some(ip("127.0.0.1")));
+
+    let req = forwarded()
+        .app_data(web::Data::new(
+            "127.0.0.1".parse::<TrustedProxies>().unwrap(),
+        ))
+        .to_http_request();
+    assert_eq!(client_ip(&req), Some(ip("198.51.100.1")));
+
+    assert_eq!(client_ip(&TestRequest::default().to_http_request()), None);
+}
@@ -8,8 +8,8 @@ mod tests {
     };
     use model::{
         cart::NewCart,
-        item::{Item, NewItem},
         order::{NewOrder, Order},
+        product::ProductFields,
     };
 
     #[test]
@@ -49,12 +49,19 @@ mod tests {
 
         let new_carts = cart
             .iter()
-            .map(|(item, qty)| NewCart {
-                order_id: *inserted_id.as_ref().unwrap(),
-                item_id: *item as i32,
-                quantity: *qty as i32,
+            .map(|(item, qty)| {
+                NewCart::new(
+                    *inserted_id.as_ref().unwrap(),
+                    *item as i32,
+                    *qty as i32,
+                    10_00,
+                    "",
+                    "",
+                )
             })
-            .collect::<Vec<NewCart>>();
+            .collect::<Result<Vec<NewCart>, String>>()
+            .expect("Cannot build carts");
+        assert!(NewCart::new(1, 1, i32::MAX, 10_00, "", "").is_err());
 
         let insert = diesel::insert_into(carts::table)
             .values(&new_carts)
@@ -141,23 +148,47 @@ mod tests {
     fn insert_stock() {
         use model::schema::stock::{self, id};
         let stock = fs::read_to_string("stock.json").unwrap();
-        let stock: Vec<Item> = serde_json::from_str(&stock).unwrap();
+        let stock: Vec<ProductFields> = serde_json::from_str(&stock).unwrap();
 
-        let num_items = stock.len();
+        let num_items = stock
+            .iter()
+            .map(|product| product.variants.len())
+            .sum::<usize>();
 
         let db = test_db::TestDb::new();
         let mut conn = db.connection();
 
-        let ins: Vec<NewItem> = stock.iter().map(NewItem::from).collect();
-
-        let res = diesel::insert_into(stock::table)
-            .values(ins)
-            .execute(&mut conn);
-
-        assert!(res.is_ok());
+        assert_eq!(test_db::insert_stock(&mut conn), stock.len());
 
         let res = SelectDsl::select(stock::table, count(id)).get_result::<i64>(&mut conn);
         assert!(res.is_ok());
         assert_eq!(num_items, res.unwrap() as usize);
     }
+
+    #[test]
+    fn kind_conversion_is_checked() {
+        use model::{
+            kind::{Kind, TableKind},
+            schema::kinds,
+        };
+
+        let db = test_db::TestDb::new();
+        let mut conn = db.connection();
+
+        let kinds = kinds::table
+            .load::<TableKind>(&mut conn)
+            .expect("Cannot fetch kinds");
+        assert_eq!(kinds.len(), 3);
+        assert!(kinds.into_iter().all(|kind| Kind::try_from(kind).is_ok()));
+
+        let invalid = TableKind {
+            id: 3,
+            name: "Sticker".to_string(),
+            display_name: "Sticker".to_string(),
+            price: 2_00,
+            shipping_class: "Pigeon".to_string(),
+            low_stock_threshold: 0,
+        };
+        assert!(Kind::try_from(invalid).is_err());
+    }
 }
@@ -0,0 +1,43 @@
+use actix_web::{http::header, test::TestRequest};
+
+use crate::funnel;
+
+const FIREFOX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0";
+
+fn browser() -> TestRequest {
+    TestRequest::default()
+        .insert_header((header::USER_AGENT, FIREFOX))
+        .insert_header((header::ACCEPT_LANGUAGE, "en-GB"))
+}
+
+#[test]
+fn test_start_session() {
+    // First requests get a session, available to the handler straight away
+    let req = browser().to_http_request();
+    let cookie = funnel::start_session(&req).expect("No session started");
+    assert_eq!(cookie.name(), funnel::SESSION_COOKIE);
+    assert_eq!(funnel::session(&req).as_deref(), Some(cookie.value()));
+
+    let req = browser()
+        .cookie(actix_web::cookie::Cookie::new(
+            funnel::SESSION_COOKIE,
+            "abc",
+        ))
+        .to_http_request();
+    assert!(funnel::start_session(&req).is_none());
+    assert_eq!(funnel::session(&req).as_deref(), Some("abc"));
+
+    // Neither bots nor visitors opting out are followed
+    let req = browser().insert_header(("DNT", "1")).to_http_request();
+    assert!(funnel::start_session(&req).is_none());
+    assert_eq!(funnel::session(&req), None);
+    let req = TestRequest::default()
+        .insert_header((header::USER_AGENT, "curl/8.5.0"))
+        .cookie(actix_web::cookie::Cookie::new(
+            funnel::SESSION_COOKIE,
+            "abc",
+        ))
+        .to_http_request();
+    assert!(funnel::start_session(&req).is_none());
+    assert_eq!(funnel::session(&req), None);
+}
@@ -0,0 +1,70 @@
+use std::net::IpAddr;
+
+use crate::geoip::GeoIp;
+
+/// Hand built city database covering 81.2.69.0/24 (London), 216.160.83.56/29
+/// (Milton, Washington) and 2.125.160.216/29 (United Kingdom, no city)
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/geoip.mmdb");
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[test]
+fn test_locate() {
+    let geoip = GeoIp::open(FIXTURE);
+
+    let london = geoip.locate(ip("81.2.69.160"));
+    assert_eq!(
+        (london.country.as_str(), london.state.as_str(), london.city),
+        ("United Kingdom", "England", Some("London".to_string()))
+    );
+    let milton = geoip.locate(ip("216.160.83.60"));
+    assert_eq!(
+        (milton.country.as_str(), milton.state.as_str(), milton.city),
+        ("United States", "Washington", Some("Milton".to_string()))
+    );
+
+    // Missing fields are left empty
+    let country_only = geoip.locate(ip("2.125.160.220"));
+    assert_eq!(
+        (
+            country_only.country.as_str(),
+            country_only.state.as_str(),
+            country_only.city
+        ),
+        ("United Kingdom", "", None)
+    );
+
+    for unknown in ["127.0.0.1", "10.1.2.3", "8.8.8.8", "2001:db8::1"] {
+        let location = geoip.locate(ip(unknown));
+        assert_eq!(
+            (location.country.as_str(), location.city),
+            ("", None),
+            "{unknown}"
+        );
+    }
+}
+
+#[test]
+fn test_reload() {
+    let path = std::env::temp_dir().join(format!("kiggyshop_geoip_{}.mmdb", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // A missing database isn't fatal, lookups just come back empty
+    let geoip = GeoIp::open(&path);
+    assert_eq!(geoip.locate(ip("81.2.69.160")).country, "");
+    assert_eq!(geoip.reload_if_changed(), Ok(false));
+
+    std::fs::copy(FIXTURE, &path).expect("Cannot copy fixture");
+    assert_eq!(geoip.reload_if_changed(), Ok(true));
+    assert_eq!(geoip.locate(ip("81.2.69.160")).country, "United Kingdom");
+    assert_eq!(geoip.reload_if_changed(), Ok(false));
+
+    // A broken update keeps the database that was loaded
+    std::fs::write(&path, b"not a database").expect("Cannot overwrite fixture");
+    assert!(geoip.reload().is_err());
+    assert_eq!(geoip.locate(ip("81.2.69.160")).country, "United Kingdom");
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,94 @@
+use log::kv::Value;
+use model::address::Address;
+
+use crate::{
+    logging::{redact_email, redact_emails, to_json, Redacted},
+    request_id::RequestId,
+};
+
+#[test]
+fn test_redact_emails() {
+    let cases = [
+        ("kiggy@kiggy.shop", "k***@kiggy.shop"),
+        (
+            "Invalid email kiggy.moth+shop@mail.kiggy.shop: unknown host",
+            "Invalid email k***@mail.kiggy.shop: unknown host",
+        ),
+        (
+            "to <kiggy@kiggy.shop>, <moth@kiggy.shop>.",
+            "to <k***@kiggy.shop>, <m***@kiggy.shop>.",
+        ),
+        // Not emails
+        ("@kiggy.shop", "@kiggy.shop"),
+        ("kiggy@localhost", "kiggy@localhost"),
+        ("a @ b", "a @ b"),
+        ("no at sign", "no at sign"),
+    ];
+
+    for (text, redacted) in cases {
+        assert_eq!(redact_emails(text), redacted, "{text}");
+    }
+    assert_eq!(redact_email("not an email"), "***");
+}
+
+#[test]
+fn test_redacted() {
+    let address = Address {
+        name: "Kiggy".to_string(),
+        number: 12,
+        street: "Moth Lane".to_string(),
+        city: "Portland".to_string(),
+        state: "OR".to_string(),
+        zipcode: 97201,
+    };
+
+    assert_eq!(Redacted(&address).to_string(), "Portland, OR");
+    assert_eq!(Redacted("kiggy@kiggy.shop").to_string(), "k***@kiggy.shop");
+}
+
+#[test]
+fn test_to_json() {
+    let time = chrono::NaiveDate::from_ymd_opt(2026, 10, 25)
+        .unwrap()
+        .and_hms_milli_opt(9, 30, 0, 250)
+        .unwrap();
+    let fields = [
+        ("order_id", Value::from(7u64)),
+        ("email", Value::from("kiggy@kiggy.shop")),
+        ("refunded", Value::from(true)),
+        // Can't replace the standard fields
+        ("level", Value::from("nonsense")),
+    ];
+    let line = to_json(
+        &log::Record::builder()
+            .level(log::Level::Warn)
+            .target("kiggyserve::api::stripe")
+            .args(format_args!("Cannot email kiggy@kiggy.shop"))
+            .key_values(&fields)
+            .build(),
+        Some(RequestId::from_headers(
+            &[(
+                crate::request_id::HEADER,
+                actix_web::http::header::HeaderValue::from_static("abc-123"),
+            )]
+            .into_iter()
+            .collect(),
+        )),
+        time,
+    );
+
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+        serde_json::json!({
+            "time": "2026-10-25T09:30:00.250Z",
+            "level": "WARN",
+            "target": "kiggyserve::api::stripe",
+            "request_id": "abc-123",
+            "message": "Cannot email k***@kiggy.shop",
+            "order_id": 7,
+            "email": "k***@kiggy.shop",
+            "refunded": true,
+        })
+    );
+    assert!(!line.contains('\n'));
+}
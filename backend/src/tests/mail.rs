@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use lettre::transport::smtp::authentication::Credentials;
-use model::{item, order};
+use model::{inventory, order, product};
 
 use crate::{
     api::stripe,
@@ -14,7 +14,8 @@ async fn test_confirmation_email() {
     let order = serde_json::from_slice::<order::Order>(include_bytes!("mock_order.json")).unwrap();
 
     let items =
-        serde_json::from_slice::<Vec<item::Item>>(include_bytes!("../../stock.json")).unwrap();
+        serde_json::from_slice::<Vec<product::ProductFields>>(include_bytes!("../../stock.json"))
+            .unwrap();
 
     let order::Order {
         name,
@@ -28,7 +29,7 @@ async fn test_confirmation_email() {
     let cart = cart
         .iter()
         .map(|(id, qty)| {
-            let price = items[*id as usize].price();
+            let price = items[*id as usize].variants[0].price.unwrap_or(20_00);
 
             (
                 *id,
@@ -36,6 +37,7 @@ async fn test_confirmation_email() {
                     price,
                     title: items[*id as usize].title.clone(),
                     quantity: *qty,
+                    ships_on: None,
                 },
             )
         })
@@ -84,6 +86,13 @@ async fn test_shipping() {
         total: total as i32,
         shipped: false,
         tracking_number: Some("URSILLY8901".to_string()),
+        created: chrono::Utc::now().naive_utc(),
+        subtotal: total as i32,
+        shipping: 0,
+        tax: 0,
+        refunded: 0,
+        payment_intent: None,
+        warnings: None,
     };
     let shipped =
         shipped::Shipped::try_from(table_order).expect("Cannot convert <TableOrder> to <Shipped>");
@@ -101,3 +110,26 @@ async fn test_shipping() {
         panic!("Cannot send confirmation test email: {e}");
     }
 }
+
+#[test]
+fn test_low_stock_alert() {
+    let item = |sku: &str, quantity: i32| inventory::LowStock {
+        item: 1,
+        title: "moth".to_string(),
+        sku: sku.to_string(),
+        quantity,
+        threshold: 2,
+    };
+    let alert = mail::low_stock::LowStock::new(
+        "Order #3".to_string(),
+        vec![item("MOTH-S", 1), item("MOTH-P", -3)],
+    );
+
+    assert_eq!(alert.subject(), "Items have sold out");
+    let plaintext = alert.render_plaintext();
+    assert!(plaintext.starts_with("Order #3 left these items"));
+    assert!(plaintext.contains("Sold out, 3 owed"));
+    assert!(askama::Template::render(&alert)
+        .unwrap()
+        .contains("Sold out, 3 owed"));
+}
@@ -1,4 +1,13 @@
 mod api;
+mod bots;
+mod client_ip;
 mod db;
+mod funnel;
+mod geoip;
+mod logging;
 mod mail;
+mod monitoring;
+mod request_id;
 mod test_db;
+mod user_agent;
+mod visits;
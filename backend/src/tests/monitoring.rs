@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use crate::monitoring::{Metrics, MetricsAccess, PoolUsage, WebhookOutcome};
+
+const POOL: PoolUsage = PoolUsage {
+    connections: 3,
+    idle_connections: 2,
+    max_size: 10,
+};
+
+#[test]
+fn test_render() {
+    let metrics = Metrics::default();
+    metrics.observe_request("GET", "/api/stock/{id}", 200, Duration::from_millis(20));
+    metrics.observe_request("GET", "/api/stock/{id}", 200, Duration::from_millis(300));
+    metrics.observe_request("GET", "/api/stock/{id}", 404, Duration::from_secs(20));
+    metrics.webhook_event("checkout.session.completed", WebhookOutcome::Handled);
+    metrics.webhook_event("checkout.session.completed", WebhookOutcome::Handled);
+    metrics.webhook_event("customer.created", WebhookOutcome::Ignored);
+    metrics.email("confirmation", true);
+    metrics.email("confirmation", false);
+    metrics.checkout_link_failed();
+
+    let rendered = metrics.render(POOL);
+    let lines = rendered.lines().collect::<Vec<&str>>();
+    for expected in [
+        "# TYPE kiggyserve_http_requests_total counter",
+        r#"kiggyserve_http_requests_total{method="GET",route="/api/stock/{id}",status="200"} 2"#,
+        r#"kiggyserve_http_requests_total{method="GET",route="/api/stock/{id}",status="404"} 1"#,
+        "# TYPE kiggyserve_http_request_duration_seconds histogram",
+        // Buckets count every observation up to their bound
+        r#"kiggyserve_http_request_duration_seconds_bucket{method="GET",route="/api/stock/{id}",le="0.01"} 0"#,
+        r#"kiggyserve_http_request_duration_seconds_bucket{method="GET",route="/api/stock/{id}",le="0.025"} 1"#,
+        r#"kiggyserve_http_request_duration_seconds_bucket{method="GET",route="/api/stock/{id}",le="0.5"} 2"#,
+        r#"kiggyserve_http_request_duration_seconds_bucket{method="GET",route="/api/stock/{id}",le="10"} 2"#,
+        r#"kiggyserve_http_request_duration_seconds_bucket{method="GET",route="/api/stock/{id}",le="+Inf"} 3"#,
+        r#"kiggyserve_http_request_duration_seconds_count{method="GET",route="/api/stock/{id}"} 3"#,
+        "kiggyserve_db_pool_connections 3",
+        "kiggyserve_db_pool_idle_connections 2",
+        "kiggyserve_db_pool_max_connections 10",
+        r#"kiggyserve_webhook_events_total{type="checkout.session.completed",outcome="handled"} 2"#,
+        r#"kiggyserve_webhook_events_total{type="customer.created",outcome="ignored"} 1"#,
+        r#"kiggyserve_emails_total{kind="confirmation",outcome="failed"} 1"#,
+        r#"kiggyserve_emails_total{kind="confirmation",outcome="sent"} 1"#,
+        "kiggyserve_checkout_link_failures_total 1",
+    ] {
+        assert!(lines.contains(&expected), "{expected} not in:\n{rendered}");
+    }
+}
+
+#[test]
+fn test_render_escapes_labels() {
+    let metrics = Metrics::default();
+    metrics.observe_request("GET", "/a\"b\\c", 200, Duration::ZERO);
+
+    assert!(metrics
+        .render(POOL)
+        .contains(r#"{method="GET",route="/a\"b\\c",status="200"} 1"#));
+}
+
+#[test]
+fn test_metrics_access() {
+    let access = "127.0.0.1, 10.0.0.0/8"
+        .parse::<MetricsAccess>()
+        .expect("Cannot parse networks");
+    assert!(access.is_enabled());
+    assert!(access.allows("127.0.0.1".parse().unwrap()));
+    assert!(access.allows("10.1.2.3".parse().unwrap()));
+    // IPv4 addresses mapped to IPv6 are their IPv4 ones
+    assert!(access.allows("::ffff:10.1.2.3".parse().unwrap()));
+    assert!(!access.allows("192.168.1.1".parse().unwrap()));
+
+    assert!(!"".parse::<MetricsAccess>().unwrap().is_enabled());
+    assert!("10.0.0.0/33".parse::<MetricsAccess>().is_err());
+}
@@ -0,0 +1,67 @@
+use actix_web::http::header::{HeaderMap, HeaderValue};
+
+use crate::request_id::{self, RequestId, HEADER};
+
+fn headers(id: &'static str) -> HeaderMap {
+    [(HEADER, HeaderValue::from_static(id))]
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn test_from_headers() {
+    assert_eq!(
+        RequestId::from_headers(&headers(" abc-123 ")).to_string(),
+        "abc-123"
+    );
+
+    // Missing, blank, long or spaced out IDs are replaced so they can't forge
+    // log lines
+    let long = "a".repeat(65);
+    let long = HeaderValue::from_str(&long).unwrap();
+    for headers in [
+        HeaderMap::new(),
+        headers("   "),
+        headers("abc 123"),
+        [(HEADER, long)].into_iter().collect(),
+    ] {
+        let id = RequestId::from_headers(&headers).to_string();
+        assert_eq!(id.len(), 32, "{id}");
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+    assert_ne!(RequestId::new(), RequestId::new());
+}
+
+#[test]
+fn test_enter() {
+    let (a, b) = (RequestId::new(), RequestId::new());
+    assert_eq!(RequestId::current(), None);
+    {
+        let _a = a.enter();
+        {
+            let _b = b.enter();
+            assert_eq!(RequestId::current(), Some(b.clone()));
+        }
+        assert_eq!(RequestId::current(), Some(a.clone()));
+    }
+    assert_eq!(RequestId::current(), None);
+}
+
+#[actix_web::test]
+async fn test_carried_to_other_work() {
+    let id = RequestId::new();
+
+    let (blocked, spawned) = request_id::scope(Some(id.clone()), async {
+        let blocked = request_id::block(RequestId::current).await.unwrap();
+        let spawned = request_id::spawn(async { RequestId::current() })
+            .await
+            .unwrap();
+        (blocked, spawned)
+    })
+    .await;
+
+    assert_eq!(blocked, Some(id.clone()));
+    assert_eq!(spawned, Some(id));
+    // It's only current while the request's work is polled
+    assert_eq!(RequestId::current(), None);
+}
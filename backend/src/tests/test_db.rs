@@ -1,4 +1,5 @@
-use diesel::{Connection, SqliteConnection};
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection};
+use model::{product::ProductFields, schema::kinds};
 use std::{path::PathBuf, sync::atomic::AtomicU32};
 
 static TEST_DB_COUNTER: AtomicU32 = AtomicU32::new(0);
@@ -26,6 +27,46 @@ impl TestDb {
                 path.to_str().expect("Malformed test db path"),
                 include_str!("../../../model/migrations/2024-05-19-142608_init/up.sql"),
                 include_str!("../../../model/migrations/2024-05-19-142611_address/up.sql"),
+                include_str!("../../../model/migrations/2024-06-02-231054_metrics/up.sql"),
+                include_str!("../../../model/migrations/2024-06-05-121417_tracking/up.sql"),
+                include_str!("../../../model/migrations/2026-10-19-101512_kinds/up.sql"),
+                include_str!("../../../model/migrations/2026-10-19-134207_variants/up.sql"),
+                include_str!("../../../model/migrations/2026-10-19-160331_images/up.sql"),
+                include_str!("../../../model/migrations/2026-10-19-183954_taxonomy/up.sql"),
+                include_str!("../../../model/migrations/2026-10-19-201845_search/up.sql"),
+                include_str!(
+                    "../../../model/migrations/2026-10-19-223012_catalogue_version/up.sql"
+                ),
+                include_str!(
+                    "../../../model/migrations/2026-10-20-091407_inventory_movements/up.sql"
+                ),
+                include_str!("../../../model/migrations/2026-10-20-142836_low_stock/up.sql"),
+                include_str!(
+                    "../../../model/migrations/2026-10-20-165203_stock_notifications/up.sql"
+                ),
+                include_str!("../../../model/migrations/2026-10-20-190418_sales_modes/up.sql"),
+                include_str!("../../../model/migrations/2026-10-21-094733_limited_drops/up.sql"),
+                include_str!("../../../model/migrations/2026-10-21-131520_archive_items/up.sql"),
+                include_str!("../../../model/migrations/2026-10-21-160204_price_history/up.sql"),
+                include_str!("../../../model/migrations/2026-10-22-091200_visit_privacy/up.sql"),
+                include_str!("../../../model/migrations/2026-10-22-140512_visit_referrers/up.sql"),
+                include_str!("../../../model/migrations/2026-10-23-083045_bot_visits/up.sql"),
+                include_str!(
+                    "../../../model/migrations/2026-10-23-141210_user_agent_columns/up.sql"
+                ),
+                include_str!("../../../model/migrations/2026-10-24-102233_funnel_events/up.sql"),
+                include_str!("../../../model/migrations/2026-10-25-091530_order_totals/up.sql"),
+                include_str!("../../../model/migrations/2026-10-26-093214_order_warnings/up.sql"),
+                include_str!(
+                    "../../../model/migrations/2026-10-26-141805_daily_unique_visitors/up.sql"
+                ),
+                include_str!("../../../model/migrations/2026-10-27-104512_cart_kinds/up.sql"),
+                include_str!(
+                    "../../../model/migrations/2026-10-27-141920_drop_movement_actor/up.sql"
+                ),
+                include_str!(
+                    "../../../model/migrations/2026-10-28-093140_notification_resends/up.sql"
+                ),
             ])
             .spawn();
         if let Ok(mut child) = migration {
@@ -55,6 +96,31 @@ impl TestDb {
     }
 }
 
+/// Inserts the contents of stock.json, returning the number of products inserted
+pub fn insert_stock(conn: &mut SqliteConnection) -> usize {
+    let stock: Vec<ProductFields> = serde_json::from_str(include_str!("../../stock.json"))
+        .expect("Cannot deserialize stock.json");
+
+    for product in &stock {
+        let kinds = product
+            .variants
+            .iter()
+            .map(|variant| {
+                kinds::table
+                    .select(kinds::id)
+                    .filter(kinds::name.eq(&variant.kind))
+                    .first::<i32>(conn)
+                    .expect("Unknown kind in stock.json")
+            })
+            .collect::<Vec<i32>>();
+
+        crate::api::stock::insert_product(conn, product, &kinds, None, None)
+            .expect("Cannot insert stock.json into DB");
+    }
+
+    stock.len()
+}
+
 impl Drop for TestDb {
     fn drop(&mut self) {
         if !self.delete_on_drop {
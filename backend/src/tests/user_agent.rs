@@ -0,0 +1,245 @@
+use model::user::{Device, DeviceClass};
+
+use crate::user_agent;
+
+/// User agent, then the browser, its version, the OS, its version and the
+/// device class it parses into
+type Case<'a> = (
+    &'a str,
+    Option<&'a str>,
+    Option<&'a str>,
+    Option<&'a str>,
+    Option<&'a str>,
+    DeviceClass,
+);
+
+#[test]
+fn test_parse_user_agents() {
+    use DeviceClass::*;
+
+    let cases: &[Case] = &[
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36",
+            Some("Chrome"),
+            Some("130.0.0.0"),
+            Some("Windows"),
+            Some("10"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36 Edg/130.0.2849.56",
+            Some("Edge"),
+            Some("130.0.2849.56"),
+            Some("Windows"),
+            Some("10"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 6.1; WOW64; Trident/7.0; rv:11.0) like Gecko",
+            Some("Internet Explorer"),
+            Some("11.0"),
+            Some("Windows"),
+            Some("7"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36 OPR/114.0.0.0",
+            Some("Opera"),
+            Some("114.0.0.0"),
+            Some("Windows"),
+            Some("10"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.0 Safari/605.1.15",
+            Some("Safari"),
+            Some("18.0"),
+            Some("macOS"),
+            Some("10.15.7"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:131.0) Gecko/20100101 Firefox/131.0",
+            Some("Firefox"),
+            Some("131.0"),
+            Some("macOS"),
+            Some("10.15"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0",
+            Some("Firefox"),
+            Some("131.0"),
+            Some("Linux"),
+            None,
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:130.0) Gecko/20100101 Firefox/130.0",
+            Some("Firefox"),
+            Some("130.0"),
+            Some("Linux"),
+            None,
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36",
+            Some("Chrome"),
+            Some("130.0.0.0"),
+            Some("ChromeOS"),
+            Some("14541.0.0"),
+            Desktop,
+        ),
+        (
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 18_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.0 Mobile/15E148 Safari/604.1",
+            Some("Safari"),
+            Some("18.0"),
+            Some("iOS"),
+            Some("18.0"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_6_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) CriOS/130.0.6723.37 Mobile/15E148 Safari/604.1",
+            Some("Chrome"),
+            Some("130.0.6723.37"),
+            Some("iOS"),
+            Some("17.6.1"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 18_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) FxiOS/131.0 Mobile/15E148 Safari/605.1.15",
+            Some("Firefox"),
+            Some("131.0"),
+            Some("iOS"),
+            Some("18.0"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (iPad; CPU OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1",
+            Some("Safari"),
+            Some("17.4"),
+            Some("iPadOS"),
+            Some("17.4"),
+            Tablet,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.6723.58 Mobile Safari/537.36",
+            Some("Chrome"),
+            Some("130.0.6723.58"),
+            Some("Android"),
+            Some("14"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 13; SM-X700) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.6723.58 Safari/537.36",
+            Some("Chrome"),
+            Some("130.0.6723.58"),
+            Some("Android"),
+            Some("13"),
+            Tablet,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 14; SAMSUNG SM-S921B) AppleWebKit/537.36 (KHTML, like Gecko) SamsungBrowser/26.0 Chrome/122.0.0.0 Mobile Safari/537.36",
+            Some("Samsung Internet"),
+            Some("26.0"),
+            Some("Android"),
+            Some("14"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (Android 14; Mobile; rv:131.0) Gecko/131.0 Firefox/131.0",
+            Some("Firefox"),
+            Some("131.0"),
+            Some("Android"),
+            Some("14"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (Android 14; Tablet; rv:131.0) Gecko/131.0 Firefox/131.0",
+            Some("Firefox"),
+            Some("131.0"),
+            Some("Android"),
+            Some("14"),
+            Tablet,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 9; KFTRWI) AppleWebKit/537.36 (KHTML, like Gecko) Silk/130.3.1 like Chrome/130.0.6723.102 Safari/537.36",
+            Some("Silk"),
+            Some("130.3.1"),
+            Some("Android"),
+            Some("9"),
+            Tablet,
+        ),
+        (
+            "Mozilla/5.0 (Mobile; Windows Phone 8.1; Android 4.0; ARM; Trident/7.0; Touch; rv:11.0; IEMobile/11.0; NOKIA; Lumia 635) like iPhone OS 7_0_3 Mac OS X AppleWebKit/537 (KHTML, like Gecko) Mobile Safari/537",
+            Some("Internet Explorer"),
+            Some("11.0"),
+            Some("Windows Phone"),
+            Some("8.1"),
+            Phone,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 6.0.1; Nexus 5X Build/MMB29P) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.6723.69 Mobile Safari/537.36 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+            Some("Chrome"),
+            Some("130.0.6723.69"),
+            Some("Android"),
+            Some("6.0.1"),
+            Bot,
+        ),
+        (
+            "facebookexternalhit/1.1 (+http://www.facebook.com/externalhit_uatext.php)",
+            None,
+            None,
+            None,
+            None,
+            Bot,
+        ),
+        ("curl/8.5.0", None, None, None, None, Bot),
+        ("", None, None, None, None, Unknown),
+    ];
+
+    for &(user_agent, browser, browser_version, os, os_version, device_class) in cases {
+        let agent = user_agent::parse(user_agent);
+        assert_eq!(
+            (
+                agent.browser.as_deref(),
+                agent.browser_version.as_deref(),
+                agent.os.as_deref(),
+                agent.os_version.as_deref(),
+                agent.device_class,
+            ),
+            (browser, browser_version, os, os_version, device_class),
+            "{user_agent}"
+        );
+    }
+}
+
+#[test]
+fn test_device_from_user_agent() {
+    let cases = [
+        ("Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0", Device::Linux),
+        (
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.6723.58 Mobile Safari/537.36",
+            Device::Android,
+        ),
+        (
+            "Mozilla/5.0 (iPad; CPU OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1",
+            Device::Ipad,
+        ),
+        (
+            "Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36",
+            Device::ChromeOs,
+        ),
+        ("curl/8.5.0", Device::Unknown),
+    ];
+
+    for (user_agent, device) in cases {
+        assert_eq!(
+            Device::from(&user_agent::parse(user_agent)),
+            device,
+            "{user_agent}"
+        );
+        // Devices are stored by name
+        assert_eq!(device.to_string().parse::<Device>(), Ok(device));
+    }
+}
@@ -0,0 +1,153 @@
+use actix_web::test::TestRequest;
+use chrono::NaiveDate;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use model::{
+    schema::{daily_unique_visitors, daily_visits, users, visit_salts},
+    user,
+};
+
+use crate::{tests::test_db, visits};
+
+fn day(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2026, 10, day).unwrap()
+}
+
+#[test]
+fn test_daily_salt() {
+    let db = test_db::TestDb::new();
+    let mut conn = db.connection();
+    let ip = "198.51.100.1".parse().unwrap();
+
+    let first = visits::daily_salt(&mut conn, day(1)).expect("Cannot create salt");
+    assert_eq!(visits::daily_salt(&mut conn, day(1)), Ok(first.clone()));
+    assert_eq!(
+        visits::hash_visitor(&first, ip),
+        visits::hash_visitor(&first, ip)
+    );
+    assert_ne!(
+        visits::hash_visitor(&first, ip),
+        visits::hash_visitor(&first, "198.51.100.2".parse().unwrap())
+    );
+
+    // A new day's salt replaces the old one
+    let second = visits::daily_salt(&mut conn, day(2)).expect("Cannot create salt");
+    assert_ne!(first, second);
+    assert_ne!(
+        visits::hash_visitor(&first, ip),
+        visits::hash_visitor(&second, ip)
+    );
+    assert_eq!(
+        visit_salts::table
+            .select(visit_salts::day)
+            .get_results::<NaiveDate>(&mut conn),
+        Ok(vec![day(2)])
+    );
+}
+
+#[test]
+fn test_roll_up() {
+    let db = test_db::TestDb::new();
+    let mut conn = db.connection();
+
+    let visits = [
+        (1, "a", "UK", "Linux", false),
+        (1, "a", "UK", "Linux", false),
+        (1, "b", "UK", "Linux", false),
+        (1, "c", "US", "Mac", false),
+        (1, "f", "US", "Linux", true),
+        (2, "d", "UK", "Linux", false),
+        (20, "e", "UK", "Linux", false),
+    ];
+    for (visit_day, visitor, country, device, is_bot) in visits {
+        diesel::insert_into(users::table)
+            .values((
+                users::visitor.eq(visitor),
+                users::device.eq(device),
+                users::time.eq(day(visit_day).and_hms_opt(12, 0, 0)),
+                users::country.eq(country),
+                users::state.eq(""),
+                users::is_bot.eq(is_bot),
+            ))
+            .execute(&mut conn)
+            .expect("Cannot insert visit");
+    }
+
+    // Keeping 10 days on the 13th keeps the 3rd onwards, the bot isn't rolled up
+    assert_eq!(visits::roll_up(&mut conn, 10, day(13)), Ok(6));
+    assert_eq!(visits::roll_up(&mut conn, 10, day(13)), Ok(0));
+    let rollups = daily_visits::table
+        .select(user::DailyVisits::as_select())
+        .order((daily_visits::day, daily_visits::country))
+        .get_results::<user::DailyVisits>(&mut conn)
+        .expect("Cannot fetch rollups");
+    let rollup = |visit_day, country: &str, device: &str, visits| user::DailyVisits {
+        day: day(visit_day),
+        country: country.to_string(),
+        state: String::new(),
+        device: device.to_string(),
+        referrer: String::new(),
+        visits,
+    };
+    assert_eq!(
+        rollups,
+        vec![
+            rollup(1, "UK", "Linux", 3),
+            rollup(1, "US", "Mac", 1),
+            rollup(2, "UK", "Linux", 1),
+        ]
+    );
+    let visitors = daily_unique_visitors::table
+        .select((
+            daily_unique_visitors::day,
+            daily_unique_visitors::breakdown,
+            daily_unique_visitors::name,
+            daily_unique_visitors::unique_visitors,
+        ))
+        .filter(daily_unique_visitors::breakdown.eq_any(["total", "country"]))
+        .order((daily_unique_visitors::day, daily_unique_visitors::breakdown))
+        .then_order_by(daily_unique_visitors::name)
+        .get_results::<(NaiveDate, String, String, i32)>(&mut conn)
+        .expect("Cannot fetch rollups");
+    let visitors_of = |visit_day, breakdown: &str, name: &str, unique_visitors| {
+        (
+            day(visit_day),
+            breakdown.to_string(),
+            name.to_string(),
+            unique_visitors,
+        )
+    };
+    assert_eq!(
+        visitors,
+        vec![
+            visitors_of(1, "country", "UK", 2),
+            visitors_of(1, "country", "US", 1),
+            visitors_of(1, "total", "", 3),
+            visitors_of(2, "country", "UK", 1),
+            visitors_of(2, "total", "", 1),
+        ]
+    );
+    assert_eq!(users::table.count().get_result::<i64>(&mut conn), Ok(1));
+}
+
+#[test]
+fn test_opted_out() {
+    let cases = [
+        (None, false),
+        (Some(("DNT", "1")), true),
+        (Some(("DNT", "0")), false),
+        (Some(("Sec-GPC", "1")), true),
+        (Some(("Sec-GPC", " 1 ")), true),
+    ];
+
+    for (header, opted_out) in cases {
+        let mut req = TestRequest::default();
+        if let Some(header) = header {
+            req = req.insert_header(header);
+        }
+        assert_eq!(
+            visits::opted_out(&req.to_http_request()),
+            opted_out,
+            "{header:?}"
+        );
+    }
+}
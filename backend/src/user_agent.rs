@@ -0,0 +1,154 @@
+// Parses user agents into browser, OS and device class for visitor analytics.
+// Most browsers' user agents also name other browsers and OSes for
+// compatibility, so the more specific names are looked for first
+use model::user::{os, DeviceClass, UserAgent};
+
+use crate::bots;
+
+/// Browsers by the product token carrying their version. IE Edge's user agent
+/// also has Chrome's and Safari's tokens, so it comes before them
+const BROWSERS: &[(&str, &str)] = &[
+    ("EdgA/", "Edge"),
+    ("EdgiOS/", "Edge"),
+    ("Edg/", "Edge"),
+    // EdgeHTML, before Edge was based on Chromium
+    ("Edge/", "Edge"),
+    ("OPR/", "Opera"),
+    ("OPiOS/", "Opera"),
+    ("SamsungBrowser/", "Samsung Internet"),
+    ("YaBrowser/", "Yandex Browser"),
+    ("Vivaldi/", "Vivaldi"),
+    ("UCBrowser/", "UC Browser"),
+    ("Silk/", "Silk"),
+    ("DuckDuckGo/", "DuckDuckGo"),
+    ("FxiOS/", "Firefox"),
+    ("Firefox/", "Firefox"),
+    ("CriOS/", "Chrome"),
+    ("Chromium/", "Chromium"),
+    ("Chrome/", "Chrome"),
+];
+
+/// The version following `token`, with iOS and macOS style underscores made
+/// dots, IE "Mac OS X 10_15_7" -> "10.15.7"
+fn version_after(user_agent: &str, token: &str) -> Option<String> {
+    let start = user_agent.find(token)? + token.len();
+    let version = user_agent[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '_')
+        .map(|c| if c == '_' { '.' } else { c })
+        .collect::<String>();
+    let version = version.trim_end_matches('.');
+
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+fn browser(user_agent: &str) -> Option<(&'static str, Option<String>)> {
+    if let Some((token, name)) = BROWSERS
+        .iter()
+        .find(|(token, _)| user_agent.contains(token))
+    {
+        return Some((name, version_after(user_agent, token)));
+    }
+
+    if user_agent.contains("Safari/") && user_agent.contains("Version/") {
+        Some(("Safari", version_after(user_agent, "Version/")))
+    } else if user_agent.contains("MSIE ") {
+        Some(("Internet Explorer", version_after(user_agent, "MSIE ")))
+    } else if user_agent.contains("Trident/") {
+        // IE 11 dropped its MSIE token
+        Some(("Internet Explorer", version_after(user_agent, "rv:")))
+    } else {
+        None
+    }
+}
+
+fn windows_version(nt_version: &str) -> String {
+    match nt_version {
+        // Windows 11 reports itself as 10 too
+        "10.0" => "10",
+        "6.3" => "8.1",
+        "6.2" => "8",
+        "6.1" => "7",
+        "6.0" => "Vista",
+        "5.1" | "5.2" => "XP",
+        other => other,
+    }
+    .to_string()
+}
+
+fn operating_system(user_agent: &str) -> Option<(&'static str, Option<String>)> {
+    Some(if user_agent.contains("Windows Phone") {
+        (
+            os::WINDOWS_PHONE,
+            version_after(user_agent, "Windows Phone OS ")
+                .or_else(|| version_after(user_agent, "Windows Phone ")),
+        )
+    } else if user_agent.contains("iPad") {
+        // IE "CPU OS 17_4 like Mac OS X"
+        (os::IPADOS, version_after(user_agent, " OS "))
+    } else if user_agent.contains("iPhone") || user_agent.contains("iPod") {
+        // IE "CPU iPhone OS 18_0 like Mac OS X"
+        (os::IOS, version_after(user_agent, " OS "))
+    } else if let Some((_, platform)) = user_agent.split_once("CrOS ") {
+        // IE "CrOS x86_64 14541.0.0", the version follows the architecture
+        let version = platform
+            .split_whitespace()
+            .nth(1)
+            .map(|version| version.trim_end_matches(')').to_string());
+        (os::CHROMEOS, version)
+    } else if user_agent.contains("Android") {
+        (os::ANDROID, version_after(user_agent, "Android "))
+    } else if user_agent.contains("Windows") {
+        (
+            os::WINDOWS,
+            version_after(user_agent, "Windows NT ").map(|version| windows_version(&version)),
+        )
+    } else if user_agent.contains("Macintosh") || user_agent.contains("Mac OS X") {
+        (os::MACOS, version_after(user_agent, "Mac OS X "))
+    } else if user_agent.contains("Linux") || user_agent.contains("X11") {
+        (os::LINUX, None)
+    } else {
+        return None;
+    })
+}
+
+fn device_class(user_agent: &str, os: Option<&str>) -> DeviceClass {
+    if bots::is_bot_agent(user_agent) {
+        return DeviceClass::Bot;
+    }
+    let tablet = ["iPad", "Tablet", "Kindle", "Silk/"]
+        .iter()
+        .any(|token| user_agent.contains(token));
+    if tablet {
+        return DeviceClass::Tablet;
+    }
+
+    match os {
+        // Android tablets leave out "Mobile"
+        Some(os::ANDROID) if user_agent.contains("Mobile") => DeviceClass::Phone,
+        Some(os::ANDROID) => DeviceClass::Tablet,
+        Some(os::IOS | os::WINDOWS_PHONE) => DeviceClass::Phone,
+        Some(os::WINDOWS | os::MACOS | os::LINUX | os::CHROMEOS) => DeviceClass::Desktop,
+        _ if user_agent.contains("Mobile") => DeviceClass::Phone,
+        _ => DeviceClass::Unknown,
+    }
+}
+
+pub fn parse(user_agent: &str) -> UserAgent {
+    let (browser, browser_version) = match browser(user_agent) {
+        Some((browser, version)) => (Some(browser.to_string()), version),
+        None => (None, None),
+    };
+    let (os, os_version) = match operating_system(user_agent) {
+        Some((os, version)) => (Some(os), version),
+        None => (None, None),
+    };
+
+    UserAgent {
+        browser,
+        browser_version,
+        os: os.map(str::to_string),
+        os_version,
+        device_class: device_class(user_agent, os),
+    }
+}
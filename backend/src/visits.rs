@@ -0,0 +1,143 @@
+// Keeps visitor analytics free of personal data: IPs are only stored as hashes
+// salted per day, and visits past the retention period are rolled up by day
+use std::{net::IpAddr, time::Duration};
+
+use chrono::{Days, NaiveDate, Utc};
+use diesel::{prelude::*, sql_types::Date};
+use model::schema::visit_salts;
+use sha2::{Digest, Sha256};
+
+use crate::DbPool;
+
+/// How often visits past the retention period are rolled up
+pub const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Distinct visitors per day, overall and per breakdown of visit reports, of a
+/// `visits` CTE with `day`, `visitor`, `device`, `country`, `state` and
+/// `referrer` columns. Salts only live a day, so counting distinct hashes within
+/// a day is exact
+pub const UNIQUE_VISITORS: &str = "\
+    select day, 'total' as breakdown, '' as country, '' as name, \
+    count(distinct visitor) as unique_visitors from visits group by day \
+    union all select day, 'device', '', device, count(distinct visitor) \
+    from visits group by day, device \
+    union all select day, 'country', '', country, count(distinct visitor) \
+    from visits group by day, country \
+    union all select day, 'state', country, state, count(distinct visitor) \
+    from visits group by day, country, state \
+    union all select day, 'referrer', '', referrer, count(distinct visitor) \
+    from visits where referrer != '' group by day, referrer";
+
+/// The salt visitors are hashed with on `day`, created on the day's first
+/// visit. Older salts are deleted so earlier hashes can't be linked to new ones
+pub fn daily_salt(conn: &mut SqliteConnection, day: NaiveDate) -> QueryResult<String> {
+    conn.transaction(|conn| {
+        let salt = visit_salts::table
+            .filter(visit_salts::day.eq(day))
+            .select(visit_salts::salt)
+            .first::<String>(conn)
+            .optional()?;
+        if let Some(salt) = salt {
+            return Ok(salt);
+        }
+
+        let salt = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        diesel::delete(visit_salts::table.filter(visit_salts::day.ne(day))).execute(conn)?;
+        diesel::insert_into(visit_salts::table)
+            .values((visit_salts::day.eq(day), visit_salts::salt.eq(&salt)))
+            .execute(conn)?;
+
+        Ok(salt)
+    })
+}
+
+pub fn hash_visitor(salt: &str, ip: IpAddr) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(ip.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Whether the request asks not to be tracked, through Do Not Track or Global
+/// Privacy Control
+pub fn opted_out(req: &actix_web::HttpRequest) -> bool {
+    ["DNT", "Sec-GPC"].iter().any(|name| {
+        req.headers()
+            .get(*name)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim() == "1")
+    })
+}
+
+/// Rolls visits from before the last `keep_days` days into `daily_visits` and
+/// `daily_unique_visitors` and deletes them, returning how many were deleted.
+/// Bots' visits are only deleted
+pub fn roll_up(
+    conn: &mut SqliteConnection,
+    keep_days: u32,
+    today: NaiveDate,
+) -> QueryResult<usize> {
+    let cutoff = today - Days::new(keep_days as u64);
+
+    conn.transaction(|conn| {
+        diesel::sql_query(
+            "insert into daily_visits \
+             (day, country, state, device, referrer, visits) \
+             select date(time), country, state, device, coalesce(referrer, ''), count(*) \
+             from users where date(time) < ? and not is_bot \
+             group by date(time), country, state, device, coalesce(referrer, '') \
+             on conflict (day, country, state, device, referrer) do update set \
+             visits = visits + excluded.visits",
+        )
+        .bind::<Date, _>(cutoff)
+        .execute(conn)?;
+
+        // The select needs a where clause for `on conflict` to parse
+        diesel::sql_query(format!(
+            "with visits as (\
+             select date(time) as day, visitor, device, country, state, \
+             coalesce(referrer, '') as referrer \
+             from users where date(time) < ? and not is_bot) \
+             insert into daily_unique_visitors \
+             (day, breakdown, country, name, unique_visitors) \
+             select * from ({UNIQUE_VISITORS}) where true \
+             on conflict (day, breakdown, country, name) do update set \
+             unique_visitors = unique_visitors + excluded.unique_visitors"
+        ))
+        .bind::<Date, _>(cutoff)
+        .execute(conn)?;
+
+        diesel::sql_query("delete from users where date(time) < ?")
+            .bind::<Date, _>(cutoff)
+            .execute(conn)
+    })
+}
+
+/// Applies the retention period every `RETENTION_INTERVAL`, for as long as the
+/// server runs
+pub async fn enforce_retention(pool: DbPool, keep_days: u32) {
+    let mut interval = actix_web::rt::time::interval(RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        let rolled_up = actix_web::web::block(move || {
+            let mut conn = pool
+                .get()
+                .map_err(|e| format!("Cannot connect to DB: {e}"))?;
+            roll_up(&mut conn, keep_days, Utc::now().date_naive())
+                .map_err(|e| format!("Cannot roll up visits: {e}"))
+        })
+        .await;
+
+        match rolled_up {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => log::error!("Cannot apply visit retention: {e}"),
+            Err(e) => log::error!("Cannot apply visit retention: {e}"),
+        }
+    }
+}
@@ -19,6 +19,7 @@ import Messages exposing (Loading(..), Menu(..), Msg(..), Nav(..), Route(..))
 import Ports exposing (setCart)
 import Stock exposing (Stock)
 import Url exposing (Url)
+import Url.Builder
 import Url.Parser as Parse exposing ((</>))
 
 
@@ -61,20 +62,28 @@ parseRoute url =
     Parse.parse routeParser url |> Maybe.withDefault Gallery
 
 
-main : Program (Maybe String) Model Msg
+{-| The saved cart, and the page that linked to the shop for visitor analytics
+-}
+type alias Flags =
+    { cart : Maybe String
+    , referrer : String
+    }
+
+
+main : Program Flags Model Msg
 main =
     Browser.application { init = init, onUrlChange = onUrlChange, onUrlRequest = onUrlRequest, update = update, view = view, subscriptions = always Sub.none }
 
 
-init : Maybe String -> Url -> Nav.Key -> ( Model, Cmd Msg )
-init maybeCart url key =
+init : Flags -> Url -> Nav.Key -> ( Model, Cmd Msg )
+init { cart, referrer } url key =
     let
         tryCart =
-            maybeCart |> Maybe.withDefault "" |> Decode.decodeString Cart.decoder
+            cart |> Maybe.withDefault "" |> Decode.decodeString Cart.decoder
     in
     let
         cmd =
-            getStockWithCart (Result.withDefault Dict.empty tryCart)
+            getStockPage [ Http.header "X-Page-Referrer" referrer ] (Result.withDefault Dict.empty tryCart) Dict.empty 1
     in
     ( Uninit (Page key (parseRoute url)), cmd )
 
@@ -94,11 +103,31 @@ onUrlRequest req =
             NoOp
 
 
-getStockWithCart : Cart -> Cmd Msg
-getStockWithCart jsonCart =
-    Http.get
-        { url = "/api/stock"
-        , expect = Http.expectJson (\res -> Load (GotCart ( jsonCart, res ))) Stock.stockDecoder
+{-| The most products the backend lists at once
+-}
+stockPageSize : Int
+stockPageSize =
+    200
+
+
+{-| Requests a page of the listing, `loaded` holds the pages before it. Only
+the first page counts as a visit, so only it needs the referrer header
+-}
+getStockPage : List Http.Header -> Cart -> Stock -> Int -> Cmd Msg
+getStockPage headers jsonCart loaded pageNumber =
+    Http.request
+        { method = "GET"
+        , headers = headers
+        , url =
+            Url.Builder.absolute [ "api", "stock" ]
+                [ Url.Builder.int "limit" stockPageSize, Url.Builder.int "page" pageNumber ]
+        , body = Http.emptyBody
+        , expect =
+            Http.expectJson
+                (Load << GotCart { cart = jsonCart, loaded = loaded, pageNumber = pageNumber })
+                Stock.stockDecoder
+        , timeout = Nothing
+        , tracker = Nothing
         }
 
 
@@ -153,10 +182,18 @@ handleCartAction action model =
 handleLoadAction : Loading -> Page -> ( Model, Cmd Msg )
 handleLoadAction msg page =
     case msg of
-        GotCart ( cart, maybeStock ) ->
+        GotCart { cart, loaded, pageNumber } maybeStock ->
             case maybeStock of
-                Ok stock ->
-                    ( Success { shop = { stock = stock, cart = cart }, view = Closed, page = page }, Cmd.none )
+                Ok { stock, more } ->
+                    let
+                        allStock =
+                            Dict.union loaded stock
+                    in
+                    if more then
+                        ( Uninit page, getStockPage [] cart allStock (pageNumber + 1) )
+
+                    else
+                        ( Success { shop = { stock = allStock, cart = cart }, view = Closed, page = page }, Cmd.none )
 
                 Err e ->
                     let
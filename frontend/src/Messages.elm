@@ -2,7 +2,7 @@ module Messages exposing (..)
 
 import Cart exposing (Cart)
 import Http
-import Stock exposing (Stock)
+import Stock exposing (Stock, StockPage)
 import Url exposing (Url)
 
 
@@ -26,7 +26,7 @@ type Nav
 
 
 type Loading
-    = GotCart ( Cart, Result Http.Error Stock )
+    = GotCart { cart : Cart, loaded : Stock, pageNumber : Int } (Result Http.Error StockPage)
     | MakeCart (Result Http.Error Stock)
 
 
@@ -11,22 +11,55 @@ type alias Stock =
     Dict Product.ID Product
 
 
-stockDecoder : JD.Decoder Stock
+{-| One page of the listing, and whether any products are on later pages
+-}
+type alias StockPage =
+    { stock : Stock
+    , more : Bool
+    }
+
+
+{-| The backend lists products a page at a time with their variants keyed by
+item ID, the shop only deals in items so the variants are flattened out
+-}
+stockDecoder : JD.Decoder StockPage
 stockDecoder =
-    JD.keyValuePairs Product.decoder
+    JD.map4 (\stock total page limit -> StockPage stock (page * limit < total))
+        (JD.field "products" (JD.list (JD.field "product" productDecoder))
+            |> JD.map (List.concat >> Dict.fromList)
+        )
+        (JD.field "total" JD.int)
+        (JD.field "page" JD.int)
+        (JD.field "limit" JD.int)
+
+
+productDecoder : JD.Decoder (List ( Product.ID, Product ))
+productDecoder =
+    JD.map3
+        (\title description variants ->
+            List.map (\( id, ( kind, quantity ) ) -> ( id, Product title description kind quantity )) variants
+        )
+        (JD.field "title" JD.string)
+        (JD.field "description" JD.string)
+        (JD.field "variants" (intKeyed (JD.map2 Tuple.pair (JD.field "kind" Product.kindDecoder) (JD.field "quantity" JD.int))))
+
+
+intKeyed : JD.Decoder a -> JD.Decoder (List ( Int, a ))
+intKeyed decoder =
+    JD.keyValuePairs decoder
         |> JD.andThen
             (\pairs ->
                 let
-                    addPair : ( String, Product ) -> JD.Decoder Stock -> JD.Decoder Stock
-                    addPair ( k, v ) decoder =
+                    addPair : ( String, a ) -> JD.Decoder (List ( Int, a )) -> JD.Decoder (List ( Int, a ))
+                    addPair ( k, v ) acc =
                         case String.toInt k of
                             Just key ->
-                                JD.map (Dict.insert key v) decoder
+                                JD.map ((::) ( key, v )) acc
 
                             Nothing ->
                                 JD.fail "Key must be an integer"
                 in
-                List.foldr addPair (JD.succeed Dict.empty) pairs
+                List.foldr addPair (JD.succeed []) pairs
             )
 
 
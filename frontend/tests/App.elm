@@ -18,9 +18,21 @@ suite =
             \s -> decodeString Cart.decoder s |> Expect.err
         , test "Valid stock parser" <|
             \_ ->
-                """{"1": {"title":"cat","kind":"BigPrint","description":"8x17 print from a local print shop with high quality 100lb silk cover paper","quantity":20}}"""
+                """{"products":[{"id":1,"product":{"title":"cat","description":"8x17 print from a local print shop with high quality 100lb silk cover paper","variants":{"1":{"product":1,"sku":"cat-big","options":{},"kind":"BigPrint","price":20,"quantity":20}},"images":[],"category":null,"tags":[]}}],"total":1,"page":1,"limit":200}"""
                     |> decodeString stockDecoder
                     |> Expect.ok
+        , test "Stock parser finds later pages" <|
+            \_ ->
+                """{"products":[],"total":201,"page":1,"limit":200}"""
+                    |> decodeString stockDecoder
+                    |> Result.map .more
+                    |> Expect.equal (Ok True)
+        , test "Stock parser stops at the last page" <|
+            \_ ->
+                """{"products":[],"total":201,"page":2,"limit":200}"""
+                    |> decodeString stockDecoder
+                    |> Result.map .more
+                    |> Expect.equal (Ok False)
         , fuzz string "Fuzz stock parser" <|
             \s -> decodeString stockDecoder s |> Expect.err
         , test "Valid kind parser" <|
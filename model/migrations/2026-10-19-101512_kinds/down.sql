@@ -0,0 +1,22 @@
+PRAGMA foreign_keys = OFF;
+
+create table stock_new (
+  id integer not null primary key autoincrement,
+  title text not null,
+  kind integer not null,
+  description text not null,
+  quantity integer not null
+);
+
+insert into stock_new (
+  id, title, kind, description, quantity
+)
+select id, title, kind, description, quantity
+from stock;
+
+drop table stock;
+alter table stock_new rename to stock;
+
+drop table kinds;
+
+PRAGMA foreign_keys = ON;
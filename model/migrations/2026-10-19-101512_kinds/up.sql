@@ -0,0 +1,40 @@
+PRAGMA foreign_keys = OFF;
+
+create table kinds (
+  id integer not null primary key autoincrement,
+  name text unique not null,
+  display_name text not null,
+  price integer not null check (price >= 0),
+  shipping_class text not null check (shipping_class in ('Flat', 'Parcel'))
+);
+
+insert into kinds (id, name, display_name, price, shipping_class)
+values
+  (0, 'BigPrint', 'Big print', 2000, 'Parcel'),
+  (1, 'SmallPrint', 'Small print', 700, 'Flat'),
+  (2, 'Button', 'Button', 300, 'Flat');
+
+create table stock_new (
+  id integer not null primary key autoincrement,
+  title text not null,
+  kind integer not null references kinds (id),
+  description text not null,
+  quantity integer not null
+);
+
+insert into stock_new (
+  id, title, kind, description, quantity
+)
+select
+  id,
+  title,
+  -- Unknown kinds come out null and fail the migration rather than being guessed
+  (select kinds.id from kinds where kinds.id = stock.kind) as kind,
+  description,
+  quantity
+from stock;
+
+drop table stock;
+alter table stock_new rename to stock;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,23 @@
+PRAGMA foreign_keys = OFF;
+
+create table stock_new (
+  id integer not null primary key autoincrement,
+  title text not null,
+  kind integer not null references kinds (id),
+  description text not null,
+  quantity integer not null
+);
+
+insert into stock_new (
+  id, title, kind, description, quantity
+)
+select s.id, p.title, s.kind, p.description, s.quantity
+from stock s
+join products p on s.product_id = p.id;
+
+drop table stock;
+alter table stock_new rename to stock;
+
+drop table products;
+
+PRAGMA foreign_keys = ON;
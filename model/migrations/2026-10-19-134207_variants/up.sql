@@ -0,0 +1,43 @@
+PRAGMA foreign_keys = OFF;
+
+create table products (
+  id integer not null primary key autoincrement,
+  title text unique not null,
+  description text not null
+);
+
+-- Every existing item becomes a product with a single variant, both keeping
+-- the item's ID so carts.item_id still points at the same thing
+insert into products (id, title, description)
+select id, title, description
+from stock;
+
+create table stock_new (
+  id integer not null primary key autoincrement,
+  product_id integer not null references products (id),
+  sku text unique not null,
+  options text not null default '{}',
+  kind integer not null references kinds (id),
+  price integer check (price >= 0),
+  quantity integer not null
+);
+
+insert into stock_new (
+  id, product_id, sku, options, kind, price, quantity
+)
+select
+  id,
+  id,
+  printf('KS-%04d', id),
+  '{}',
+  kind,
+  null,
+  quantity
+from stock;
+
+drop table stock;
+alter table stock_new rename to stock;
+
+create index stock_product_id on stock (product_id);
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,9 @@
+create table images (
+  id integer not null primary key autoincrement,
+  product_id integer not null references products (id),
+  position integer not null,
+  key text unique not null,
+  original text not null
+);
+
+create index images_product_id on images (product_id, position);
@@ -0,0 +1,7 @@
+alter table products drop column category_id;
+
+drop table collection_products;
+drop table collections;
+drop table product_tags;
+drop table tags;
+drop table categories;
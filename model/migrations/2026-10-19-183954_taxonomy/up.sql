@@ -0,0 +1,30 @@
+create table categories (
+  id integer not null primary key autoincrement,
+  name text unique not null
+);
+
+create table tags (
+  id integer not null primary key autoincrement,
+  name text unique not null
+);
+
+create table product_tags (
+  product_id integer not null references products (id),
+  tag_id integer not null references tags (id),
+  primary key (product_id, tag_id)
+);
+
+create table collections (
+  id integer not null primary key autoincrement,
+  name text unique not null,
+  description text not null default ''
+);
+
+create table collection_products (
+  collection_id integer not null references collections (id),
+  product_id integer not null references products (id),
+  position integer not null,
+  primary key (collection_id, product_id)
+);
+
+alter table products add column category_id integer references categories (id);
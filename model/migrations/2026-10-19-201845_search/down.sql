@@ -0,0 +1,8 @@
+drop trigger product_search_tag_rename;
+drop trigger product_search_tag_delete;
+drop trigger product_search_tag_insert;
+drop trigger product_search_delete;
+drop trigger product_search_update;
+drop trigger product_search_insert;
+
+drop table product_search;
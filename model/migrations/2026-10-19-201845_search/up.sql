@@ -0,0 +1,81 @@
+create virtual table product_search using fts5 (
+  title,
+  description,
+  tags,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+insert into product_search (rowid, title, description, tags)
+select
+  products.id,
+  products.title,
+  products.description,
+  coalesce(
+    (
+      select group_concat(tags.name, ' ')
+      from product_tags
+      join tags on tags.id = product_tags.tag_id
+      where product_tags.product_id = products.id
+    ),
+    ''
+  )
+from products;
+
+-- The rowid of each row is its product's ID, these keep the index in sync
+-- with products and their tags
+create trigger product_search_insert after insert on products begin
+  insert into product_search (rowid, title, description, tags)
+  values (new.id, new.title, new.description, '');
+end;
+
+create trigger product_search_update after update of title, description on products begin
+  update product_search
+  set title = new.title, description = new.description
+  where rowid = new.id;
+end;
+
+create trigger product_search_delete after delete on products begin
+  delete from product_search where rowid = old.id;
+end;
+
+create trigger product_search_tag_insert after insert on product_tags begin
+  update product_search
+  set tags = coalesce(
+    (
+      select group_concat(tags.name, ' ')
+      from product_tags
+      join tags on tags.id = product_tags.tag_id
+      where product_tags.product_id = new.product_id
+    ),
+    ''
+  )
+  where rowid = new.product_id;
+end;
+
+create trigger product_search_tag_delete after delete on product_tags begin
+  update product_search
+  set tags = coalesce(
+    (
+      select group_concat(tags.name, ' ')
+      from product_tags
+      join tags on tags.id = product_tags.tag_id
+      where product_tags.product_id = old.product_id
+    ),
+    ''
+  )
+  where rowid = old.product_id;
+end;
+
+create trigger product_search_tag_rename after update of name on tags begin
+  update product_search
+  set tags = coalesce(
+    (
+      select group_concat(tags.name, ' ')
+      from product_tags
+      join tags on tags.id = product_tags.tag_id
+      where product_tags.product_id = product_search.rowid
+    ),
+    ''
+  )
+  where rowid in (select product_id from product_tags where tag_id = new.id);
+end;
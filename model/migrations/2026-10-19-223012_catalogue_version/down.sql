@@ -0,0 +1,23 @@
+drop trigger catalogue_version_products_insert;
+drop trigger catalogue_version_products_update;
+drop trigger catalogue_version_products_delete;
+drop trigger catalogue_version_stock_insert;
+drop trigger catalogue_version_stock_update;
+drop trigger catalogue_version_stock_delete;
+drop trigger catalogue_version_kinds_insert;
+drop trigger catalogue_version_kinds_update;
+drop trigger catalogue_version_kinds_delete;
+drop trigger catalogue_version_images_insert;
+drop trigger catalogue_version_images_update;
+drop trigger catalogue_version_images_delete;
+drop trigger catalogue_version_categories_insert;
+drop trigger catalogue_version_categories_update;
+drop trigger catalogue_version_categories_delete;
+drop trigger catalogue_version_tags_insert;
+drop trigger catalogue_version_tags_update;
+drop trigger catalogue_version_tags_delete;
+drop trigger catalogue_version_product_tags_insert;
+drop trigger catalogue_version_product_tags_update;
+drop trigger catalogue_version_product_tags_delete;
+
+drop table catalogue_version;
@@ -0,0 +1,94 @@
+create table catalogue_version (
+  id integer not null primary key check (id = 1),
+  version integer not null,
+  modified timestamp not null
+);
+
+insert into catalogue_version (id, version, modified) values (1, 0, current_timestamp);
+
+-- Bumped by every write to a table the stock listing reads from, the listing's
+-- ETag and Last-Modified headers come from here
+
+create trigger catalogue_version_products_insert after insert on products begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_products_update after update on products begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_products_delete after delete on products begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_stock_insert after insert on stock begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_stock_update after update on stock begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_stock_delete after delete on stock begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_kinds_insert after insert on kinds begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_kinds_update after update on kinds begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_kinds_delete after delete on kinds begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_images_insert after insert on images begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_images_update after update on images begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_images_delete after delete on images begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_categories_insert after insert on categories begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_categories_update after update on categories begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_categories_delete after delete on categories begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_tags_insert after insert on tags begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_tags_update after update on tags begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_tags_delete after delete on tags begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_product_tags_insert after insert on product_tags begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_product_tags_update after update on product_tags begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
+
+create trigger catalogue_version_product_tags_delete after delete on product_tags begin
+  update catalogue_version set version = version + 1, modified = current_timestamp;
+end;
@@ -0,0 +1,19 @@
+create table inventory_movements (
+  id integer not null primary key autoincrement,
+  -- Not foreign keys, the ledger outlives deleted items and orders
+  item_id integer not null,
+  delta integer not null,
+  reason text not null,
+  note text,
+  actor text,
+  order_id integer,
+  time timestamp not null default current_timestamp
+);
+
+create index inventory_movements_item_id on inventory_movements (item_id, id);
+
+-- Quantities from before the ledger existed become each item's opening balance
+insert into inventory_movements (item_id, delta, reason, note)
+select id, quantity, 'Correction', 'Opening balance'
+from stock
+where quantity != 0;
@@ -0,0 +1,3 @@
+alter table stock drop column low_stock_threshold;
+
+alter table kinds drop column low_stock_threshold;
@@ -0,0 +1,4 @@
+alter table kinds add column low_stock_threshold integer not null default 0 check (low_stock_threshold >= 0);
+
+-- Overrides the kind's threshold when not null, like stock.price
+alter table stock add column low_stock_threshold integer check (low_stock_threshold >= 0);
@@ -0,0 +1,16 @@
+create table stock_notifications (
+  id integer not null primary key autoincrement,
+  product_id integer not null references products (id),
+  -- A single variant, or any of the product's variants when null
+  item_id integer references stock (id),
+  email text not null,
+  -- Identifies the signup in confirmation and unsubscribe links
+  token text not null unique,
+  confirmed boolean not null default false,
+  notified timestamp,
+  created timestamp not null default current_timestamp
+);
+
+create unique index stock_notifications_signup on stock_notifications (product_id, ifnull(item_id, -1), email);
+
+create index stock_notifications_item_id on stock_notifications (item_id);
@@ -0,0 +1,9 @@
+alter table carts drop column ships_on;
+
+alter table carts drop column awaiting_stock;
+
+alter table stock drop column preorder_cap;
+
+alter table stock drop column ships_on;
+
+alter table stock drop column sales_mode;
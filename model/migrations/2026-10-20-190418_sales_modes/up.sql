@@ -0,0 +1,12 @@
+alter table stock add column sales_mode text not null default 'Normal' check (sales_mode in ('Normal', 'PreOrder', 'Backorder'));
+
+-- Required for pre-orders, optional for backorders
+alter table stock add column ships_on date;
+
+-- How far below zero pre-orders may take the quantity, unlimited when null
+alter table stock add column preorder_cap integer check (preorder_cap >= 0);
+
+-- Units of the line that were not on hand when it was sold
+alter table carts add column awaiting_stock integer not null default 0;
+
+alter table carts add column ships_on date;
@@ -0,0 +1,9 @@
+drop index orders_email;
+
+alter table stock drop column max_per_customer;
+
+alter table stock drop column max_per_order;
+
+alter table stock drop column available_until;
+
+alter table stock drop column available_from;
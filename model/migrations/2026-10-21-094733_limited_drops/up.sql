@@ -0,0 +1,11 @@
+alter table stock add column available_from timestamp;
+
+-- Drops can't be bought before available_from or after available_until, in UTC
+alter table stock add column available_until timestamp;
+
+alter table stock add column max_per_order integer check (max_per_order > 0);
+
+-- Counted across every order placed with the same email
+alter table stock add column max_per_customer integer check (max_per_customer > 0);
+
+create index orders_email on orders (lower(email));
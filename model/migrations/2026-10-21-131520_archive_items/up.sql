@@ -0,0 +1 @@
+alter table stock add column archived timestamp;
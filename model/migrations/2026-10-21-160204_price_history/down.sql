@@ -0,0 +1,7 @@
+drop table price_history;
+
+alter table carts drop column line_total;
+
+alter table carts drop column title;
+
+alter table carts drop column unit_price;
@@ -0,0 +1,39 @@
+alter table carts add column unit_price integer not null default 0;
+
+-- Snapshots of the line at purchase time, so later catalogue edits don't
+-- change what an order says was bought
+alter table carts add column title text not null default '';
+
+alter table carts add column line_total integer not null default 0;
+
+-- Lines sold before prices were recorded get today's price and product title,
+-- the closest there is to what was paid
+update carts set
+  unit_price = coalesce((
+    select coalesce(stock.price, kinds.price)
+    from stock join kinds on kinds.id = stock.kind
+    where stock.id = carts.item_id
+  ), 0),
+  title = coalesce((
+    select products.title
+    from stock join products on products.id = stock.product_id
+    where stock.id = carts.item_id
+  ), '');
+
+update carts set line_total = unit_price * quantity;
+
+create table price_history (
+  id integer not null primary key autoincrement,
+  -- Not a foreign key, the history outlives deleted items
+  item_id integer not null,
+  -- The price the item sold for, its own or its kind's
+  price integer not null,
+  actor text,
+  time timestamp not null default current_timestamp
+);
+
+create index price_history_item_id on price_history (item_id, id);
+
+insert into price_history (item_id, price)
+select stock.id, coalesce(stock.price, kinds.price)
+from stock join kinds on kinds.id = stock.kind;
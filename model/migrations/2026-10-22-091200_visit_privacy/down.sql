@@ -0,0 +1,9 @@
+drop table daily_visits;
+
+drop table visit_salts;
+
+drop index users_time;
+
+alter table users add column ip text not null default '';
+
+alter table users drop column visitor;
@@ -0,0 +1,26 @@
+alter table users add column visitor text not null default '';
+
+-- Raw addresses are dropped, visits logged before hashing each count as their
+-- own visitor
+update users set visitor = 'legacy-' || id;
+
+alter table users drop column ip;
+
+create index users_time on users (time);
+
+-- Only the current day's salt is kept, so a visitor can be counted once per
+-- day but not followed from one day to the next
+create table visit_salts (
+  day date not null primary key,
+  salt text not null
+);
+
+-- Visits older than the retention period, rolled up
+create table daily_visits (
+  day date not null,
+  country text not null,
+  device text not null,
+  visits integer not null,
+  unique_visitors integer not null,
+  primary key (day, country, device)
+);
@@ -0,0 +1,19 @@
+create table daily_visits_old (
+  day date not null,
+  country text not null,
+  device text not null,
+  visits integer not null,
+  unique_visitors integer not null,
+  primary key (day, country, device)
+);
+
+insert into daily_visits_old
+select day, country, device, sum(visits), sum(unique_visitors)
+from daily_visits
+group by day, country, device;
+
+drop table daily_visits;
+
+alter table daily_visits_old rename to daily_visits;
+
+alter table users drop column referrer;
@@ -0,0 +1,21 @@
+alter table users add column referrer text;
+
+-- Rollups keep states and referrers for the analytics dashboard, referrers are
+-- hosts and '' for direct visits
+create table daily_visits_new (
+  day date not null,
+  country text not null,
+  state text not null,
+  device text not null,
+  referrer text not null,
+  visits integer not null,
+  unique_visitors integer not null,
+  primary key (day, country, state, device, referrer)
+);
+
+insert into daily_visits_new
+select day, country, '', device, '', visits, unique_visitors from daily_visits;
+
+drop table daily_visits;
+
+alter table daily_visits_new rename to daily_visits;
@@ -0,0 +1,9 @@
+alter table users drop column device_class;
+
+alter table users drop column os_version;
+
+alter table users drop column os;
+
+alter table users drop column browser_version;
+
+alter table users drop column browser;
@@ -0,0 +1,12 @@
+alter table users add column browser text;
+
+alter table users add column browser_version text;
+
+alter table users add column os text;
+
+alter table users add column os_version text;
+
+-- Visits logged before user agents were parsed are of an unknown class
+alter table users add column device_class text not null default 'unknown';
+
+update users set device_class = 'bot' where is_bot;
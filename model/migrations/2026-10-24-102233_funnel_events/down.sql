@@ -0,0 +1 @@
+drop table funnel_events;
@@ -0,0 +1,13 @@
+create table funnel_events (
+  id integer not null primary key autoincrement,
+  -- A random ID from a cookie, events aren't tied to visits or addresses
+  session text not null,
+  stage text not null,
+  -- Not foreign keys, the events outlive deleted products
+  product_id integer,
+  item_id integer,
+  order_id integer,
+  time timestamp not null default current_timestamp
+);
+
+create index funnel_events_time on funnel_events (time);
@@ -0,0 +1,42 @@
+create table temp_orders (
+  id integer not null primary key autoincrement,
+  name text not null,
+  email text not null,
+  total integer not null,
+  shipped boolean not null default 0 check (shipped in (0, 1)),
+  tracking_number text,
+  created timestamp not null default current_timestamp,
+  -- Breakdown of the total in cents, the subtotal is before shipping and tax.
+  -- Orders placed before these were kept have 0s
+  subtotal integer not null default 0,
+  shipping integer not null default 0,
+  tax integer not null default 0,
+  -- Refunded through Stripe, in cents
+  refunded integer not null default 0,
+  -- Ties Stripe's refunds back to the order
+  payment_intent text
+);
+
+-- Older orders weren't timestamped, their sales were if they were recorded
+insert into temp_orders (
+  id, name, email, total, shipped, tracking_number, created
+)
+select
+  id,
+  name,
+  email,
+  total,
+  shipped,
+  tracking_number,
+  coalesce(
+    (select min(time) from inventory_movements where order_id = orders.id),
+    current_timestamp
+  )
+from orders;
+
+drop table orders;
+alter table temp_orders rename to orders;
+
+create index orders_email on orders (lower(email));
+create index orders_created on orders (created);
+create unique index orders_payment_intent on orders (payment_intent);
@@ -0,0 +1,5 @@
+alter table orders add column
+  -- Why a paid order broke the limits it was checked out under, IE its items
+  -- going off sale or the customer's limit being reached before it was paid
+  -- for. A JSON list of quote warnings, null for orders that are fine
+  warnings text;
@@ -0,0 +1,6 @@
+alter table daily_visits add column unique_visitors integer not null default 0;
+
+-- Groups only get back as many visitors as they had visits
+update daily_visits set unique_visitors = visits;
+
+drop table daily_unique_visitors;
@@ -0,0 +1,30 @@
+create table daily_unique_visitors (
+  day date not null,
+  -- 'total', 'device', 'country', 'state' or 'referrer', as in visit reports
+  breakdown text not null,
+  -- Only set for states
+  country text not null default '',
+  -- '' for totals
+  name text not null,
+  unique_visitors integer not null,
+  primary key (day, breakdown, country, name)
+);
+
+-- Visits already rolled up only kept visitors per group, whose sums are the
+-- best left of them
+insert into daily_unique_visitors (day, breakdown, country, name, unique_visitors)
+select day, 'total', '', '', sum(unique_visitors) from daily_visits group by day
+union all
+select day, 'device', '', device, sum(unique_visitors)
+from daily_visits group by day, device
+union all
+select day, 'country', '', country, sum(unique_visitors)
+from daily_visits group by day, country
+union all
+select day, 'state', country, state, sum(unique_visitors)
+from daily_visits group by day, country, state
+union all
+select day, 'referrer', '', referrer, sum(unique_visitors)
+from daily_visits where referrer != '' group by day, referrer;
+
+alter table daily_visits drop column unique_visitors;
@@ -0,0 +1 @@
+alter table carts drop column kind;
@@ -0,0 +1,9 @@
+alter table carts add column kind text not null default '';
+
+-- Lines sold before kinds were kept get their item's current kind, items
+-- deleted since are left without one
+update carts set kind = coalesce((
+  select kinds.name
+  from stock join kinds on kinds.id = stock.kind
+  where stock.id = carts.item_id
+), '');
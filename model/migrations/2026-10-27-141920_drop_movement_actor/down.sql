@@ -0,0 +1 @@
+alter table inventory_movements add column actor text;
@@ -0,0 +1 @@
+alter table inventory_movements drop column actor;
@@ -0,0 +1 @@
+alter table stock_notifications drop column confirmation_sent;
@@ -0,0 +1,4 @@
+alter table stock_notifications add column confirmation_sent timestamp;
+
+-- Existing signups were sent their confirmation when they signed up
+update stock_notifications set confirmation_sent = created;
@@ -0,0 +1,140 @@
+use std::fmt;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{catalogue::Format, user::Device};
+
+/// How reports group days, weeks start on Monday
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// First day of the period `day` is in
+    pub fn period_start(self, day: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => day,
+            Interval::Week => day - Days::new(day.weekday().num_days_from_monday() as u64),
+            Interval::Month => day.with_day(1).unwrap_or(day),
+        }
+    }
+
+    /// First day of the period after the one starting on `start`
+    pub fn next_period(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => start + Days::new(1),
+            Interval::Week => start + Days::new(7),
+            Interval::Month => start + Months::new(1),
+        }
+    }
+
+    /// The start of every period overlapping `from` to `to` inclusive
+    pub fn periods(self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let mut periods = Vec::new();
+        let mut start = self.period_start(from);
+        while start <= to {
+            periods.push(start);
+            start = self.next_period(start);
+        }
+        periods
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Interval::Day => "day",
+            Interval::Week => "week",
+            Interval::Month => "month",
+        })
+    }
+}
+
+/// Query parameters of the analytics dashboard. The range is inclusive and
+/// defaults to the last 30 days
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct AnalyticsQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub interval: Interval,
+    #[serde(default)]
+    pub format: Format,
+    /// Count bots' visits too, only recent ones as they aren't rolled up
+    #[serde(default)]
+    pub bots: bool,
+}
+
+/// Visits and unique visitors of one group. Visitors are only recognised
+/// within a day, so one visiting on several days counts once per day
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Visits {
+    pub visits: u32,
+    pub unique_visitors: u32,
+}
+
+impl std::ops::AddAssign for Visits {
+    fn add_assign(&mut self, other: Self) {
+        self.visits += other.visits;
+        self.unique_visitors += other.unique_visitors;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodVisits {
+    /// First day of the period
+    pub start: NaiveDate,
+    #[serde(flatten)]
+    pub visits: Visits,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceVisits {
+    pub device: Device,
+    #[serde(flatten)]
+    pub visits: Visits,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountryVisits {
+    pub country: String,
+    #[serde(flatten)]
+    pub visits: Visits,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateVisits {
+    pub country: String,
+    pub state: String,
+    #[serde(flatten)]
+    pub visits: Visits,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferrerVisits {
+    pub referrer: String,
+    #[serde(flatten)]
+    pub visits: Visits,
+}
+
+/// Visits over a date range. Every period of the range is listed, breakdowns
+/// are sorted by most visits. Unknown locations are ''
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VisitsReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub interval: Interval,
+    pub total: Visits,
+    pub periods: Vec<PeriodVisits>,
+    pub devices: Vec<DeviceVisits>,
+    pub countries: Vec<CountryVisits>,
+    pub states: Vec<StateVisits>,
+    /// The sites sending the most visitors, direct visits aren't included
+    pub referrers: Vec<ReferrerVisits>,
+}
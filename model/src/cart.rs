@@ -32,12 +32,51 @@ pub struct TableCart {
     pub order_id: i32,
     pub item_id: i32,
     pub quantity: i32,
+    pub awaiting_stock: i32,
+    pub ships_on: Option<chrono::NaiveDate>,
+    pub unit_price: i32,
+    pub title: String,
+    pub line_total: i32,
+    pub kind: String,
 }
 
+/// `unit_price`, `title` and `kind` are what the item sold as, kept even if
+/// the catalogue changes later
 #[derive(Insertable, Clone, Copy, Debug, Serialize)]
 #[diesel(table_name = crate::schema::carts)]
-pub struct NewCart {
+pub struct NewCart<'a> {
     pub order_id: i32,
     pub item_id: i32,
     pub quantity: i32,
+    pub unit_price: i32,
+    pub title: &'a str,
+    pub line_total: i32,
+    /// Name of the item's kind
+    pub kind: &'a str,
+}
+
+impl<'a> NewCart<'a> {
+    /// Fails if the line's total doesn't fit in the column
+    pub fn new(
+        order_id: i32,
+        item_id: i32,
+        quantity: i32,
+        unit_price: i32,
+        title: &'a str,
+        kind: &'a str,
+    ) -> Result<Self, String> {
+        let line_total = unit_price.checked_mul(quantity).ok_or_else(|| {
+            format!("{quantity} of item {item_id} at {unit_price} each is too large a total")
+        })?;
+
+        Ok(Self {
+            order_id,
+            item_id,
+            quantity,
+            unit_price,
+            title,
+            line_total,
+            kind,
+        })
+    }
 }
@@ -0,0 +1,113 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{inventory::LowStock, item::ItemFields, ItemId};
+
+/// Formats the catalogue can be exported to and imported from. JSON is a list
+/// of products, like stock.json, CSV has a row per variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    #[default]
+    Json,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Json => "application/json",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            other => Err(format!("Unknown catalogue format: {other}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+        })
+    }
+}
+
+/// Query parameters of catalogue imports
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub format: Format,
+    /// Report what would change without changing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// A product, or one of its variants, as read from an import. Products with
+/// several variants span several rows sharing a title
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogueRow {
+    /// Line of a CSV file or position of a product in a JSON list, from 1
+    pub row: usize,
+    pub title: String,
+    pub description: String,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub variant: Option<ItemFields>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Create,
+    Update,
+}
+
+/// A product, or a variant if `sku` is present, that an import creates or updates
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub row: usize,
+    pub title: String,
+    pub sku: Option<String>,
+    pub action: Action,
+    /// Names of the fields an update changes
+    pub fields: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowError {
+    pub row: usize,
+    pub message: String,
+}
+
+/// Outcome of an import. Nothing is applied if there are any errors
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ImportReport {
+    pub applied: bool,
+    pub changes: Vec<Change>,
+    /// Rows matching what's already in the catalogue
+    pub unchanged: usize,
+    pub errors: Vec<RowError>,
+    /// Sold out items the import brought back in stock
+    #[serde(default)]
+    pub restocked: Vec<ItemId>,
+    /// Items the import took down to their low stock threshold
+    #[serde(default)]
+    pub low_stock: Vec<LowStock>,
+}
@@ -0,0 +1,34 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Represents ID of a Category -> convert to i32 before entry into DB
+pub type CategoryId = u32;
+
+/// Each product belongs to at most one category, IE "prints" or "buttons"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Category {
+    pub id: CategoryId,
+    pub name: String,
+}
+
+impl From<TableCategory> for Category {
+    fn from(TableCategory { id, name }: TableCategory) -> Self {
+        Self {
+            id: id as CategoryId,
+            name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::categories)]
+pub struct TableCategory {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable, AsChangeset, Deserialize)]
+#[diesel(table_name = crate::schema::categories)]
+pub struct NewCategory {
+    pub name: String,
+}
@@ -0,0 +1,76 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::product::ProductId;
+
+/// Represents ID of a Collection -> convert to i32 before entry into DB
+pub type CollectionId = u32;
+
+/// An ordered selection of products curated by the admin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collection {
+    pub id: CollectionId,
+    pub name: String,
+    pub description: String,
+    pub products: Vec<ProductId>,
+}
+
+impl From<TableCollection> for Collection {
+    fn from(
+        TableCollection {
+            id,
+            name,
+            description,
+        }: TableCollection,
+    ) -> Self {
+        Self {
+            id: id as CollectionId,
+            name,
+            description,
+            products: Vec::new(),
+        }
+    }
+}
+
+/// Body of collection creation/update requests, `products` is in display order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectionFields {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub products: Vec<ProductId>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::collections)]
+pub struct TableCollection {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::collections)]
+pub struct NewCollection<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+}
+
+impl<'a, 'b: 'a> From<&'b CollectionFields> for NewCollection<'a> {
+    fn from(
+        CollectionFields {
+            name, description, ..
+        }: &'b CollectionFields,
+    ) -> Self {
+        Self { name, description }
+    }
+}
+
+#[derive(Insertable, Clone, Copy, Debug)]
+#[diesel(table_name = crate::schema::collection_products)]
+pub struct NewCollectionProduct {
+    pub collection_id: i32,
+    pub product_id: i32,
+    pub position: i32,
+}
@@ -0,0 +1,97 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{catalogue::Format, product::ProductId};
+
+/// The steps from a visit to a purchase, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    CatalogueView,
+    ItemView,
+    CheckoutStarted,
+    PurchaseCompleted,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::CatalogueView,
+        Stage::ItemView,
+        Stage::CheckoutStarted,
+        Stage::PurchaseCompleted,
+    ];
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Stage::ALL
+            .into_iter()
+            .find(|stage| stage.to_string() == s)
+            .ok_or_else(|| format!("Unknown funnel stage: {s}"))
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::CatalogueView => "catalogue_view",
+            Stage::ItemView => "item_view",
+            Stage::CheckoutStarted => "checkout_started",
+            Stage::PurchaseCompleted => "purchase_completed",
+        })
+    }
+}
+
+/// A step of an anonymous session, item events are recorded per item so
+/// conversions can be told apart by product
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = crate::schema::funnel_events)]
+pub struct NewFunnelEvent {
+    pub session: String,
+    pub stage: String,
+    pub product_id: Option<i32>,
+    pub item_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub time: NaiveDateTime,
+}
+
+/// Query parameters of funnel reports. The range is inclusive and defaults to
+/// the last 30 days
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct FunnelQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FunnelStep {
+    pub stage: Stage,
+    pub sessions: u32,
+    /// Share of the previous step's sessions, 1 for the first step
+    pub conversion: f64,
+}
+
+/// Sessions that viewed a product, started checking out with it and bought it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProductFunnel {
+    pub product_id: ProductId,
+    pub title: String,
+    pub steps: Vec<FunnelStep>,
+}
+
+/// Sessions reaching each stage over a date range, overall and per product.
+/// Products are sorted by most purchases
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FunnelReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub steps: Vec<FunnelStep>,
+    pub products: Vec<ProductFunnel>,
+}
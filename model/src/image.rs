@@ -0,0 +1,116 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Represents ID of an Image -> convert to i32 before entry into DB
+pub type ImageId = u32;
+
+/// Where the backend serves the image directory from
+pub const IMAGE_ROUTE: &str = "/api/images";
+
+/// Resized copies generated for every upload, alongside the untouched original
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Thumbnail,
+    Web,
+}
+
+impl Size {
+    pub const ALL: [Size; 2] = [Size::Thumbnail, Size::Web];
+
+    /// Longest edge in pixels, images are never scaled up
+    pub fn max_dimension(&self) -> u32 {
+        match self {
+            Size::Thumbnail => 400,
+            Size::Web => 1600,
+        }
+    }
+
+    pub fn stem(&self) -> &'static str {
+        match self {
+            Size::Thumbnail => "thumbnail",
+            Size::Web => "web",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    WebP,
+    Jpeg,
+}
+
+impl Format {
+    pub const ALL: [Format; 2] = [Format::WebP, Format::Jpeg];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::WebP => "webp",
+            Format::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Name of the file holding `size` encoded as `format`, inside the image's directory
+pub fn file_name(size: Size, format: Format) -> String {
+    format!("{}.{}", size.stem(), format.extension())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rendition {
+    pub webp: String,
+    pub jpeg: String,
+}
+
+impl Rendition {
+    fn new(key: &str, size: Size) -> Self {
+        Self {
+            webp: format!("{IMAGE_ROUTE}/{key}/{}", file_name(size, Format::WebP)),
+            jpeg: format!("{IMAGE_ROUTE}/{key}/{}", file_name(size, Format::Jpeg)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Image {
+    pub id: ImageId,
+    pub original: String,
+    pub web: Rendition,
+    pub thumbnail: Rendition,
+}
+
+impl From<TableImage> for Image {
+    fn from(
+        TableImage {
+            id, key, original, ..
+        }: TableImage,
+    ) -> Self {
+        Self {
+            id: id as ImageId,
+            original: format!("{IMAGE_ROUTE}/{key}/{original}"),
+            web: Rendition::new(&key, Size::Web),
+            thumbnail: Rendition::new(&key, Size::Thumbnail),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(table_name = crate::schema::images)]
+#[diesel(belongs_to(crate::product::TableProduct, foreign_key = product_id))]
+pub struct TableImage {
+    pub id: i32,
+    pub product_id: i32,
+    pub position: i32,
+    /// Name of the directory the image's files are stored in
+    pub key: String,
+    /// File name of the upload as received
+    pub original: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::images)]
+pub struct NewImage<'a> {
+    pub product_id: i32,
+    pub position: i32,
+    pub key: &'a str,
+    pub original: &'a str,
+}
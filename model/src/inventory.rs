@@ -0,0 +1,172 @@
+use std::{fmt, str::FromStr};
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ItemId;
+
+/// Represents ID of an inventory movement -> convert to i32 before entry into DB
+pub type MovementId = u32;
+
+/// Why an item's quantity changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Reason {
+    /// Sold through checkout, always negative
+    Sale,
+    /// New units arrived, always positive
+    Restock,
+    /// Manual fix after a count, either sign
+    Correction,
+    /// Units of a cancelled order put back, always positive
+    CancellationReturn,
+    /// Units written off, always negative
+    Damage,
+}
+
+impl Reason {
+    /// Whether `delta` has the sign this reason requires
+    pub fn allows(&self, delta: i32) -> bool {
+        match self {
+            Reason::Restock | Reason::CancellationReturn => delta > 0,
+            Reason::Sale | Reason::Damage => delta < 0,
+            Reason::Correction => delta != 0,
+        }
+    }
+}
+
+impl FromStr for Reason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Sale" => Ok(Reason::Sale),
+            "Restock" => Ok(Reason::Restock),
+            "Correction" => Ok(Reason::Correction),
+            "CancellationReturn" => Ok(Reason::CancellationReturn),
+            "Damage" => Ok(Reason::Damage),
+            other => Err(format!("Unknown inventory movement reason: {other}")),
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Sale => "Sale",
+            Reason::Restock => "Restock",
+            Reason::Correction => "Correction",
+            Reason::CancellationReturn => "CancellationReturn",
+            Reason::Damage => "Damage",
+        })
+    }
+}
+
+/// One change to an item's quantity, the ledger is append-only
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Movement {
+    pub id: MovementId,
+    pub item: ItemId,
+    pub delta: i32,
+    pub reason: Reason,
+    pub note: Option<String>,
+    pub order: Option<u32>,
+    pub time: NaiveDateTime,
+}
+
+impl TryFrom<TableMovement> for Movement {
+    type Error = String;
+
+    fn try_from(
+        TableMovement {
+            id,
+            item_id,
+            delta,
+            reason,
+            note,
+            order_id,
+            time,
+        }: TableMovement,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: id as MovementId,
+            item: item_id as ItemId,
+            delta,
+            reason: reason.parse::<Reason>()?,
+            note,
+            order: order_id.map(|id| id as u32),
+            time,
+        })
+    }
+}
+
+/// An item's movements, oldest first, along with its stored quantity and the
+/// quantity rebuilt from the ledger. The two only differ if stock was written
+/// around the ledger
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemHistory {
+    pub item: ItemId,
+    pub quantity: i64,
+    pub ledger_quantity: i64,
+    pub movements: Vec<Movement>,
+}
+
+impl ItemHistory {
+    pub fn is_consistent(&self) -> bool {
+        self.quantity == self.ledger_quantity
+    }
+}
+
+/// An item a sale left at or below its low stock threshold, zero units means
+/// it has sold out and fewer are owed to pre-orders and backorders
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LowStock {
+    pub item: ItemId,
+    pub title: String,
+    pub sku: String,
+    pub quantity: i32,
+    pub threshold: u32,
+}
+
+/// Body of manual adjustment requests, sales are only recorded by checkout
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdjustmentFields {
+    pub delta: i32,
+    pub reason: Reason,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::inventory_movements)]
+pub struct TableMovement {
+    pub id: i32,
+    pub item_id: i32,
+    pub delta: i32,
+    pub reason: String,
+    pub note: Option<String>,
+    pub order_id: Option<i32>,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = crate::schema::inventory_movements)]
+pub struct NewMovement<'a> {
+    pub item_id: i32,
+    pub delta: i32,
+    pub reason: String,
+    pub note: Option<&'a str>,
+    pub order_id: Option<i32>,
+}
+
+impl<'a> NewMovement<'a> {
+    pub fn new(item_id: i32, delta: i32, reason: Reason) -> Self {
+        Self {
+            item_id,
+            delta,
+            reason: reason.to_string(),
+            note: None,
+            order_id: None,
+        }
+    }
+}
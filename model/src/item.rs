@@ -1,116 +1,409 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 
 use diesel::prelude::*;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
-#[repr(u8)]
-pub enum Kind {
+use crate::{
+    kind::{Kind, TableKind},
+    product::{ProductId, TableProduct},
+    Quantity,
+};
+
+/// Option name -> value, IE {"size": "8.5x11", "paper": "silk"}
+pub type Options = BTreeMap<String, String>;
+
+/// Whether an item can be sold beyond the units on hand
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SalesMode {
+    /// Only units on hand can be sold
     #[default]
-    BigPrint = 0,
-    SmallPrint = 1,
-    Button = 2,
+    Normal,
+    /// Made to order, sold ahead of an expected ship date up to an optional cap
+    PreOrder,
+    /// Sold without limit, waiting orders ship once it's restocked
+    Backorder,
 }
 
-impl From<i32> for Kind {
-    fn from(value: i32) -> Self {
-        unsafe { std::mem::transmute(value as u8) }
+impl FromStr for SalesMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Normal" => Ok(SalesMode::Normal),
+            "PreOrder" => Ok(SalesMode::PreOrder),
+            "Backorder" => Ok(SalesMode::Backorder),
+            other => Err(format!("Unknown sales mode: {other}")),
+        }
     }
 }
 
-impl From<Kind> for i32 {
-    fn from(value: Kind) -> Self {
-        (value as u8) as i32
+impl fmt::Display for SalesMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SalesMode::Normal => "Normal",
+            SalesMode::PreOrder => "PreOrder",
+            SalesMode::Backorder => "Backorder",
+        })
     }
 }
 
+/// How many units of an item with `quantity` on hand can still be sold, `None`
+/// if there's no limit
+pub fn available(
+    quantity: i32,
+    sales_mode: SalesMode,
+    preorder_cap: Option<i32>,
+) -> Option<Quantity> {
+    let on_hand = quantity.max(0);
+    match (sales_mode, preorder_cap) {
+        (SalesMode::Normal, _) => Some(on_hand as Quantity),
+        (SalesMode::PreOrder, Some(cap)) => Some((quantity + cap).max(0) as Quantity),
+        (SalesMode::PreOrder, None) | (SalesMode::Backorder, _) => None,
+    }
+}
+
+/// Whether `now` falls in a drop's release window, either end may be open
+pub fn in_window(
+    available_from: Option<NaiveDateTime>,
+    available_until: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> bool {
+    available_from.is_none_or(|from| from <= now) && available_until.is_none_or(|until| now < until)
+}
+
+/// A purchasable variant of a product, carts and stock quantities refer to these
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Item {
-    pub title: String,
-    pub kind: Kind,
-    pub description: String,
+    pub product: ProductId,
+    pub sku: String,
+    pub options: Options,
+    /// Name of the item's row in the kinds table, IE "BigPrint"
+    pub kind: String,
+    /// The item's own price if it has one, otherwise its kind's
+    pub price: u32,
+    /// Units on hand
     pub quantity: u32,
+    #[serde(default)]
+    pub sales_mode: SalesMode,
+    /// When pre-ordered or backordered units are expected to ship
+    #[serde(default)]
+    pub ships_on: Option<NaiveDate>,
+    /// Sold out items stay listed but cannot be ordered
+    #[serde(default)]
+    pub sold_out: bool,
+    /// Limited drops can only be ordered between these times, in UTC
+    #[serde(default)]
+    pub available_from: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub available_until: Option<NaiveDateTime>,
+    /// Whether it's currently between `available_from` and `available_until`,
+    /// unreleased items are listed so drops can be announced
+    #[serde(default)]
+    pub on_sale: bool,
+    #[serde(default)]
+    pub max_per_order: Option<u32>,
+    /// Counted across every order placed with the same email
+    #[serde(default)]
+    pub max_per_customer: Option<u32>,
+    /// When the item was archived, archived items are only listed to admins
+    #[serde(default)]
+    pub archived: Option<NaiveDateTime>,
 }
 
 impl std::hash::Hash for Item {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.title.hash(state)
+        self.sku.hash(state)
     }
 }
 
-impl<'a, 'b: 'a> From<&'b Item> for NewItem<'a> {
-    fn from(
-        Item {
-            title,
-            kind,
-            description,
-            quantity,
-        }: &'b Item,
-    ) -> Self {
-        NewItem {
-            title,
-            kind: *kind as i32,
-            description,
-            quantity: *quantity as i32,
-        }
+impl TryFrom<(TableItem, TableKind)> for Item {
+    type Error = String;
+
+    fn try_from(
+        (
+            TableItem {
+                product_id,
+                sku,
+                options,
+                price,
+                quantity,
+                sales_mode,
+                ships_on,
+                preorder_cap,
+                available_from,
+                available_until,
+                max_per_order,
+                max_per_customer,
+                archived,
+                ..
+            },
+            TableKind {
+                name,
+                price: kind_price,
+                ..
+            },
+        ): (TableItem, TableKind),
+    ) -> Result<Self, Self::Error> {
+        let sales_mode = sales_mode.parse::<SalesMode>()?;
+        Ok(Self {
+            product: product_id as ProductId,
+            options: parse_options(&options)?,
+            sku,
+            kind: name,
+            price: price.unwrap_or(kind_price) as u32,
+            quantity: quantity.max(0) as u32,
+            sales_mode,
+            ships_on,
+            sold_out: available(quantity, sales_mode, preorder_cap) == Some(0),
+            available_from,
+            available_until,
+            on_sale: in_window(
+                available_from,
+                available_until,
+                chrono::Utc::now().naive_utc(),
+            ),
+            max_per_order: max_per_order.map(|max| max as u32),
+            max_per_customer: max_per_customer.map(|max| max as u32),
+            archived,
+        })
     }
 }
 
-impl From<TableItem> for Item {
-    fn from(
-        TableItem {
-            title,
-            kind,
-            description,
-            quantity,
-            ..
-        }: TableItem,
-    ) -> Self {
-        Self {
-            title,
-            kind: Kind::from(kind),
-            description,
-            quantity: quantity as u32,
+/// Body of variant creation/update requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ItemFields {
+    pub sku: String,
+    #[serde(default)]
+    pub options: Options,
+    /// Name of a row in the kinds table
+    pub kind: String,
+    /// Overrides the kind's price when present
+    #[serde(default)]
+    pub price: Option<u32>,
+    /// Below zero when pre-ordered or backordered beyond the units on hand, so
+    /// editing an item keeps what's owed
+    pub quantity: i32,
+    /// Overrides the kind's low stock threshold when present
+    #[serde(default)]
+    pub low_stock_threshold: Option<u32>,
+    #[serde(default)]
+    pub sales_mode: SalesMode,
+    /// Required for pre-orders
+    #[serde(default)]
+    pub ships_on: Option<NaiveDate>,
+    /// Limits how many units can be pre-ordered
+    #[serde(default)]
+    pub preorder_cap: Option<u32>,
+    #[serde(default)]
+    pub available_from: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub available_until: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub max_per_order: Option<u32>,
+    #[serde(default)]
+    pub max_per_customer: Option<u32>,
+}
+
+impl ItemFields {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sales_mode == SalesMode::PreOrder && self.ships_on.is_none() {
+            return Err(format!("Pre-order {} needs a ship date", self.sku));
+        }
+        if self.quantity < 0 && self.sales_mode == SalesMode::Normal {
+            return Err(format!(
+                "{} can only go below zero when pre-ordered or backordered",
+                self.sku
+            ));
+        }
+        if let (Some(from), Some(until)) = (self.available_from, self.available_until) {
+            if until <= from {
+                return Err(format!(
+                    "{} is available until before it's released",
+                    self.sku
+                ));
+            }
         }
+        if self.max_per_order == Some(0) || self.max_per_customer == Some(0) {
+            return Err(format!("Limits on {} must be at least 1", self.sku));
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_options(options: &str) -> Result<Options, String> {
+    serde_json::from_str(options).map_err(|e| format!("Malformed item options {options}: {e}"))
+}
+
+/// "cat" with {"size": "A4"} -> "cat (A4)"
+pub fn display_title(product_title: &str, options: &Options) -> String {
+    if options.is_empty() {
+        product_title.to_string()
+    } else {
+        let values = options
+            .values()
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .join(", ");
+        format!("{product_title} ({values})")
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Queryable, Selectable)]
+#[derive(Debug, Clone, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(table_name = crate::schema::stock)]
+#[diesel(belongs_to(TableProduct, foreign_key = product_id))]
 pub struct TableItem {
     pub id: i32,
-    pub title: String,
+    pub product_id: i32,
+    pub sku: String,
+    pub options: String,
     pub kind: i32,
-    pub description: String,
+    pub price: Option<i32>,
     pub quantity: i32,
+    pub low_stock_threshold: Option<i32>,
+    pub sales_mode: String,
+    pub ships_on: Option<NaiveDate>,
+    pub preorder_cap: Option<i32>,
+    pub available_from: Option<NaiveDateTime>,
+    pub available_until: Option<NaiveDateTime>,
+    pub max_per_order: Option<i32>,
+    pub max_per_customer: Option<i32>,
+    pub archived: Option<NaiveDateTime>,
 }
 
-#[inline(always)]
-fn price_from_kind(kind: Kind) -> u32 {
-    use Kind::*;
-    match kind {
-        BigPrint => 20_00,
-        SmallPrint => 7_00,
-        Button => 3_00,
-    }
+/// A stock row along with its product's title and (validated) kind, this is
+/// what gets priced
+#[derive(Debug, Clone)]
+pub struct PricedItem {
+    pub item: TableItem,
+    pub title: String,
+    pub kind: Kind,
+    pub sales_mode: SalesMode,
 }
 
-impl Item {
+impl PricedItem {
     pub fn price(&self) -> u32 {
-        price_from_kind(self.kind)
+        self.item
+            .price
+            .map(|price| price as u32)
+            .unwrap_or(self.kind.price)
+    }
+
+    pub fn low_stock_threshold(&self) -> u32 {
+        self.item
+            .low_stock_threshold
+            .map(|threshold| threshold as u32)
+            .unwrap_or(self.kind.low_stock_threshold)
+    }
+
+    /// Units that can still be sold, `None` if there's no limit
+    pub fn available(&self) -> Option<Quantity> {
+        available(self.item.quantity, self.sales_mode, self.item.preorder_cap)
+    }
+
+    /// How many of `quantity` units would be sold beyond the units on hand
+    pub fn awaiting_stock(&self, quantity: Quantity) -> Quantity {
+        quantity.saturating_sub(self.item.quantity.max(0) as Quantity)
     }
 }
 
-impl TableItem {
-    pub fn price(&self) -> u32 {
-        price_from_kind(Kind::from(self.kind))
+/// Whether going from `before` to `after` units is what takes an item down to
+/// its low stock threshold, so each drop is only alerted on once
+pub fn reaches_threshold(before: i32, after: i32, threshold: i32) -> bool {
+    before > threshold && after <= threshold
+}
+
+/// Whether going from `before` to `after` units restocks a sold out item
+pub fn is_back_in_stock(before: i32, after: i32) -> bool {
+    before <= 0 && after > 0
+}
+
+impl TryFrom<(TableItem, TableProduct, TableKind)> for PricedItem {
+    type Error = String;
+
+    fn try_from(
+        (item, product, kind): (TableItem, TableProduct, TableKind),
+    ) -> Result<Self, Self::Error> {
+        let title = display_title(&product.title, &parse_options(&item.options)?);
+        let sales_mode = item.sales_mode.parse::<SalesMode>()?;
+        Ok(Self {
+            item,
+            title,
+            kind: Kind::try_from(kind)?,
+            sales_mode,
+        })
     }
 }
 
-#[derive(Insertable, AsChangeset)]
+#[derive(Insertable)]
 #[diesel(table_name = crate::schema::stock)]
 pub struct NewItem<'a> {
-    pub title: &'a str,
-    pub kind: i32,
-    pub description: &'a str,
+    #[diesel(embed)]
+    pub changes: ItemChanges<'a>,
     pub quantity: i32,
 }
+
+/// Every column of an item but its quantity, which only changes through
+/// inventory movements so edits can't overwrite a concurrent sale
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::stock)]
+#[diesel(treat_none_as_null = true)]
+pub struct ItemChanges<'a> {
+    pub product_id: i32,
+    pub sku: &'a str,
+    pub options: String,
+    pub kind: i32,
+    pub price: Option<i32>,
+    pub low_stock_threshold: Option<i32>,
+    pub sales_mode: String,
+    pub ships_on: Option<NaiveDate>,
+    pub preorder_cap: Option<i32>,
+    pub available_from: Option<NaiveDateTime>,
+    pub available_until: Option<NaiveDateTime>,
+    pub max_per_order: Option<i32>,
+    pub max_per_customer: Option<i32>,
+}
+
+impl<'a> NewItem<'a> {
+    /// `kind` is the ID of the kinds row named by `fields.kind`
+    pub fn new<'b: 'a>(
+        ItemFields {
+            sku,
+            options,
+            price,
+            quantity,
+            low_stock_threshold,
+            sales_mode,
+            ships_on,
+            preorder_cap,
+            available_from,
+            available_until,
+            max_per_order,
+            max_per_customer,
+            ..
+        }: &'b ItemFields,
+        product_id: i32,
+        kind: i32,
+    ) -> Self {
+        NewItem {
+            changes: ItemChanges {
+                product_id,
+                sku,
+                // Serializing a map of strings cannot fail
+                options: serde_json::to_string(options).unwrap_or_else(|_| "{}".to_string()),
+                kind,
+                price: price.map(|price| price as i32),
+                low_stock_threshold: low_stock_threshold.map(|threshold| threshold as i32),
+                sales_mode: sales_mode.to_string(),
+                ships_on: *ships_on,
+                preorder_cap: preorder_cap.map(|cap| cap as i32),
+                available_from: *available_from,
+                available_until: *available_until,
+                max_per_order: max_per_order.map(|max| max as i32),
+                max_per_customer: max_per_customer.map(|max| max as i32),
+            },
+            quantity: *quantity,
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Represents ID of a row in the kinds table -> convert to i32 before entry into DB
+pub type KindId = u32;
+
+/// How an item is packed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ShippingClass {
+    /// Fits in a stamped envelope: buttons, small prints
+    Flat,
+    /// Needs a rigid mailer: large prints
+    Parcel,
+}
+
+impl FromStr for ShippingClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Flat" => Ok(ShippingClass::Flat),
+            "Parcel" => Ok(ShippingClass::Parcel),
+            other => Err(format!("Unknown shipping class: {other}")),
+        }
+    }
+}
+
+impl fmt::Display for ShippingClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ShippingClass::Flat => "Flat",
+            ShippingClass::Parcel => "Parcel",
+        })
+    }
+}
+
+/// A product type, IE "BigPrint" or "Button", items reference these by name
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Kind {
+    pub id: KindId,
+    pub name: String,
+    pub display_name: String,
+    pub price: u32,
+    pub shipping_class: ShippingClass,
+    /// Admins are alerted when a sale leaves an item with this many units or fewer
+    pub low_stock_threshold: u32,
+}
+
+impl TryFrom<TableKind> for Kind {
+    type Error = String;
+
+    fn try_from(
+        TableKind {
+            id,
+            name,
+            display_name,
+            price,
+            shipping_class,
+            low_stock_threshold,
+        }: TableKind,
+    ) -> Result<Self, Self::Error> {
+        let id = KindId::try_from(id).map_err(|_| format!("Invalid kind ID: {id}"))?;
+        let price = u32::try_from(price).map_err(|_| format!("Kind {name} has price {price}"))?;
+        let shipping_class = shipping_class.parse::<ShippingClass>()?;
+        let low_stock_threshold = u32::try_from(low_stock_threshold)
+            .map_err(|_| format!("Kind {name} has low stock threshold {low_stock_threshold}"))?;
+
+        Ok(Self {
+            id,
+            name,
+            display_name,
+            price,
+            shipping_class,
+            low_stock_threshold,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::kinds)]
+pub struct TableKind {
+    pub id: i32,
+    pub name: String,
+    pub display_name: String,
+    pub price: i32,
+    pub shipping_class: String,
+    pub low_stock_threshold: i32,
+}
+
+/// Body of kind creation/update requests
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KindFields {
+    pub name: String,
+    pub display_name: String,
+    pub price: u32,
+    pub shipping_class: ShippingClass,
+    #[serde(default)]
+    pub low_stock_threshold: u32,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::kinds)]
+pub struct NewKind<'a> {
+    pub name: &'a str,
+    pub display_name: &'a str,
+    pub price: i32,
+    pub shipping_class: String,
+    pub low_stock_threshold: i32,
+}
+
+/// Fails if the price or threshold doesn't fit in its column
+impl<'a, 'b: 'a> TryFrom<&'b KindFields> for NewKind<'a> {
+    type Error = String;
+
+    fn try_from(
+        KindFields {
+            name,
+            display_name,
+            price,
+            shipping_class,
+            low_stock_threshold,
+        }: &'b KindFields,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name,
+            display_name,
+            price: i32::try_from(*price).map_err(|_| format!("Price {price} is too large"))?,
+            shipping_class: shipping_class.to_string(),
+            low_stock_threshold: i32::try_from(*low_stock_threshold)
+                .map_err(|_| format!("Low stock threshold {low_stock_threshold} is too large"))?,
+        })
+    }
+}
@@ -1,8 +1,23 @@
 pub mod address;
+pub mod analytics;
 pub mod cart;
+pub mod catalogue;
+pub mod category;
+pub mod collection;
+pub mod funnel;
+pub mod image;
+pub mod inventory;
 pub mod item;
+pub mod kind;
+pub mod notification;
 pub mod order;
+pub mod price;
+pub mod product;
+pub mod quote;
+pub mod sales;
 pub mod schema;
+pub mod search;
+pub mod tag;
 pub mod user;
 
 // Types follow a pattern of:
@@ -10,7 +25,7 @@ pub mod user;
 //  Table{Name} -> struct received from database, uses SQL-friendly types :/
 //  New{Name} -> struct that is inserted into database
 
-/// Represents ID of an Item -> convert to i32 before entry into DB
+/// Represents ID of an Item (a product variant) -> convert to i32 before entry into DB
 pub type ItemId = u32;
 /// Represents either the quantity of an item in stock, or the quantity of an item in a user's cart
 pub type Quantity = u32;
@@ -0,0 +1,42 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ItemId;
+
+/// Body of back in stock signups, `item` narrows the signup to one variant of
+/// the product
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationFields {
+    pub email: String,
+    #[serde(default)]
+    pub item: Option<ItemId>,
+}
+
+/// A signup to be emailed when a sold out product is restocked. Only confirmed
+/// signups are emailed, and they stay subscribed until they unsubscribe
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::stock_notifications)]
+pub struct TableNotification {
+    pub id: i32,
+    pub product_id: i32,
+    pub item_id: Option<i32>,
+    pub email: String,
+    pub token: String,
+    pub confirmed: bool,
+    pub notified: Option<NaiveDateTime>,
+    pub created: NaiveDateTime,
+    /// When the confirmation email was last sent, repeat signups only resend it
+    /// once this is a while ago
+    pub confirmation_sent: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::stock_notifications)]
+pub struct NewNotification<'a> {
+    pub product_id: i32,
+    pub item_id: Option<i32>,
+    pub email: &'a str,
+    pub token: &'a str,
+    pub confirmation_sent: NaiveDateTime,
+}
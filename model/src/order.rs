@@ -1,4 +1,4 @@
-use crate::address;
+use crate::{address, cart::TableCart, ItemId, Quantity};
 
 use super::CartMap;
 use diesel::prelude::*;
@@ -27,6 +27,60 @@ pub struct TableOrder {
     pub total: i32,
     pub shipped: bool,
     pub tracking_number: Option<String>,
+    pub created: chrono::NaiveDateTime,
+    pub subtotal: i32,
+    pub shipping: i32,
+    pub tax: i32,
+    pub refunded: i32,
+    pub payment_intent: Option<String>,
+    /// JSON list of the `quote::Warning`s the order was paid despite, for the
+    /// admin to refund
+    pub warnings: Option<String>,
+}
+
+/// A line of an order as it was sold, unaffected by later catalogue edits
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderLine {
+    pub item: ItemId,
+    pub title: String,
+    pub unit_price: u32,
+    pub quantity: Quantity,
+    pub line_total: u32,
+    /// Units that were pre-ordered or backordered
+    pub awaiting_stock: Quantity,
+    pub ships_on: Option<chrono::NaiveDate>,
+}
+
+impl From<TableCart> for OrderLine {
+    fn from(
+        TableCart {
+            item_id,
+            quantity,
+            awaiting_stock,
+            ships_on,
+            unit_price,
+            title,
+            line_total,
+            ..
+        }: TableCart,
+    ) -> Self {
+        Self {
+            item: item_id as ItemId,
+            title,
+            unit_price: unit_price as u32,
+            quantity: quantity as Quantity,
+            line_total: line_total as u32,
+            awaiting_stock: awaiting_stock as Quantity,
+            ships_on,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderDetail {
+    #[serde(flatten)]
+    pub order: TableOrder,
+    pub lines: Vec<OrderLine>,
 }
 
 #[derive(Insertable)]
@@ -38,6 +92,17 @@ pub struct NewOrder<'a> {
     pub shipped: bool,
 }
 
+/// What Stripe charged for an order besides its total, inserted alongside it
+#[derive(Insertable, Debug, Clone, Default, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::orders)]
+pub struct OrderPayment {
+    /// Before shipping and tax
+    pub subtotal: i32,
+    pub shipping: i32,
+    pub tax: i32,
+    pub payment_intent: Option<String>,
+}
+
 impl<'a, 'b: 'a> From<&'b Order> for NewOrder<'a> {
     fn from(
         Order {
@@ -59,4 +124,8 @@ pub enum OrderFilter {
     All = 0,
     Shipped = 1,
     Unshipped = 2,
+    /// Unshipped, with every line on hand
+    ReadyToShip = 3,
+    /// Unshipped, with pre-ordered or backordered lines that haven't been restocked
+    AwaitingStock = 4,
 }
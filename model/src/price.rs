@@ -0,0 +1,54 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ItemId;
+
+/// The price an item sold for from `time` on, its own or its kind's
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceChange {
+    pub price: u32,
+    /// "cli" for changes made from the command line. `None` otherwise, admin
+    /// routes aren't authenticated so they can't say who made a change
+    pub actor: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+impl From<TablePriceChange> for PriceChange {
+    fn from(
+        TablePriceChange {
+            price, actor, time, ..
+        }: TablePriceChange,
+    ) -> Self {
+        Self {
+            price: price as u32,
+            actor,
+            time,
+        }
+    }
+}
+
+/// An item's prices, oldest first. The last one is its current price
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceHistory {
+    pub item: ItemId,
+    pub changes: Vec<PriceChange>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::price_history)]
+pub struct TablePriceChange {
+    pub id: i32,
+    pub item_id: i32,
+    pub price: i32,
+    pub actor: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = crate::schema::price_history)]
+pub struct NewPriceChange<'a> {
+    pub item_id: i32,
+    pub price: i32,
+    pub actor: Option<&'a str>,
+}
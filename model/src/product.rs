@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    image::Image,
+    item::{Item, ItemFields},
+    ItemId,
+};
+
+/// Represents ID of a Product -> convert to i32 before entry into DB
+pub type ProductId = u32;
+
+/// A print/button/etc. as shown in the shop, the things customers actually
+/// buy are its variants
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Product {
+    pub title: String,
+    pub description: String,
+    pub variants: HashMap<ItemId, Item>,
+    /// Ordered, the first image is the one shown in listings
+    pub images: Vec<Image>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl From<TableProduct> for Product {
+    fn from(
+        TableProduct {
+            title, description, ..
+        }: TableProduct,
+    ) -> Self {
+        Self {
+            title,
+            description,
+            variants: HashMap::new(),
+            images: Vec::new(),
+            category: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Body of product creation requests, stock.json is a list of these
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ProductFields {
+    pub title: String,
+    pub description: String,
+    #[serde(default)]
+    pub variants: Vec<ItemFields>,
+    /// Name of an existing category
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Tags that don't exist yet are created
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Query parameters accepted by the stock listing, all filters must match
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct StockFilter {
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    /// Only variants of this kind are listed
+    pub kind: Option<String>,
+    /// Only list products with at least one variant in stock
+    #[serde(default)]
+    pub in_stock: bool,
+    /// Also list archived variants, and products with only archived variants
+    #[serde(default)]
+    pub archived: bool,
+}
+
+pub const DEFAULT_STOCK_LIMIT: u32 = 50;
+pub const MAX_STOCK_LIMIT: u32 = 200;
+
+/// Order of the stock listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StockSort {
+    /// A-Z
+    Title,
+    /// Cheapest variant, lowest first
+    Price,
+    /// Cheapest variant, highest first
+    PriceDesc,
+    /// Most recently added first
+    #[default]
+    Newest,
+    /// Most units across all variants first
+    Quantity,
+}
+
+/// Paging and ordering of the stock listing, pages start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StockListing {
+    #[serde(default = "first_page")]
+    pub page: u32,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+    #[serde(default)]
+    pub sort: StockSort,
+}
+
+fn first_page() -> u32 {
+    1
+}
+
+fn default_limit() -> u32 {
+    DEFAULT_STOCK_LIMIT
+}
+
+impl Default for StockListing {
+    fn default() -> Self {
+        Self {
+            page: first_page(),
+            limit: default_limit(),
+            sort: StockSort::default(),
+        }
+    }
+}
+
+impl StockListing {
+    pub fn page(&self) -> u32 {
+        self.page.max(1)
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit.clamp(1, MAX_STOCK_LIMIT)
+    }
+
+    pub fn offset(&self) -> u32 {
+        (self.page() - 1).saturating_mul(self.limit())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedProduct {
+    pub id: ProductId,
+    pub product: Product,
+}
+
+/// One page of the stock listing, in the requested order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StockPage {
+    pub products: Vec<ListedProduct>,
+    /// Number of matching products across every page
+    pub total: u32,
+    pub page: u32,
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::products)]
+pub struct TableProduct {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub category_id: Option<i32>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::products)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewProduct<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub category_id: Option<i32>,
+}
+
+impl<'a> NewProduct<'a> {
+    /// `category_id` is the ID of the category named by `fields.category`
+    pub fn new<'b: 'a>(
+        ProductFields {
+            title, description, ..
+        }: &'b ProductFields,
+        category_id: Option<i32>,
+    ) -> Self {
+        Self {
+            title,
+            description,
+            category_id,
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    item::{in_window, PricedItem, SalesMode},
+    CartMap, ItemId, Quantity,
+};
+
+/// Sales tax rate in basis points (1/100th of a percent), applied to the
+/// discounted subtotal. Sales tax is not collected yet, see TODO.md
+pub const SALES_TAX_BASIS_POINTS: u32 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteLine {
+    pub item: ItemId,
+    pub title: String,
+    pub unit_price: u32,
+    pub quantity: Quantity,
+    pub total: u32,
+    /// Units of the line that are pre-ordered or backordered
+    #[serde(default)]
+    pub awaiting_stock: Quantity,
+    /// When the units awaiting stock are expected to ship
+    #[serde(default)]
+    pub ships_on: Option<NaiveDate>,
+}
+
+/// A shipping rate the customer can be charged, mirrors Stripe's fixed amount
+/// shipping rates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShippingOption {
+    pub name: String,
+    pub amount: u32,
+    pub min_business_days: u32,
+    pub max_weeks: u32,
+}
+
+impl ShippingOption {
+    pub fn priority() -> Self {
+        Self {
+            name: "priority".to_string(),
+            amount: 10_00,
+            min_business_days: 5,
+            max_weeks: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Discount {
+    pub description: String,
+    pub amount: u32,
+}
+
+/// Problems with a cart that would prevent it from being checked out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Warning {
+    NotFound {
+        item: ItemId,
+    },
+    InsufficientStock {
+        item: ItemId,
+        requested: Quantity,
+        available: Quantity,
+    },
+    /// Outside of a limited drop's release window
+    NotOnSale {
+        item: ItemId,
+        available_from: Option<NaiveDateTime>,
+        available_until: Option<NaiveDateTime>,
+    },
+    OrderLimit {
+        item: ItemId,
+        requested: Quantity,
+        limit: Quantity,
+    },
+    /// `purchased` units were already bought in earlier orders with the same email
+    CustomerLimit {
+        item: ItemId,
+        requested: Quantity,
+        purchased: Quantity,
+        limit: Quantity,
+    },
+    /// Items limited per customer can only be checked out with an email
+    EmailRequired {
+        item: ItemId,
+    },
+    /// `requested` units cost more than can be charged, the line is left out
+    LineTooLarge {
+        item: ItemId,
+        requested: Quantity,
+    },
+    /// The lines add up to more than can be charged
+    TotalTooLarge,
+}
+
+/// Body of checkout requests, `email` is needed for items limited per customer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckoutFields {
+    pub cart: CartMap,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// Checkout used to take a bare cart, which is still accepted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CheckoutRequest {
+    Fields(CheckoutFields),
+    Cart(CartMap),
+}
+
+impl From<CheckoutRequest> for CheckoutFields {
+    fn from(request: CheckoutRequest) -> Self {
+        match request {
+            CheckoutRequest::Fields(fields) => fields,
+            CheckoutRequest::Cart(cart) => CheckoutFields { cart, email: None },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quote {
+    pub lines: Vec<QuoteLine>,
+    pub subtotal: u32,
+    pub shipping_options: Vec<ShippingOption>,
+    pub tax: u32,
+    pub discounts: Vec<Discount>,
+    pub warnings: Vec<Warning>,
+    pub total: u32,
+}
+
+impl Quote {
+    /// Prices `cart` against the matching rows from the stock table, any IDs
+    /// in the cart that are missing from `items` produce a warning. Drop windows
+    /// are checked against `now`, in UTC
+    pub fn new(items: &[PricedItem], cart: &CartMap, now: NaiveDateTime) -> Self {
+        let items = items
+            .iter()
+            .map(|priced| (priced.item.id as ItemId, priced))
+            .collect::<HashMap<ItemId, &PricedItem>>();
+
+        let mut ids = cart.keys().copied().collect::<Vec<ItemId>>();
+        ids.sort_unstable();
+
+        let mut lines = Vec::with_capacity(ids.len());
+        let mut warnings = Vec::new();
+
+        for id in ids {
+            let quantity = cart[&id];
+            let Some(priced) = items.get(&id) else {
+                warnings.push(Warning::NotFound { item: id });
+                continue;
+            };
+
+            let (available_from, available_until) =
+                (priced.item.available_from, priced.item.available_until);
+            if !in_window(available_from, available_until, now) {
+                warnings.push(Warning::NotOnSale {
+                    item: id,
+                    available_from,
+                    available_until,
+                });
+            }
+            if let Some(limit) = priced.item.max_per_order {
+                if quantity > limit as Quantity {
+                    warnings.push(Warning::OrderLimit {
+                        item: id,
+                        requested: quantity,
+                        limit: limit as Quantity,
+                    });
+                }
+            }
+            if let Some(available) = priced.available() {
+                if quantity > available {
+                    warnings.push(Warning::InsufficientStock {
+                        item: id,
+                        requested: quantity,
+                        available,
+                    });
+                }
+            }
+            let awaiting_stock = match priced.sales_mode {
+                SalesMode::Normal => 0,
+                SalesMode::PreOrder | SalesMode::Backorder => priced.awaiting_stock(quantity),
+            };
+
+            let unit_price = priced.price();
+            let Some(total) = unit_price.checked_mul(quantity) else {
+                warnings.push(Warning::LineTooLarge {
+                    item: id,
+                    requested: quantity,
+                });
+                continue;
+            };
+            lines.push(QuoteLine {
+                item: id,
+                title: priced.title.clone(),
+                unit_price,
+                quantity,
+                total,
+                awaiting_stock,
+                ships_on: (awaiting_stock > 0)
+                    .then_some(priced.item.ships_on)
+                    .flatten(),
+            });
+        }
+
+        let subtotal = lines
+            .iter()
+            .try_fold(0u32, |subtotal, line| subtotal.checked_add(line.total));
+        let discounts = Vec::<Discount>::new();
+        let discounted = subtotal
+            .unwrap_or(u32::MAX)
+            .saturating_sub(discounts.iter().map(|d| d.amount).sum());
+        let tax = (discounted as u64 * SALES_TAX_BASIS_POINTS as u64 / 10_000)
+            .try_into()
+            .unwrap_or(u32::MAX);
+        let shipping_options = vec![ShippingOption::priority()];
+        let total = discounted
+            .checked_add(tax)
+            .and_then(|total| total.checked_add(shipping_options[0].amount));
+        if subtotal.is_none() || total.is_none() {
+            warnings.push(Warning::TotalTooLarge);
+        }
+        let (subtotal, total) = (subtotal.unwrap_or(u32::MAX), total.unwrap_or(u32::MAX));
+
+        Self {
+            lines,
+            subtotal,
+            shipping_options,
+            tax,
+            discounts,
+            warnings,
+            total,
+        }
+    }
+
+    /// The shipping option checkout charges for
+    pub fn shipping(&self) -> &ShippingOption {
+        &self.shipping_options[0]
+    }
+
+    pub fn is_orderable(&self) -> bool {
+        self.warnings.is_empty() && !self.lines.is_empty()
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{analytics::Interval, catalogue::Format, ItemId, Quantity};
+
+/// Query parameters of sales reports. The range is inclusive and defaults to
+/// the last 30 days
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct SalesQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub interval: Interval,
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Takings of a group of orders in cents. Revenue is what customers paid,
+/// shipping and tax included, net revenue is what they kept after refunds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Takings {
+    pub orders: u32,
+    pub revenue: u32,
+    pub refunded: u32,
+    pub net_revenue: u32,
+    pub shipping: u32,
+    pub tax: u32,
+}
+
+impl std::ops::AddAssign for Takings {
+    fn add_assign(&mut self, other: Self) {
+        self.orders += other.orders;
+        self.revenue += other.revenue;
+        self.refunded += other.refunded;
+        self.net_revenue += other.net_revenue;
+        self.shipping += other.shipping;
+        self.tax += other.tax;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodSales {
+    /// First day of the period
+    pub start: NaiveDate,
+    #[serde(flatten)]
+    pub takings: Takings,
+}
+
+/// Units of an item sold and what its lines came to, before shipping and tax
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemSales {
+    pub item_id: ItemId,
+    /// The title it was last sold under
+    pub title: String,
+    pub kind: String,
+    pub units: Quantity,
+    pub revenue: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KindSales {
+    pub kind: String,
+    pub units: Quantity,
+    pub revenue: u32,
+}
+
+/// A customer's orders, told apart by email regardless of case
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomerSales {
+    pub email: String,
+    pub orders: u32,
+    pub revenue: u32,
+    pub refunded: u32,
+}
+
+/// Sales of orders placed over a date range. Every period of the range is
+/// listed, breakdowns are sorted by most revenue. Cancelled orders are deleted
+/// so they aren't counted, refunded ones are
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SalesReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub interval: Interval,
+    pub total: Takings,
+    /// Revenue per order, 0 without orders
+    pub average_order_value: u32,
+    /// Orders that were refunded, fully or partly
+    pub refunds: u32,
+    pub periods: Vec<PeriodSales>,
+    pub items: Vec<ItemSales>,
+    pub kinds: Vec<KindSales>,
+    /// The customers who spent the most after refunds
+    pub customers: Vec<CustomerSales>,
+}
@@ -19,6 +19,109 @@ diesel::table! {
         quantity -> Integer,
         order_id -> Integer,
         item_id -> Integer,
+        awaiting_stock -> Integer,
+        ships_on -> Nullable<Date>,
+        unit_price -> Integer,
+        title -> Text,
+        line_total -> Integer,
+        kind -> Text,
+    }
+}
+
+diesel::table! {
+    catalogue_version (id) {
+        id -> Integer,
+        version -> Integer,
+        modified -> Timestamp,
+    }
+}
+
+diesel::table! {
+    categories (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    collection_products (collection_id, product_id) {
+        collection_id -> Integer,
+        product_id -> Integer,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    collections (id) {
+        id -> Integer,
+        name -> Text,
+        description -> Text,
+    }
+}
+
+diesel::table! {
+    daily_unique_visitors (day, breakdown, country, name) {
+        day -> Date,
+        breakdown -> Text,
+        country -> Text,
+        name -> Text,
+        unique_visitors -> Integer,
+    }
+}
+
+diesel::table! {
+    daily_visits (day, country, state, device, referrer) {
+        day -> Date,
+        country -> Text,
+        state -> Text,
+        device -> Text,
+        referrer -> Text,
+        visits -> Integer,
+    }
+}
+
+diesel::table! {
+    funnel_events (id) {
+        id -> Integer,
+        session -> Text,
+        stage -> Text,
+        product_id -> Nullable<Integer>,
+        item_id -> Nullable<Integer>,
+        order_id -> Nullable<Integer>,
+        time -> Timestamp,
+    }
+}
+
+diesel::table! {
+    images (id) {
+        id -> Integer,
+        product_id -> Integer,
+        position -> Integer,
+        key -> Text,
+        original -> Text,
+    }
+}
+
+diesel::table! {
+    inventory_movements (id) {
+        id -> Integer,
+        item_id -> Integer,
+        delta -> Integer,
+        reason -> Text,
+        note -> Nullable<Text>,
+        order_id -> Nullable<Integer>,
+        time -> Timestamp,
+    }
+}
+
+diesel::table! {
+    kinds (id) {
+        id -> Integer,
+        name -> Text,
+        display_name -> Text,
+        price -> Integer,
+        shipping_class -> Text,
+        low_stock_threshold -> Integer,
     }
 }
 
@@ -30,40 +133,145 @@ diesel::table! {
         total -> Integer,
         shipped -> Bool,
         tracking_number -> Nullable<Text>,
+        created -> Timestamp,
+        subtotal -> Integer,
+        shipping -> Integer,
+        tax -> Integer,
+        refunded -> Integer,
+        payment_intent -> Nullable<Text>,
+        warnings -> Nullable<Text>,
     }
 }
 
 diesel::table! {
-    stock (id) {
+    price_history (id) {
+        id -> Integer,
+        item_id -> Integer,
+        price -> Integer,
+        actor -> Nullable<Text>,
+        time -> Timestamp,
+    }
+}
+
+diesel::table! {
+    product_tags (product_id, tag_id) {
+        product_id -> Integer,
+        tag_id -> Integer,
+    }
+}
+
+diesel::table! {
+    products (id) {
         id -> Integer,
         title -> Text,
-        kind -> Integer,
         description -> Text,
+        category_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    stock (id) {
+        id -> Integer,
+        product_id -> Integer,
+        sku -> Text,
+        options -> Text,
+        kind -> Integer,
+        price -> Nullable<Integer>,
         quantity -> Integer,
+        low_stock_threshold -> Nullable<Integer>,
+        sales_mode -> Text,
+        ships_on -> Nullable<Date>,
+        preorder_cap -> Nullable<Integer>,
+        available_from -> Nullable<Timestamp>,
+        available_until -> Nullable<Timestamp>,
+        max_per_order -> Nullable<Integer>,
+        max_per_customer -> Nullable<Integer>,
+        archived -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    stock_notifications (id) {
+        id -> Integer,
+        product_id -> Integer,
+        item_id -> Nullable<Integer>,
+        email -> Text,
+        token -> Text,
+        confirmed -> Bool,
+        notified -> Nullable<Timestamp>,
+        created -> Timestamp,
+        confirmation_sent -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Integer,
+        name -> Text,
     }
 }
 
 diesel::table! {
     users (id) {
         id -> Nullable<Integer>,
-        ip -> Text,
         user_agent -> Nullable<Text>,
         device -> Text,
         time -> Nullable<Timestamp>,
         country -> Text,
         state -> Text,
         city -> Nullable<Text>,
+        visitor -> Text,
+        referrer -> Nullable<Text>,
+        is_bot -> Bool,
+        browser -> Nullable<Text>,
+        browser_version -> Nullable<Text>,
+        os -> Nullable<Text>,
+        os_version -> Nullable<Text>,
+        device_class -> Text,
+    }
+}
+
+diesel::table! {
+    visit_salts (day) {
+        day -> Date,
+        salt -> Text,
     }
 }
 
 diesel::joinable!(addresses -> orders (order_id));
 diesel::joinable!(carts -> orders (order_id));
 diesel::joinable!(carts -> stock (item_id));
+diesel::joinable!(collection_products -> collections (collection_id));
+diesel::joinable!(collection_products -> products (product_id));
+diesel::joinable!(images -> products (product_id));
+diesel::joinable!(product_tags -> products (product_id));
+diesel::joinable!(product_tags -> tags (tag_id));
+diesel::joinable!(products -> categories (category_id));
+diesel::joinable!(stock -> kinds (kind));
+diesel::joinable!(stock -> products (product_id));
+diesel::joinable!(stock_notifications -> products (product_id));
+diesel::joinable!(stock_notifications -> stock (item_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     addresses,
     carts,
+    catalogue_version,
+    categories,
+    collection_products,
+    collections,
+    daily_unique_visitors,
+    daily_visits,
+    funnel_events,
+    images,
+    inventory_movements,
+    kinds,
     orders,
+    price_history,
+    product_tags,
+    products,
     stock,
+    stock_notifications,
+    tags,
     users,
+    visit_salts,
 );
@@ -0,0 +1,98 @@
+use diesel::{
+    prelude::*,
+    sql_types::{Double, Integer, Text},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::product::{Product, ProductId};
+
+pub const DEFAULT_PER_PAGE: u32 = 20;
+pub const MAX_PER_PAGE: u32 = 100;
+
+/// Marks matched terms in highlights and snippets
+pub const HIGHLIGHT_OPEN: &str = "<mark>";
+pub const HIGHLIGHT_CLOSE: &str = "</mark>";
+
+/// Query parameters of stock searches, pages start at 1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    #[serde(default = "first_page")]
+    pub page: u32,
+    #[serde(default = "default_per_page")]
+    pub per_page: u32,
+}
+
+fn first_page() -> u32 {
+    1
+}
+
+fn default_per_page() -> u32 {
+    DEFAULT_PER_PAGE
+}
+
+impl SearchQuery {
+    /// Turns the customer's query into an FTS5 match expression. Every word
+    /// must match the start of a term, FTS5 operators are treated as plain text.
+    /// `None` if there's nothing to search for
+    pub fn match_expression(&self) -> Option<String> {
+        let terms = self
+            .q
+            .split_whitespace()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<String>>();
+
+        (!terms.is_empty()).then(|| terms.join(" "))
+    }
+
+    pub fn page(&self) -> u32 {
+        self.page.max(1)
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.per_page.clamp(1, MAX_PER_PAGE)
+    }
+
+    pub fn offset(&self) -> u32 {
+        (self.page() - 1).saturating_mul(self.limit())
+    }
+}
+
+/// A matching product, best matches come first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub id: ProductId,
+    /// The product's title with matched terms wrapped in `HIGHLIGHT_OPEN`/`HIGHLIGHT_CLOSE`
+    pub title: String,
+    /// Excerpt of the description around the matched terms, highlighted the same way
+    pub snippet: String,
+    pub product: Product,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    /// Number of matching products across every page
+    pub total: u32,
+    pub page: u32,
+    pub per_page: u32,
+}
+
+/// Row of the `product_search` FTS5 table, which diesel's schema doesn't cover
+#[derive(Debug, Clone, QueryableByName)]
+pub struct TableSearchHit {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
+    pub title: String,
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+    #[diesel(sql_type = Double)]
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, Copy, QueryableByName)]
+pub struct SearchCount {
+    #[diesel(sql_type = Integer)]
+    pub total: i32,
+}
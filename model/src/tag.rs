@@ -0,0 +1,41 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Represents ID of a Tag -> convert to i32 before entry into DB
+pub type TagId = u32;
+
+/// Free-form label, products can have any number of these, IE "cats" or "new"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: TagId,
+    pub name: String,
+}
+
+impl From<TableTag> for Tag {
+    fn from(TableTag { id, name }: TableTag) -> Self {
+        Self {
+            id: id as TagId,
+            name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::tags)]
+pub struct TableTag {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable, AsChangeset, Deserialize)]
+#[diesel(table_name = crate::schema::tags)]
+pub struct NewTag {
+    pub name: String,
+}
+
+#[derive(Insertable, Clone, Copy, Debug)]
+#[diesel(table_name = crate::schema::product_tags)]
+pub struct NewProductTag {
+    pub product_id: i32,
+    pub tag_id: i32,
+}
@@ -1,9 +1,9 @@
 use chrono::NaiveDateTime;
-use diesel::{Insertable, Selectable};
+use diesel::{Insertable, Queryable, QueryableByName, Selectable};
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use std::{borrow::Cow, str::FromStr};
 
-#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Device {
     Linux,
@@ -11,31 +11,25 @@ pub enum Device {
     Windows,
     Iphone,
     Android,
+    Ipad,
+    ChromeOs,
     #[default]
     Unknown,
 }
 
-impl<T> From<T> for Device
-where
-    T: AsRef<str>,
-{
-    fn from(value: T) -> Self {
+/// The platform a user agent runs on, by its OS
+impl From<&UserAgent> for Device {
+    fn from(agent: &UserAgent) -> Self {
         use Device::*;
-        let value = value.as_ref().to_lowercase();
-        if value.contains("linux") {
-            if value.contains("android") {
-                Android
-            } else {
-                Linux
-            }
-        } else if value.contains("macintosh") || value.contains("mac os x") {
-            Mac
-        } else if value.contains("windows") {
-            Windows
-        } else if value.contains("iphone") {
-            Iphone
-        } else {
-            Unknown
+        match agent.os.as_deref() {
+            Some(os::LINUX) => Linux,
+            Some(os::MACOS) => Mac,
+            Some(os::WINDOWS) => Windows,
+            Some(os::IOS) => Iphone,
+            Some(os::ANDROID) => Android,
+            Some(os::IPADOS) => Ipad,
+            Some(os::CHROMEOS) => ChromeOs,
+            _ => Unknown,
         }
     }
 }
@@ -52,12 +46,91 @@ impl std::fmt::Display for Device {
                 Windows => "Windows",
                 Iphone => "Iphone",
                 Android => "Android",
+                Ipad => "Ipad",
+                ChromeOs => "ChromeOS",
                 Unknown => "Unknown Device",
             }
         )
     }
 }
 
+/// Parses a device as stored, IE from its `Display` name rather than a user agent
+impl FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Device::*;
+        [
+            Linux, Mac, Windows, Iphone, Android, Ipad, ChromeOs, Unknown,
+        ]
+        .into_iter()
+        .find(|device| device.to_string() == s)
+        .ok_or_else(|| format!("Unknown device: {s}"))
+    }
+}
+
+/// Names of the OS families user agents are parsed into
+pub mod os {
+    pub const ANDROID: &str = "Android";
+    pub const CHROMEOS: &str = "ChromeOS";
+    pub const IOS: &str = "iOS";
+    pub const IPADOS: &str = "iPadOS";
+    pub const LINUX: &str = "Linux";
+    pub const MACOS: &str = "macOS";
+    pub const WINDOWS: &str = "Windows";
+    pub const WINDOWS_PHONE: &str = "Windows Phone";
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceClass {
+    Desktop,
+    Phone,
+    Tablet,
+    Bot,
+    #[default]
+    Unknown,
+}
+
+impl std::fmt::Display for DeviceClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        f.write_str(match self {
+            DeviceClass::Desktop => "desktop",
+            DeviceClass::Phone => "phone",
+            DeviceClass::Tablet => "tablet",
+            DeviceClass::Bot => "bot",
+            DeviceClass::Unknown => "unknown",
+        })
+    }
+}
+
+impl FromStr for DeviceClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use DeviceClass::*;
+        [Desktop, Phone, Tablet, Bot, Unknown]
+            .into_iter()
+            .find(|class| class.to_string() == s)
+            .ok_or_else(|| format!("Unknown device class: {s}"))
+    }
+}
+
+/// What a user agent says about the visitor's browser and device, families
+/// are `None` when they aren't recognised
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+pub struct UserAgent {
+    /// IE "Firefox"
+    pub browser: Option<String>,
+    /// IE "131.0"
+    pub browser_version: Option<String>,
+    /// One of `os`'s names
+    pub os: Option<String>,
+    /// IE "14" for Android 14, "10.15.7" for macOS
+    pub os_version: Option<String>,
+    pub device_class: DeviceClass,
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct Location {
     pub country: String,
@@ -68,35 +141,58 @@ pub struct Location {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct User {
     pub device: Device,
-    pub ip: String,
+    /// Salted hash of the visitor's IP, the salt changes daily
+    pub visitor: String,
     pub user_agent: Option<String>,
+    /// Parsed from `user_agent`
+    pub agent: UserAgent,
     pub time: NaiveDateTime,
     pub country: String,
     pub state: String,
     pub city: Option<String>,
+    /// Host of the page that linked to the shop, `None` for direct visits
+    pub referrer: Option<String>,
+    /// Crawlers, uptime checkers and link previewers are logged but not counted
+    pub is_bot: bool,
 }
 
 impl From<TableUser> for User {
     fn from(
         TableUser {
-            ip,
+            visitor,
             user_agent,
             device,
             time,
             country,
             state,
             city,
+            referrer,
+            is_bot,
+            browser,
+            browser_version,
+            os,
+            os_version,
+            device_class,
             ..
         }: TableUser,
     ) -> Self {
         Self {
-            device: Device::from(device),
-            ip,
+            device: device.parse().unwrap_or_default(),
+            visitor,
             user_agent,
+            agent: UserAgent {
+                browser,
+                browser_version,
+                os,
+                os_version,
+                device_class: device_class.parse().unwrap_or_default(),
+            },
             time,
             country,
             state,
             city,
+            referrer,
+            is_bot,
         }
     }
 }
@@ -104,48 +200,124 @@ impl From<TableUser> for User {
 #[derive(Selectable, Clone, Debug)]
 #[diesel(table_name = crate::schema::users)]
 pub struct TableUser {
-    ip: String,
+    visitor: String,
     user_agent: Option<String>,
     device: String,
     time: NaiveDateTime,
     country: String,
     state: String,
     city: Option<String>,
+    referrer: Option<String>,
+    is_bot: bool,
+    browser: Option<String>,
+    browser_version: Option<String>,
+    os: Option<String>,
+    os_version: Option<String>,
+    device_class: String,
 }
 
 #[derive(Insertable, Clone)]
 #[diesel(table_name = crate::schema::users)]
 pub struct NewUser<'a> {
-    ip: &'a str,
+    visitor: &'a str,
     user_agent: Option<Cow<'a, str>>,
     device: Cow<'a, str>,
     time: &'a NaiveDateTime,
     country: &'a str,
     state: &'a str,
     city: Option<Cow<'a, str>>,
+    referrer: Option<&'a str>,
+    is_bot: bool,
+    browser: Option<&'a str>,
+    browser_version: Option<&'a str>,
+    os: Option<&'a str>,
+    os_version: Option<&'a str>,
+    device_class: Cow<'a, str>,
 }
 
 impl<'a, 'b: 'a> From<&'b User> for NewUser<'a> {
     fn from(
         User {
             device,
-            ip,
+            visitor,
             user_agent,
+            agent,
             time,
             country,
             state,
             city,
-            ..
+            referrer,
+            is_bot,
         }: &'b User,
     ) -> Self {
         Self {
             device: Cow::Owned(device.to_string()),
-            ip,
+            visitor,
             user_agent: user_agent.as_deref().map(Cow::Borrowed),
             time,
             country,
             state,
             city: city.as_deref().map(Cow::Borrowed),
+            referrer: referrer.as_deref(),
+            is_bot: *is_bot,
+            browser: agent.browser.as_deref(),
+            browser_version: agent.browser_version.as_deref(),
+            os: agent.os.as_deref(),
+            os_version: agent.os_version.as_deref(),
+            device_class: Cow::Owned(agent.device_class.to_string()),
         }
     }
 }
+
+/// Visits from one place and device, through one referrer, on one day. What's
+/// kept of visits once they're past the retention period, along with
+/// `DailyUniqueVisitors`
+#[derive(
+    Queryable,
+    QueryableByName,
+    Selectable,
+    Insertable,
+    Serialize,
+    Deserialize,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+#[diesel(table_name = crate::schema::daily_visits)]
+pub struct DailyVisits {
+    pub day: chrono::NaiveDate,
+    pub country: String,
+    pub state: String,
+    pub device: String,
+    /// '' for direct visits
+    pub referrer: String,
+    pub visits: i32,
+}
+
+/// Distinct visitors on one day, overall or in one breakdown of visit reports.
+/// Counted apart from `DailyVisits` as a visitor can be in several of its
+/// groups, IE by coming through a referrer and then directly
+#[derive(
+    Queryable,
+    QueryableByName,
+    Selectable,
+    Insertable,
+    Serialize,
+    Deserialize,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+#[diesel(table_name = crate::schema::daily_unique_visitors)]
+pub struct DailyUniqueVisitors {
+    pub day: chrono::NaiveDate,
+    /// "total", "device", "country", "state" or "referrer"
+    pub breakdown: String,
+    /// Only set for states
+    pub country: String,
+    /// '' for totals
+    pub name: String,
+    pub unique_visitors: i32,
+}